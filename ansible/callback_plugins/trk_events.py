@@ -0,0 +1,73 @@
+# Writes one JSON line per finished task to $TRK_ANSIBLE_EVENTS, which trk
+# reads back into its own report once ansible-playbook exits. The default
+# stdout callback keeps printing to the terminal as before.
+
+from __future__ import annotations
+
+import json
+import os
+import time
+
+from ansible.plugins.callback import CallbackBase
+
+DOCUMENTATION = """
+name: trk_events
+type: aggregate
+short_description: task results as JSON lines for trk
+description:
+  - Appends a JSON object per task and host to the file named by
+    TRK_ANSIBLE_EVENTS.
+requirements:
+  - enabled through ANSIBLE_CALLBACKS_ENABLED
+"""
+
+
+class CallbackModule(CallbackBase):
+    CALLBACK_VERSION = 2.0
+    CALLBACK_TYPE = "aggregate"
+    CALLBACK_NAME = "trk_events"
+    CALLBACK_NEEDS_ENABLED = True
+
+    def __init__(self):
+        super().__init__()
+        self._path = os.environ.get("TRK_ANSIBLE_EVENTS")
+        self._started = {}
+
+    def v2_playbook_on_task_start(self, task, is_conditional):
+        self._started[task._uuid] = time.monotonic()
+
+    def v2_playbook_on_handler_task_start(self, task):
+        self._started[task._uuid] = time.monotonic()
+
+    def v2_runner_on_ok(self, result):
+        status = "changed" if result._result.get("changed") else "ok"
+        self._write(result, status)
+
+    def v2_runner_on_failed(self, result, ignore_errors=False):
+        self._write(result, "ok" if ignore_errors else "failed")
+
+    def v2_runner_on_skipped(self, result):
+        self._write(result, "skipped")
+
+    def v2_runner_on_unreachable(self, result):
+        self._write(result, "unreachable")
+
+    def _write(self, result, status):
+        if not self._path:
+            return
+        task = result._task
+        started = self._started.get(task._uuid)
+        outcome = result._result
+        message = outcome.get("msg") or ""
+        stderr = outcome.get("stderr") or ""
+        if status in ("failed", "unreachable") and stderr and stderr not in message:
+            message = f"{message}: {stderr}" if message else stderr
+        event = {
+            "task": task.get_name(),
+            "path": task.get_path(),
+            "status": status,
+            "duration": time.monotonic() - started if started else 0.0,
+            "message": str(message),
+        }
+        with open(self._path, "a", encoding="utf-8") as events:
+            events.write(json.dumps(event) + "\n")
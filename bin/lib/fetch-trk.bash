@@ -0,0 +1,35 @@
+# Sourced by bin/bootstrap and bin/update: `fetch_trk` points TRK_BIN at the
+# trk binary for this Mac, downloading it from the latest release and
+# checking it against the release's SHA-256 first.
+
+TRK_RELEASE_URL="${TRK_RELEASE_URL:-https://github.com/trkw/trk/releases/latest/download}"
+
+fetch_trk() {
+    # Set TRK_BIN to run a local build instead of the released binary.
+    if [[ -n "${TRK_BIN:-}" ]]; then
+        return
+    fi
+    TRK_BIN="${HOME}/.local/bin/trk"
+
+    # `uname -m` says x86_64 in a shell running under Rosetta, so ask the
+    # hardware instead.
+    local target="x86_64-apple-darwin"
+    if [[ "$(/usr/sbin/sysctl -n hw.optional.arm64 2>/dev/null)" == "1" ]]; then
+        target="aarch64-apple-darwin"
+    fi
+    local url="${TRK_RELEASE_URL}/trk-${target}"
+
+    echo "Downloading trk (${target}) to ${TRK_BIN}..."
+    mkdir -p "$(dirname "${TRK_BIN}")"
+    local expected
+    expected="$(curl -fsSL "${url}.sha256" | awk '{ print $1 }')"
+    curl -fsSL "${url}" -o "${TRK_BIN}.download"
+    if ! echo "${expected}  ${TRK_BIN}.download" | shasum -a 256 -c - >/dev/null; then
+        rm -f "${TRK_BIN}.download"
+        echo "trk-${target} does not match its published SHA-256; not running it." >&2
+        exit 1
+    fi
+    chmod +x "${TRK_BIN}.download"
+    mv "${TRK_BIN}.download" "${TRK_BIN}"
+    echo -e "\n"
+}
@@ -0,0 +1,112 @@
+//! `trk capture`: adds what is installed but not listed to a Brewfile,
+//! without rewriting the lines that are already there.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::steps::deprecated_tap;
+
+use super::{Brewfile, Check, Entry, Installed, Kind, Value};
+
+/// Heads the section new entries are added to. A later capture adds to
+/// the same section.
+pub const SECTION: &str = "# Found by `trk capture`; move these into the groups above.";
+
+/// Adds the installed but unlisted entries to the Brewfile at `path`,
+/// creating it if there is none, and returns them. With `dry_run` the file
+/// is left alone.
+pub fn run(cx: &Context, path: &Path, dry_run: bool) -> Result<Vec<Entry>> {
+    let path_error = |err: io::Error| Error::Path {
+        path: path.to_owned(),
+        message: err.to_string(),
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(path_error(err)),
+    };
+    let brewfile = Brewfile::parse(&source, path)?;
+    let installed = Installed::read(cx)?;
+    let leaves = if cx.which("brew").is_some() {
+        let cmd = cx.cmd("brew").arg("leaves");
+        let out = cmd.output()?.check(&cmd)?;
+        out.stdout.lines().map(|l| l.trim().to_owned()).collect()
+    } else {
+        Vec::new()
+    };
+
+    let entries = unlisted(&brewfile, &installed, &leaves);
+    if !dry_run && !entries.is_empty() {
+        let tmp = path.with_extension("trk-capture.tmp");
+        fs::write(&tmp, merge(&source, &entries)).map_err(path_error)?;
+        fs::rename(&tmp, path).map_err(path_error)?;
+    }
+    Ok(entries)
+}
+
+/// Installed taps, leaf formulae, casks and App Store apps that `brewfile`
+/// does not list, in Brewfile order (taps first). `leaves` is `brew
+/// leaves`: formulae that nothing else installed depends on. Retired
+/// official taps are left out, so the tap audit keeps reporting them.
+pub fn unlisted(brewfile: &Brewfile, installed: &Installed, leaves: &[String]) -> Vec<Entry> {
+    let check = Check::new(brewfile, installed);
+    let mut entries: Vec<Entry> = check
+        .extra
+        .into_iter()
+        .filter(|item| match item.kind {
+            Kind::Tap => deprecated_tap(&item.name).is_none(),
+            Kind::Cask | Kind::Mas => true,
+            Kind::Brew => installed
+                .formulae
+                .iter()
+                .any(|f| f.full_name == item.name && leaves.iter().any(|leaf| f.is(leaf))),
+            Kind::Whalebrew | Kind::Vscode => false,
+        })
+        .map(|item| Entry {
+            kind: item.kind,
+            options: item
+                .id
+                .and_then(|id| i64::try_from(id).ok())
+                .map(|id| ("id".to_owned(), Value::Int(id)))
+                .into_iter()
+                .collect(),
+            name: item.name,
+            line: 0,
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.kind);
+    entries
+}
+
+/// `source` with `entries` added at the end of the [`SECTION`], which is
+/// started at the end of the file if there is none yet. Every existing
+/// line, comment and blank line is kept as it is.
+pub fn merge(source: &str, entries: &[Entry]) -> String {
+    if entries.is_empty() {
+        return source.to_owned();
+    }
+    let mut lines: Vec<String> = source.lines().map(str::to_owned).collect();
+    let new = entries.iter().map(Entry::to_string);
+    match lines.iter().position(|line| line.trim() == SECTION) {
+        Some(start) => {
+            let end = lines[start..]
+                .iter()
+                .position(|line| line.trim().is_empty())
+                .map_or(lines.len(), |len| start + len);
+            lines.splice(end..end, new);
+        }
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(SECTION.to_owned());
+            lines.extend(new);
+        }
+    }
+    let mut merged = lines.join("\n");
+    merged.push('\n');
+    merged
+}
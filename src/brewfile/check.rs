@@ -0,0 +1,205 @@
+//! `brew bundle check`, answered from a parsed Brewfile and [`Installed`].
+
+use std::fmt;
+
+use serde::Serialize;
+
+use super::{Brewfile, Entry, Installed, Kind};
+
+/// How the machine differs from a Brewfile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Check {
+    /// Entries that are not installed.
+    pub missing: Vec<Item>,
+    /// Entries installed in an older version than Homebrew has.
+    pub outdated: Vec<Item>,
+    /// Installed taps, requested formulae, casks, apps and extensions the
+    /// Brewfile does not list.
+    pub extra: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Item {
+    pub kind: Kind,
+    pub name: String,
+    /// App Store id of a `mas` item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// The Brewfile line, for missing and outdated items.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest: Option<String>,
+}
+
+impl Check {
+    pub fn new(brewfile: &Brewfile, installed: &Installed) -> Self {
+        let mut check = Check::default();
+        for entry in &brewfile.entries {
+            let item = Item::listed(entry);
+            match entry.kind {
+                Kind::Tap => {
+                    if !installed
+                        .taps
+                        .iter()
+                        .any(|t| t.eq_ignore_ascii_case(&entry.name))
+                    {
+                        check.missing.push(item);
+                    }
+                }
+                Kind::Brew => match installed.formulae.iter().find(|f| f.is(&entry.name)) {
+                    None => check.missing.push(item),
+                    Some(formula) if formula.outdated => check.outdated.push(Item {
+                        installed: Some(formula.version.clone()),
+                        latest: formula.latest.clone(),
+                        ..item
+                    }),
+                    Some(_) => {}
+                },
+                Kind::Cask => match installed.casks.iter().find(|c| c.is(&entry.name)) {
+                    None => check.missing.push(item),
+                    Some(cask) if cask.outdated => check.outdated.push(Item {
+                        installed: Some(cask.version.clone()),
+                        latest: Some(cask.latest.clone()),
+                        ..item
+                    }),
+                    Some(_) => {}
+                },
+                Kind::Mas => {
+                    if !installed
+                        .mas
+                        .iter()
+                        .any(|app| Some(app.id) == entry.mas_id())
+                    {
+                        check.missing.push(item);
+                    }
+                }
+                Kind::Whalebrew => {
+                    if !installed.whalebrew.contains(&entry.name) {
+                        check.missing.push(item);
+                    }
+                }
+                Kind::Vscode => {
+                    if !installed
+                        .vscode
+                        .iter()
+                        .any(|e| e.eq_ignore_ascii_case(&entry.name))
+                    {
+                        check.missing.push(item);
+                    }
+                }
+            }
+        }
+
+        let listed =
+            |kind: Kind, matches: &dyn Fn(&Entry) -> bool| brewfile.entries(kind).any(matches);
+        for tap in &installed.taps {
+            if !listed(Kind::Tap, &|e| e.name.eq_ignore_ascii_case(tap)) {
+                check.extra.push(Item::extra(Kind::Tap, tap, None));
+            }
+        }
+        for formula in installed.formulae.iter().filter(|f| f.on_request) {
+            if !listed(Kind::Brew, &|e| formula.is(&e.name)) {
+                check
+                    .extra
+                    .push(Item::extra(Kind::Brew, &formula.full_name, None));
+            }
+        }
+        for cask in &installed.casks {
+            if !listed(Kind::Cask, &|e| cask.is(&e.name)) {
+                check
+                    .extra
+                    .push(Item::extra(Kind::Cask, &cask.full_token, None));
+            }
+        }
+        for app in &installed.mas {
+            if !listed(Kind::Mas, &|e| e.mas_id() == Some(app.id)) {
+                check
+                    .extra
+                    .push(Item::extra(Kind::Mas, &app.name, Some(app.id)));
+            }
+        }
+        for image in &installed.whalebrew {
+            if !listed(Kind::Whalebrew, &|e| e.name == *image) {
+                check.extra.push(Item::extra(Kind::Whalebrew, image, None));
+            }
+        }
+        for extension in &installed.vscode {
+            if !listed(Kind::Vscode, &|e| e.name.eq_ignore_ascii_case(extension)) {
+                check.extra.push(Item::extra(Kind::Vscode, extension, None));
+            }
+        }
+        check
+    }
+
+    /// Nothing is missing or outdated; extra entries are allowed, as with
+    /// `brew bundle check`.
+    pub fn is_satisfied(&self) -> bool {
+        self.missing.is_empty() && self.outdated.is_empty()
+    }
+}
+
+impl Item {
+    fn listed(entry: &Entry) -> Self {
+        Self {
+            kind: entry.kind,
+            name: entry.name.clone(),
+            id: entry.mas_id(),
+            line: Some(entry.line),
+            installed: None,
+            latest: None,
+        }
+    }
+
+    fn extra(kind: Kind, name: &str, id: Option<u64>) -> Self {
+        Self {
+            kind,
+            name: name.to_owned(),
+            id,
+            line: None,
+            installed: None,
+            latest: None,
+        }
+    }
+}
+
+/// `brew wget`, `mas Xcode (497799835)`, `brew node 20.1.0 -> 21.0.0`.
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.name)?;
+        if let Some(id) = self.id {
+            write!(f, " ({id})")?;
+        }
+        if let Some(installed) = &self.installed {
+            write!(f, " {installed}")?;
+            if let Some(latest) = &self.latest {
+                write!(f, " -> {latest}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_satisfied() && self.extra.is_empty() {
+            return writeln!(f, "The Brewfile's dependencies are satisfied.");
+        }
+        for (heading, items) in [
+            ("missing", &self.missing),
+            ("outdated", &self.outdated),
+            ("extra", &self.extra),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            writeln!(f, "{heading}")?;
+            for item in items {
+                writeln!(f, "  {item}")?;
+            }
+        }
+        Ok(())
+    }
+}
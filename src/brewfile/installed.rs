@@ -0,0 +1,214 @@
+//! What is installed, as `brew bundle` would see it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+
+/// Installed formulae, casks, taps, App Store apps, whalebrew images and VS
+/// Code extensions. Tools that are not on `$PATH` contribute nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Installed {
+    pub taps: Vec<String>,
+    pub formulae: Vec<Formula>,
+    pub casks: Vec<Cask>,
+    pub mas: Vec<MasApp>,
+    pub whalebrew: Vec<String>,
+    pub vscode: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Formula {
+    pub name: String,
+    /// `user/repo/name` for formulae from third-party taps.
+    pub full_name: String,
+    /// Other names the formula answers to, including old names.
+    pub aliases: Vec<String>,
+    pub version: String,
+    pub latest: Option<String>,
+    pub outdated: bool,
+    /// Installed by the user rather than pulled in as a dependency.
+    pub on_request: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Cask {
+    pub token: String,
+    pub full_token: String,
+    pub old_tokens: Vec<String>,
+    pub version: String,
+    pub latest: String,
+    pub outdated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MasApp {
+    pub id: u64,
+    pub name: String,
+    pub version: String,
+}
+
+/// The parts of `brew info --json=v2 --installed` trk uses.
+#[derive(Deserialize)]
+struct Info {
+    formulae: Vec<FormulaInfo>,
+    casks: Vec<CaskInfo>,
+}
+
+#[derive(Deserialize)]
+struct FormulaInfo {
+    name: String,
+    full_name: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    oldnames: Vec<String>,
+    #[serde(default)]
+    versions: Versions,
+    installed: Vec<InstalledVersion>,
+    #[serde(default)]
+    outdated: bool,
+}
+
+#[derive(Default, Deserialize)]
+struct Versions {
+    stable: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InstalledVersion {
+    version: String,
+    #[serde(default)]
+    installed_on_request: bool,
+}
+
+#[derive(Deserialize)]
+struct CaskInfo {
+    token: String,
+    full_token: String,
+    #[serde(default)]
+    old_tokens: Vec<String>,
+    installed: Option<String>,
+    version: String,
+    #[serde(default)]
+    outdated: bool,
+}
+
+impl Installed {
+    /// Asks brew, mas, whalebrew and code what they have installed.
+    pub fn read(cx: &Context) -> Result<Self> {
+        let mut installed = Self::default();
+        if cx.which("brew").is_some() {
+            let info = output(cx, "brew", &["info", "--json=v2", "--installed"])?;
+            installed = Self::from_brew_info(&info)?;
+            installed.taps = lines(&output(cx, "brew", &["tap"])?);
+        }
+        if cx.which("mas").is_some() {
+            installed.mas = parse_mas_list(&output(cx, "mas", &["list"])?);
+        }
+        if cx.which("whalebrew").is_some() {
+            let list = output(cx, "whalebrew", &["list"])?;
+            installed.whalebrew = list
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_whitespace().nth(1))
+                .map(str::to_owned)
+                .collect();
+        }
+        if cx.which("code").is_some() {
+            installed.vscode = lines(&output(cx, "code", &["--list-extensions"])?);
+        }
+        Ok(installed)
+    }
+
+    /// Formulae and casks from `brew info --json=v2 --installed`.
+    pub fn from_brew_info(json: &str) -> Result<Self> {
+        let info: Info = serde_json::from_str(json).map_err(|err| Error::Output {
+            command: "brew info --json=v2 --installed".into(),
+            message: err.to_string(),
+        })?;
+        let formulae = info
+            .formulae
+            .into_iter()
+            .filter_map(|f| {
+                let last = f.installed.last()?;
+                let mut aliases = f.aliases;
+                aliases.extend(f.oldnames);
+                Some(Formula {
+                    version: last.version.clone(),
+                    on_request: f.installed.iter().any(|i| i.installed_on_request),
+                    name: f.name,
+                    full_name: f.full_name,
+                    aliases,
+                    latest: f.versions.stable,
+                    outdated: f.outdated,
+                })
+            })
+            .collect();
+        let casks = info
+            .casks
+            .into_iter()
+            .filter_map(|c| {
+                Some(Cask {
+                    version: c.installed?,
+                    token: c.token,
+                    full_token: c.full_token,
+                    old_tokens: c.old_tokens,
+                    latest: c.version,
+                    outdated: c.outdated,
+                })
+            })
+            .collect();
+        Ok(Self {
+            formulae,
+            casks,
+            ..Self::default()
+        })
+    }
+}
+
+impl Formula {
+    /// Whether a Brewfile `brew "name"` refers to this formula.
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name || self.full_name == name || self.aliases.iter().any(|a| a == name)
+    }
+}
+
+impl Cask {
+    pub fn is(&self, name: &str) -> bool {
+        self.token == name || self.full_token == name || self.old_tokens.iter().any(|t| t == name)
+    }
+}
+
+/// `mas list` lines look like `497799835  Xcode  (15.0)`.
+pub fn parse_mas_list(list: &str) -> Vec<MasApp> {
+    list.lines()
+        .filter_map(|line| {
+            let (id, rest) = line.trim().split_once(char::is_whitespace)?;
+            let rest = rest.trim();
+            let (name, version) = match rest.rsplit_once('(') {
+                Some((name, version)) => (name.trim(), version.trim_end_matches(')').trim()),
+                None => (rest, ""),
+            };
+            Some(MasApp {
+                id: id.parse().ok()?,
+                name: name.to_owned(),
+                version: version.to_owned(),
+            })
+        })
+        .collect()
+}
+
+fn lines(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn output(cx: &Context, program: &str, args: &[&str]) -> Result<String> {
+    let cmd = cx.cmd(program).args(args);
+    Ok(cmd.output()?.check(&cmd)?.stdout)
+}
@@ -0,0 +1,222 @@
+//! Brewfiles, read without Ruby.
+//!
+//! trk understands the part of the `brew bundle` DSL that dotfiles
+//! Brewfiles use: `tap`, `brew`, `cask`, `mas`, `whalebrew`, `vscode` and
+//! `cask_args`, each with literal arguments. Anything else (conditionals,
+//! method calls, interpolation) is reported with its line rather than
+//! guessed at.
+
+pub mod capture;
+mod check;
+mod installed;
+mod parse;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+pub use check::{Check, Item};
+pub use installed::{parse_mas_list, Cask, Formula, Installed, MasApp};
+
+/// A parsed Brewfile.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Brewfile {
+    pub entries: Vec<Entry>,
+    /// Options from `cask_args`, applied to every cask.
+    pub cask_args: Vec<(String, Value)>,
+}
+
+/// One `tap`, `brew`, `cask`, ... line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub name: String,
+    /// Keyword options such as `args:`, `restart_service:` or `id:`; a
+    /// tap's clone URL is kept as `url`.
+    pub options: Vec<(String, Value)>,
+    /// 1-based line the entry starts on.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Tap,
+    Brew,
+    Cask,
+    Mas,
+    Whalebrew,
+    Vscode,
+}
+
+/// A literal option value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Symbol(String),
+    Bool(bool),
+    Int(i64),
+    Nil,
+    Array(Vec<Value>),
+    Hash(Vec<(String, Value)>),
+}
+
+/// When `brew bundle` restarts a formula's service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartService {
+    /// `restart_service: true`: on every run.
+    Always,
+    /// `restart_service: :changed`: only after an install or upgrade.
+    Changed,
+}
+
+impl Brewfile {
+    /// Parses Brewfile source; `path` is only used in error messages.
+    pub fn parse(source: &str, path: &Path) -> Result<Self> {
+        parse::parse(source).map_err(|(line, message)| Error::Brewfile {
+            path: path.to_owned(),
+            line,
+            message,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path).map_err(|err| Error::Path {
+            path: path.to_owned(),
+            message: err.to_string(),
+        })?;
+        Self::parse(&source, path)
+    }
+
+    pub fn entries(&self, kind: Kind) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(move |e| e.kind == kind)
+    }
+}
+
+impl Entry {
+    pub fn option(&self, key: &str) -> Option<&Value> {
+        self.options.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// `args: ["with-foo"]` as command-line flags, `--with-foo`.
+    pub fn args(&self) -> Vec<String> {
+        match self.option("args") {
+            Some(Value::Array(args)) => args
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|arg| format!("--{}", arg.trim_start_matches('-')))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn restart_service(&self) -> Option<RestartService> {
+        match self.option("restart_service") {
+            Some(Value::Bool(true)) => Some(RestartService::Always),
+            Some(value) if value.as_str() == Some("changed") => Some(RestartService::Changed),
+            _ => None,
+        }
+    }
+
+    /// `link: true` or `link: false`; `None` leaves linking to Homebrew.
+    pub fn link(&self) -> Option<bool> {
+        match self.option("link") {
+            Some(Value::Bool(link)) => Some(*link),
+            _ => None,
+        }
+    }
+
+    /// The App Store id of a `mas` entry.
+    pub fn mas_id(&self) -> Option<u64> {
+        match self.option("id") {
+            Some(Value::Int(id)) => u64::try_from(*id).ok(),
+            _ => None,
+        }
+    }
+
+    /// The clone URL of a `tap` entry with a custom remote.
+    pub fn tap_url(&self) -> Option<&str> {
+        self.option("url").and_then(Value::as_str)
+    }
+}
+
+impl Value {
+    /// The text of a string or symbol.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) | Value::Symbol(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Tap => "tap",
+            Kind::Brew => "brew",
+            Kind::Cask => "cask",
+            Kind::Mas => "mas",
+            Kind::Whalebrew => "whalebrew",
+            Kind::Vscode => "vscode",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Ruby literal syntax, so entries can be written back into a Brewfile.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+            Value::Symbol(s) => write!(f, ":{s}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Nil => f.write_str("nil"),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            Value::Hash(pairs) => {
+                f.write_str("{ ")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{key}: {value}")?;
+                }
+                f.write_str(" }")
+            }
+        }
+    }
+}
+
+/// The entry as a Brewfile line, e.g. `brew "git", args: ["HEAD"]`.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, Value::Str(self.name.clone()))?;
+        for (key, value) in &self.options {
+            if self.kind == Kind::Tap && key == "url" {
+                write!(f, ", {value}")?;
+            } else {
+                write!(f, ", {key}: {value}")?;
+            }
+        }
+        Ok(())
+    }
+}
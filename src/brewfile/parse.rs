@@ -0,0 +1,377 @@
+//! A tokenizer and parser for the literal subset of Ruby that Brewfiles
+//! are written in. Errors carry the 1-based line they were found on.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::{Brewfile, Entry, Kind, Value};
+
+type Error = (usize, String);
+
+/// Positional arguments, then keyword arguments.
+type Arguments = (Vec<Value>, Vec<(String, Value)>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    /// `name:` in keyword-argument position.
+    Label(String),
+    Str(String),
+    Symbol(String),
+    Int(i64),
+    Arrow,
+    Punct(char),
+    Newline,
+}
+
+pub(super) fn parse(source: &str) -> Result<Brewfile, Error> {
+    let tokens = tokenize(source)?;
+    let mut brewfile = Brewfile::default();
+    for statement in statements(&tokens) {
+        parse_statement(statement, &mut brewfile)?;
+    }
+    Ok(brewfile)
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Tok)>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        let tok = match c {
+            '\n' => {
+                tokens.push((line, Tok::Newline));
+                line += 1;
+                continue;
+            }
+            '#' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            '\\' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '"' | '\'' => Tok::Str(string(&mut chars, c, line)?),
+            ':' => match chars.peek() {
+                Some('"' | '\'') => {
+                    let quote = chars.next().expect("peeked");
+                    Tok::Symbol(string(&mut chars, quote, line)?)
+                }
+                Some(&c) if is_ident_start(c) => Tok::Symbol(ident(&mut chars, None)),
+                _ => return Err((line, "expected a symbol after `:`".into())),
+            },
+            '=' if chars.peek() == Some(&'>') => {
+                chars.next();
+                Tok::Arrow
+            }
+            '[' | ']' | '(' | ')' | '{' | '}' | ',' | '.' => Tok::Punct(c),
+            c if c.is_ascii_digit()
+                || (c == '-' && chars.peek().is_some_and(char::is_ascii_digit)) =>
+            {
+                let mut digits = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit() || *d == '_') {
+                    if d != '_' {
+                        digits.push(d);
+                    }
+                }
+                Tok::Int(
+                    digits
+                        .parse()
+                        .map_err(|_| (line, format!("number `{digits}` is too large")))?,
+                )
+            }
+            c if is_ident_start(c) => {
+                let name = ident(&mut chars, Some(c));
+                let mut ahead = chars.clone();
+                if ahead.next() == Some(':') && ahead.next() != Some(':') {
+                    chars.next();
+                    Tok::Label(name)
+                } else {
+                    Tok::Ident(name)
+                }
+            }
+            c => return Err((line, format!("unexpected `{c}`"))),
+        };
+        tokens.push((line, tok));
+    }
+    Ok(tokens)
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn ident(chars: &mut Peekable<Chars<'_>>, first: Option<char>) -> String {
+    let mut name: String = first.into_iter().collect();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '?' | '!'))
+    {
+        name.push(c);
+    }
+    name
+}
+
+fn string(chars: &mut Peekable<Chars<'_>>, quote: char, line: usize) -> Result<String, Error> {
+    let mut out = String::new();
+    loop {
+        match chars.next() {
+            None | Some('\n') => return Err((line, "unterminated string".into())),
+            Some(c) if c == quote => return Ok(out),
+            Some('\\') => match chars.next() {
+                Some('n') if quote == '"' => out.push('\n'),
+                Some('t') if quote == '"' => out.push('\t'),
+                Some(c) if c == quote || c == '\\' => out.push(c),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => return Err((line, "unterminated string".into())),
+            },
+            Some('#') if quote == '"' && chars.peek() == Some(&'{') => {
+                return Err((line, "string interpolation is not supported".into()));
+            }
+            Some(c) => out.push(c),
+        }
+    }
+}
+
+/// Splits the tokens at newlines that end a statement: not inside
+/// brackets and not after a `,` or `=>`. The other newlines are dropped.
+fn statements(tokens: &[(usize, Tok)]) -> Vec<Vec<&(usize, Tok)>> {
+    let mut statements = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, (_, tok)) in tokens.iter().enumerate() {
+        match tok {
+            Tok::Punct('[' | '(' | '{') => depth += 1,
+            Tok::Punct(']' | ')' | '}') => depth -= 1,
+            Tok::Newline => {
+                let continued = i > 0 && matches!(tokens[i - 1].1, Tok::Punct(',') | Tok::Arrow);
+                if depth <= 0 && !continued {
+                    statements.push(&tokens[start..i]);
+                    start = i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    statements.push(&tokens[start..]);
+    statements
+        .into_iter()
+        .map(|s| {
+            s.iter()
+                .filter(|(_, t)| *t != Tok::Newline)
+                .collect::<Vec<_>>()
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+struct Cursor<'a> {
+    tokens: Vec<&'a (usize, Tok)>,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<&'a Tok> {
+        self.tokens.get(self.pos).map(|(_, tok)| tok)
+    }
+
+    fn next(&mut self) -> Option<&'a Tok> {
+        let tok = self.tokens.get(self.pos).map(|(line, tok)| {
+            self.line = *line;
+            tok
+        });
+        self.pos += 1;
+        tok
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        if self.peek() == Some(&Tok::Punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        (self.line, message.into())
+    }
+
+    /// Positional and keyword arguments up to `close` (or the end).
+    fn arguments(&mut self, close: Option<char>) -> Result<Arguments, Error> {
+        let (mut positional, mut keywords) = (Vec::new(), Vec::new());
+        loop {
+            match (self.peek(), close) {
+                (None, None) => break,
+                (Some(Tok::Punct(c)), Some(close)) if *c == close => {
+                    self.pos += 1;
+                    break;
+                }
+                (None, Some(close)) => return Err(self.error(format!("missing `{close}`"))),
+                _ => {}
+            }
+            if let Some(Tok::Label(key)) = self.peek() {
+                self.pos += 1;
+                keywords.push((key.clone(), self.value()?));
+            } else {
+                let value = self.value()?;
+                if self.peek() == Some(&Tok::Arrow) {
+                    self.pos += 1;
+                    let key = value
+                        .as_str()
+                        .ok_or_else(|| self.error("hash keys must be strings or symbols"))?
+                        .to_owned();
+                    keywords.push((key, self.value()?));
+                } else if keywords.is_empty() {
+                    positional.push(value);
+                } else {
+                    return Err(self.error("positional argument after keyword arguments"));
+                }
+            }
+            if !self.eat(',') {
+                match (self.peek(), close) {
+                    (None, None) => {}
+                    (Some(Tok::Punct(c)), Some(close)) if *c == close => {}
+                    _ => return Err(self.error("expected `,` between arguments")),
+                }
+            }
+        }
+        Ok((positional, keywords))
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        Ok(match self.next() {
+            Some(Tok::Str(s)) => Value::Str(s.clone()),
+            Some(Tok::Symbol(s)) => Value::Symbol(s.clone()),
+            Some(Tok::Int(i)) => Value::Int(*i),
+            Some(Tok::Ident(word)) => match word.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "nil" => Value::Nil,
+                other => return Err(self.error(format!("`{other}` is not a literal value"))),
+            },
+            Some(Tok::Punct('[')) => {
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.value()?);
+                    if !self.eat(',') && self.peek() != Some(&Tok::Punct(']')) {
+                        return Err(self.error("expected `,` or `]` in array"));
+                    }
+                }
+                Value::Array(items)
+            }
+            Some(Tok::Punct('{')) => {
+                let (positional, keywords) = self.arguments(Some('}'))?;
+                if !positional.is_empty() {
+                    return Err(self.error("expected `key: value` in hash"));
+                }
+                Value::Hash(keywords)
+            }
+            Some(tok) => return Err(self.error(format!("unexpected {}", describe(tok)))),
+            None => return Err(self.error("expected a value")),
+        })
+    }
+}
+
+fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Ident(name) => format!("`{name}`"),
+        Tok::Label(name) => format!("`{name}:`"),
+        Tok::Str(s) => format!("string \"{s}\""),
+        Tok::Symbol(s) => format!("`:{s}`"),
+        Tok::Int(i) => format!("`{i}`"),
+        Tok::Arrow => "`=>`".into(),
+        Tok::Punct(c) => format!("`{c}`"),
+        Tok::Newline => "end of line".into(),
+    }
+}
+
+fn parse_statement(tokens: Vec<&(usize, Tok)>, brewfile: &mut Brewfile) -> Result<(), Error> {
+    let line = tokens[0].0;
+    let mut cursor = Cursor {
+        tokens,
+        pos: 0,
+        line,
+    };
+    let method = match cursor.next() {
+        Some(Tok::Ident(method)) => method.as_str(),
+        Some(tok) => return Err(cursor.error(format!("unexpected {}", describe(tok)))),
+        None => return Ok(()),
+    };
+    let kind = match method {
+        "tap" => Kind::Tap,
+        "brew" => Kind::Brew,
+        "cask" => Kind::Cask,
+        "mas" => Kind::Mas,
+        "whalebrew" => Kind::Whalebrew,
+        "vscode" => Kind::Vscode,
+        "cask_args" => {
+            let (positional, keywords) = call_arguments(&mut cursor)?;
+            if !positional.is_empty() {
+                return Err((line, "`cask_args` takes only `key: value` options".into()));
+            }
+            brewfile.cask_args.extend(keywords);
+            return Ok(());
+        }
+        "if" | "unless" | "else" | "elsif" | "end" | "case" | "when" => {
+            return Err((
+                line,
+                format!(
+                    "`{method}`: conditionals are not supported; keep them in a separate Brewfile"
+                ),
+            ));
+        }
+        other => {
+            return Err((
+                line,
+                format!(
+                    "unsupported `{other}`; trk understands tap, brew, cask, mas, whalebrew, \
+                     vscode and cask_args"
+                ),
+            ));
+        }
+    };
+
+    let (positional, mut options) = call_arguments(&mut cursor)?;
+    let mut positional = positional.into_iter();
+    let name = match positional.next() {
+        Some(Value::Str(name)) if !name.trim().is_empty() => name,
+        _ => return Err((line, format!("`{method}` needs a name in quotes"))),
+    };
+    match (kind, positional.next()) {
+        (_, None) => {}
+        (Kind::Tap, Some(Value::Str(url))) => options.insert(0, ("url".into(), Value::Str(url))),
+        (_, Some(_)) => return Err((line, format!("`{method}` takes one name"))),
+    }
+    if positional.next().is_some() {
+        return Err((line, format!("`{method}` takes one name")));
+    }
+    let entry = Entry {
+        kind,
+        name,
+        options,
+        line,
+    };
+    if kind == Kind::Mas && entry.mas_id().is_none() {
+        return Err((
+            line,
+            format!("mas `{}` needs `id:` with its App Store id", entry.name),
+        ));
+    }
+    brewfile.entries.push(entry);
+    Ok(())
+}
+
+fn call_arguments(cursor: &mut Cursor<'_>) -> Result<Arguments, Error> {
+    let parenthesized = cursor.eat('(');
+    let arguments = cursor.arguments(parenthesized.then_some(')'))?;
+    if let Some(tok) = cursor.next() {
+        return Err(cursor.error(format!("unexpected {} after the arguments", describe(tok))));
+    }
+    Ok(arguments)
+}
@@ -0,0 +1,603 @@
+//! Command-line interface.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::brewfile::{capture, Brewfile, Check, Installed};
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::journal::Journal;
+use crate::manifest::{self, Pin};
+use crate::plan::Plan;
+use crate::runtime;
+use crate::secret::{Backend, SecretProvider};
+use crate::ssh;
+use crate::steps::{self, MasChoice, Selection, Step, ToolVersions};
+
+#[derive(Debug, Parser)]
+#[command(name = "trk", version, about = "Set up and update this Mac")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Install Homebrew, git and the managed repositories, then
+    /// run the playbook.
+    Bootstrap(RunArgs),
+    /// Pull ~/.trk and run the playbook again.
+    Update(RunArgs),
+    /// Show what a bootstrap or update would change, without changing
+    /// anything (repositories are fetched, not merged).
+    Plan {
+        /// The run to plan.
+        #[arg(value_enum, default_value_t = Run::Update)]
+        run: Run,
+        /// Print the plan as JSON.
+        #[arg(long)]
+        json: bool,
+        /// Plan as if the run were given --stash.
+        #[arg(long)]
+        stash: bool,
+    },
+    /// Add installed taps, formulae, casks and App Store apps that the
+    /// dotfiles Brewfile does not list to a section at its end.
+    Capture {
+        /// The Brewfile to add to instead of the dotfiles one.
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+        /// Only print what would be added.
+        #[arg(long, conflicts_with = "commit")]
+        dry_run: bool,
+        /// Commit the Brewfile in its git repository afterwards.
+        #[arg(long)]
+        commit: bool,
+    },
+    /// Work with the dotfiles Brewfile.
+    #[command(subcommand)]
+    Brewfile(BrewfileCommand),
+    /// Work with the managed git checkouts (~/.trk, ~/dotfiles, ...).
+    #[command(subcommand)]
+    Repos(ReposCommand),
+    /// Work with the language runtimes in trk.toml and .tool-versions.
+    #[command(subcommand)]
+    Runtimes(RuntimesCommand),
+    /// Keep SSH keys in a password manager and install them in ~/.ssh.
+    #[command(subcommand)]
+    Ssh(SshCommand),
+    /// Look at the secrets trk can reach through its providers.
+    #[command(subcommand)]
+    Secrets(SecretsCommand),
+    /// Show what trk detected about this machine.
+    Facts {
+        /// Print the facts as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BrewfileCommand {
+    /// List Brewfile entries that are missing or outdated, and installed
+    /// packages the Brewfile does not list.
+    Check {
+        /// The Brewfile to check instead of the dotfiles one.
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+        /// Print the result as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReposCommand {
+    /// Show whether each checkout is up to date, behind, ahead, diverged,
+    /// dirty or cloned from somewhere else.
+    Status {
+        /// Compare with the remote-tracking branches as they are, without
+        /// fetching first.
+        #[arg(long)]
+        no_fetch: bool,
+        /// Print the statuses as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List newer tags for repos pinned to a tag or a version range.
+    Outdated {
+        /// Print the tags as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Move a repo's pin to a newer tag and check it out. A `tag` pin is
+    /// rewritten in trk.toml; a `version` pin moves within its range.
+    Upgrade {
+        /// The repo's name in trk.toml, e.g. `asdf`.
+        name: String,
+        /// The tag to move to instead of the newest one.
+        #[arg(long, value_name = "TAG")]
+        to: Option<String>,
+        /// Stash uncommitted changes in the checkout for the move.
+        #[arg(long)]
+        stash: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RuntimesCommand {
+    /// Resolve version aliases such as `lts` or `20` again and write the
+    /// releases they now stand for to the lock file. Nothing is installed
+    /// until the next update.
+    Update {
+        /// Only these runtimes, e.g. `nodejs`.
+        plugins: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SshCommand {
+    /// Fetch a key from the secret provider into ~/.ssh/NAME and
+    /// ~/.ssh/NAME.pub and add it to the agent.
+    Get {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// Replace a different key already in ~/.ssh.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Save ~/.ssh/NAME and its public key to the secret provider.
+    Put {
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Create an ed25519 key at ~/.ssh/NAME, save it to the secret
+    /// provider and add it to the agent.
+    Generate {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// The key's comment, e.g. an email address.
+        #[arg(long, short = 'C')]
+        comment: Option<String>,
+        /// Replace a key already at ~/.ssh/NAME.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SecretsCommand {
+    /// List the items in a vault.
+    List {
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Print the secret a reference such as op://Private/github/token
+    /// names.
+    Read { reference: String },
+}
+
+/// Which key, and where it is kept.
+#[derive(Debug, Args)]
+pub struct KeyArgs {
+    /// The key's file name in ~/.ssh and its item name in the provider.
+    #[arg(long)]
+    pub name: String,
+    #[command(flatten)]
+    pub store: StoreArgs,
+}
+
+/// A secret provider and one of its vaults.
+#[derive(Debug, Args)]
+pub struct StoreArgs {
+    /// The secret provider.
+    #[arg(long, value_enum, default_value_t)]
+    pub provider: Backend,
+    /// The provider's vault.
+    #[arg(long, default_value = "Private")]
+    pub vault: String,
+}
+
+impl StoreArgs {
+    fn open(&self, cx: &Context) -> Box<dyn SecretProvider> {
+        self.provider.provider(cx, &self.vault)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Run {
+    Bootstrap,
+    Update,
+}
+
+/// Which steps of a bootstrap or update to run.
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Skip steps that finished in the previous run and whose inputs have
+    /// not changed since.
+    #[arg(long, conflicts_with_all = ["from", "only"])]
+    pub resume: bool,
+
+    /// Start at STEP, skipping the steps before it.
+    #[arg(long, value_name = "STEP", conflicts_with = "only")]
+    pub from: Option<String>,
+
+    /// Run only STEP; may be given more than once.
+    #[arg(long, value_name = "STEP")]
+    pub only: Vec<String>,
+
+    /// Stash uncommitted changes in a checkout that is behind, fast-forward
+    /// it and restore them, instead of stopping.
+    #[arg(long)]
+    pub stash: bool,
+
+    /// Never ask for the sudo password: skip the steps that need root
+    /// (installing Homebrew or Rosetta, a playbook that becomes root) and
+    /// casks, leaving them for a later run.
+    #[arg(long)]
+    pub no_sudo: bool,
+
+    /// What to do when the Brewfile has App Store apps to install and the
+    /// App Store account is not signed in, instead of asking.
+    #[arg(long, value_enum, value_name = "CHOICE")]
+    pub mas: Option<MasChoice>,
+}
+
+impl RunArgs {
+    fn selection(self) -> Selection {
+        if self.resume {
+            Selection::Resume
+        } else if let Some(from) = self.from {
+            Selection::From(from)
+        } else if !self.only.is_empty() {
+            Selection::Only(self.only)
+        } else {
+            Selection::All
+        }
+    }
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    let mut cx = Context::from_env()?;
+    let result = dispatch(&mut cx, cli.command);
+    // Whatever the command failed with is printed, so mask it.
+    result.map_err(|err| cx.redact_error(err))
+}
+
+fn dispatch(cx: &mut Context, command: Command) -> Result<()> {
+    match command {
+        Command::Bootstrap(args) => {
+            cx.stash = args.stash;
+            cx.no_sudo = args.no_sudo;
+            cx.mas = args.mas;
+            execute(cx, "bootstrap", &steps::bootstrap(cx), args)
+        }
+        Command::Update(args) => {
+            cx.stash = args.stash;
+            cx.no_sudo = args.no_sudo;
+            cx.mas = args.mas;
+            println!("Update macOS setup tool trkw/trk\n");
+            execute(cx, "update", &steps::update(cx), args)
+        }
+        Command::Plan { run, json, stash } => {
+            cx.stash = stash;
+            let plan = match run {
+                Run::Bootstrap => Plan::build(cx, "bootstrap", &steps::bootstrap(cx)),
+                Run::Update => Plan::build(cx, "update", &steps::update(cx)),
+            };
+            if json {
+                println!("{}", plan.to_json());
+            } else {
+                print!("{plan}");
+            }
+            Ok(())
+        }
+        Command::Capture {
+            file,
+            dry_run,
+            commit,
+        } => {
+            let path = brewfile_path(cx, file)?;
+            let entries = capture::run(cx, &path, dry_run)?;
+            if entries.is_empty() {
+                println!("{} lists everything installed.", path.display());
+                return Ok(());
+            }
+            let verb = if dry_run { "Would add" } else { "Added" };
+            println!("{verb} to {}:", path.display());
+            for entry in &entries {
+                println!("  {entry}");
+            }
+            if commit {
+                commit_brewfile(cx, &path, entries.len())?;
+            }
+            Ok(())
+        }
+        Command::Brewfile(BrewfileCommand::Check { file, json }) => {
+            let path = brewfile_path(cx, file)?;
+            let check = Check::new(&Brewfile::read(&path)?, &Installed::read(cx)?);
+            if json {
+                let json = serde_json::to_string_pretty(&check).expect("check serializes");
+                println!("{}", cx.redact(&json));
+            } else {
+                print!("{check}");
+            }
+            Ok(())
+        }
+        Command::Repos(ReposCommand::Status { no_fetch, json }) => {
+            repos_status(cx, !no_fetch, json)
+        }
+        Command::Repos(ReposCommand::Outdated { json }) => repos_outdated(cx, json),
+        Command::Repos(ReposCommand::Upgrade { name, to, stash }) => {
+            repos_upgrade(cx, &name, to, stash)
+        }
+        Command::Runtimes(RuntimesCommand::Update { plugins }) => runtimes_update(cx, &plugins),
+        Command::Ssh(SshCommand::Get { key, force }) => {
+            let provider = key.store.open(cx);
+            let changed = ssh::get(cx, provider.as_ref(), &key.name, force)?;
+            let path = ssh::key_path(cx, &key.name);
+            match changed {
+                true => println!("Wrote {} from {}.", path.display(), key.store.provider),
+                false => println!("{} is up to date.", path.display()),
+            }
+            Ok(())
+        }
+        Command::Ssh(SshCommand::Put { key }) => {
+            let provider = key.store.open(cx);
+            ssh::put(cx, provider.as_ref(), &key.name)?;
+            println!("Saved {} to {}.", key.name, key.store.provider);
+            Ok(())
+        }
+        Command::Ssh(SshCommand::Generate {
+            key,
+            comment,
+            force,
+        }) => {
+            let provider = key.store.open(cx);
+            ssh::generate(cx, provider.as_ref(), &key.name, comment.as_deref(), force)?;
+            println!(
+                "Created {} and saved it to {}.",
+                ssh::key_path(cx, &key.name).display(),
+                key.store.provider
+            );
+            Ok(())
+        }
+        Command::Secrets(SecretsCommand::List { store }) => {
+            for item in store.open(cx).list()? {
+                println!("{item}");
+            }
+            Ok(())
+        }
+        Command::Secrets(SecretsCommand::Read { reference }) => {
+            println!("{}", cx.secret(&reference.parse()?)?);
+            Ok(())
+        }
+        Command::Facts { json } => {
+            if json {
+                println!("{}", cx.redact(&cx.facts.to_json()));
+            } else {
+                println!("{}", cx.facts);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// One line per checkout, and what to do about those that need a human.
+fn repos_status(cx: &Context, fetch: bool, json: bool) -> Result<()> {
+    let mut statuses = Vec::new();
+    for repo in steps::repos(cx) {
+        let status = repo.checkout().status(cx, fetch)?;
+        statuses.push((repo, status));
+    }
+    if json {
+        let report: Vec<_> = statuses
+            .iter()
+            .map(|(repo, status)| {
+                serde_json::json!({
+                    "name": repo.repo_name(),
+                    "path": repo.checkout().path(),
+                    "status": status,
+                    "problem": status.problem(repo.checkout().url(), false),
+                })
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&report).expect("statuses serialize");
+        println!("{}", cx.redact(&json));
+        return Ok(());
+    }
+    let width = statuses
+        .iter()
+        .map(|(repo, _)| repo.repo_name().len())
+        .max()
+        .unwrap_or(0);
+    for (repo, status) in &statuses {
+        let checkout = repo.checkout();
+        println!(
+            "{:width$}  {}: {status}",
+            repo.repo_name(),
+            checkout.path().display()
+        );
+        if let Some(problem) = status.problem(checkout.url(), false) {
+            println!("{:width$}  needs attention: {}", "", cx.redact(&problem));
+        }
+    }
+    Ok(())
+}
+
+/// Re-resolves the aliases of every runtime, or of `only`, into the lock.
+fn runtimes_update(cx: &Context, only: &[String]) -> Result<()> {
+    let mut runtimes = BTreeMap::new();
+    if let Some(path) = cx.dotfiles_tool_versions() {
+        runtimes = ToolVersions::new(cx.manifest.version_manager(), path).runtimes()?;
+    }
+    runtimes.extend(cx.manifest.runtimes.clone());
+    if let Some(unknown) = only.iter().find(|p| !runtimes.contains_key(p.as_str())) {
+        return Err(Error::Runtime {
+            plugin: unknown.clone(),
+            message: "not in [runtimes] or .tool-versions".into(),
+        });
+    }
+
+    let updates = runtime::update(cx, &runtimes, only)?;
+    for update in &updates {
+        let (plugin, alias, version) = (&update.plugin, &update.alias, &update.version);
+        match &update.before {
+            Some(before) if before == version => {
+                println!("{plugin} {alias}: {version} (unchanged)")
+            }
+            Some(before) => println!("{plugin} {alias}: {before} -> {version}"),
+            None => println!("{plugin} {alias}: {version} (new)"),
+        }
+    }
+    if updates.is_empty() {
+        println!("No version aliases to resolve.");
+    } else {
+        println!(
+            "Locked in {}; run `trk update` to install.",
+            cx.runtimes_lock().display()
+        );
+    }
+    Ok(())
+}
+
+/// Newer tags for every repo pinned to a tag or version.
+fn repos_outdated(cx: &Context, json: bool) -> Result<()> {
+    let mut report = Vec::new();
+    for repo in steps::repos(cx) {
+        if let Some(upgrades) = repo.checkout().upgrades(cx)? {
+            report.push((repo, upgrades));
+        }
+    }
+    if json {
+        let report: Vec<_> = report
+            .iter()
+            .map(|(repo, upgrades)| {
+                serde_json::json!({
+                    "name": repo.repo_name(),
+                    "pin": repo.checkout().pinned().map(|pin| pin.to_string()),
+                    "current": upgrades.current,
+                    "newer": upgrades.newer,
+                    "in_range": upgrades.in_range,
+                })
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&report).expect("tags serialize");
+        println!("{}", cx.redact(&json));
+        return Ok(());
+    }
+    let width = report
+        .iter()
+        .map(|(repo, _)| repo.repo_name().len())
+        .max()
+        .unwrap_or(0);
+    for (repo, upgrades) in &report {
+        let name = repo.repo_name();
+        let current = &upgrades.current;
+        match upgrades.newer.last() {
+            None => println!("{name:width$}  {current}  up to date"),
+            Some(latest) => {
+                print!(
+                    "{name:width$}  {current} -> {latest}  ({} newer",
+                    upgrades.newer.len()
+                );
+                match &upgrades.in_range {
+                    Some(tag) if tag != latest => print!("; {tag} within the version range"),
+                    _ => {}
+                }
+                println!(")");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `trk repos upgrade <name>`: the newest tag, or `to`.
+fn repos_upgrade(cx: &Context, name: &str, to: Option<String>, stash: bool) -> Result<()> {
+    let find = |cx: &Context| {
+        let repos = steps::repos(cx);
+        let known = repos.iter().map(|r| r.repo_name().to_owned()).collect();
+        repos
+            .into_iter()
+            .find(|repo| repo.repo_name() == name)
+            .ok_or_else(|| Error::UnknownRepo {
+                name: name.to_owned(),
+                known,
+            })
+    };
+    let repo = find(cx)?;
+    let checkout = repo.checkout();
+    let Some(upgrades) = checkout.upgrades(cx)? else {
+        // Branches have nothing to move to; sync them as a run would.
+        checkout.sync(cx, stash)?;
+        println!("{name}: {}", checkout.status(cx, false)?);
+        return Ok(());
+    };
+    let target = match to {
+        Some(tag) if checkout.remote_tags(cx)?.contains(&tag) => tag,
+        Some(tag) => {
+            return Err(Error::Repo {
+                path: checkout.path().to_owned(),
+                problem: format!("there is no tag `{tag}` in {}", checkout.url()),
+            })
+        }
+        None => match upgrades.in_range.or(upgrades.newer.last().cloned()) {
+            Some(tag) => tag,
+            None => {
+                println!("{name} is at the newest tag, {}", upgrades.current);
+                return Ok(());
+            }
+        },
+    };
+
+    match checkout.pinned() {
+        Some(Pin::Tag(_)) => {
+            let declared = &cx.manifest.repos[name];
+            let file = manifest::pin_tag(&cx.trk_dir, &cx.dotfiles_dir, name, declared, &target)?;
+            println!("{name}: pinned to {target} in {}", file.display());
+            let mut cx = cx.clone();
+            cx.load_manifest()?;
+            find(&cx)?.checkout().sync(&cx, stash)?;
+        }
+        _ => {
+            checkout.upgrade_to(cx, &target, stash)?;
+        }
+    }
+    println!("{name}: {} -> {target}", upgrades.current);
+    Ok(())
+}
+
+/// `--file`, or the dotfiles Brewfile from the manifest.
+fn brewfile_path(cx: &Context, file: Option<PathBuf>) -> Result<PathBuf> {
+    match file {
+        Some(file) => Ok(file),
+        None => cx.dotfiles_brewfile().ok_or_else(|| Error::Path {
+            path: cx.dotfiles_dir.clone(),
+            message: "trk.toml names no dotfiles brewfile; pass --file".into(),
+        }),
+    }
+}
+
+/// Commits just the Brewfile, leaving anything else staged alone.
+fn commit_brewfile(cx: &Context, path: &Path, added: usize) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let file = path.to_string_lossy();
+    cx.cmd("git").cwd(dir).args(["add", "--", &file]).status()?;
+    let message = format!(
+        "Capture {added} installed package{} into {}",
+        if added == 1 { "" } else { "s" },
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    cx.cmd("git")
+        .cwd(dir)
+        .args(["commit", "--edit", "-m", &message, "--", &file])
+        .status()
+}
+
+fn execute(cx: &Context, name: &str, steps: &[Box<dyn Step>], args: RunArgs) -> Result<()> {
+    println!("machine: {}\n", cx.facts);
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, name))?;
+    steps::run_all(cx, steps, &args.selection(), &mut journal)
+}
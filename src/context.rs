@@ -0,0 +1,252 @@
+//! Per-run state shared by every step.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::error::{Error, Result};
+use crate::exec::{Cmd, CommandRunner, Recorder, SystemRunner};
+use crate::facts::Facts;
+use crate::manifest::Manifest;
+use crate::redact::Redactor;
+use crate::report::Report;
+use crate::runtime;
+use crate::secret::{OpSession, Reference};
+use crate::steps::MasChoice;
+
+/// What a run knows about the machine and the user's environment.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub home: PathBuf,
+    /// Checkout of this repository, `~/.trk`.
+    pub trk_dir: PathBuf,
+    /// Checkout of the user's dotfiles, `~/dotfiles` unless the manifest
+    /// says otherwise.
+    pub dotfiles_dir: PathBuf,
+    /// `$DOTFILES_URL` or the manifest's dotfiles URL, cloned to
+    /// [`Context::dotfiles_dir`] when set.
+    pub dotfiles_url: Option<String>,
+    /// User-writable directory for binaries trk installs, `~/.local/bin`.
+    pub bin_dir: PathBuf,
+    /// Where releases are downloaded from, `https://github.com` unless
+    /// `$TRK_GITHUB_URL` says otherwise.
+    pub github_url: String,
+    /// The machine: OS, architecture, Rosetta, Homebrew prefix.
+    pub facts: Facts,
+    /// `$PRIVATE=y`: this is a personal machine, so optional extras such
+    /// as Rosetta are installed too.
+    pub private: bool,
+    /// `--stash`: uncommitted changes in a checkout that is behind are
+    /// stashed for the fast-forward instead of stopping the run.
+    pub stash: bool,
+    /// `--no-sudo`: steps that need root are skipped and left for a later
+    /// run, and nothing asks for a password.
+    pub no_sudo: bool,
+    /// `--mas`: what to do when App Store apps are due and the account is
+    /// not signed in, instead of asking.
+    pub mas: Option<MasChoice>,
+    pub manifest: Manifest,
+    path: OsString,
+    runner: Arc<dyn CommandRunner>,
+    report: Arc<Mutex<Report>>,
+    /// Secrets resolved so far, by reference, so each is fetched once per
+    /// run.
+    secrets: Arc<Mutex<BTreeMap<String, String>>>,
+    op_session: Arc<OpSession>,
+    redactor: Arc<Redactor>,
+}
+
+impl Context {
+    /// Builds the context from the environment, the way the shell scripts
+    /// did before each run, and loads the manifest.
+    ///
+    /// With `$TRK_RECORD` set, every command is also written to that file
+    /// as a fixture for [`FakeRunner::replay`](crate::exec::FakeRunner::replay).
+    pub fn from_env() -> Result<Self> {
+        let home = env::var_os("HOME")
+            .filter(|h| !h.is_empty())
+            .map(PathBuf::from)
+            .ok_or(Error::NoHome)?;
+        let runner: Arc<dyn CommandRunner> = match env::var_os("TRK_RECORD") {
+            Some(fixture) => Arc::new(Recorder::new(Arc::new(SystemRunner), fixture)),
+            None => Arc::new(SystemRunner),
+        };
+        let facts = Facts::detect(&runner);
+        let path = env::var_os("PATH").unwrap_or_default();
+        let mut cx = Self::new(home, path, runner, facts)?;
+        cx.private = env::var("PRIVATE").is_ok_and(|p| p == "y");
+        if let Some(url) = env::var("DOTFILES_URL").ok().filter(|u| !u.is_empty()) {
+            cx.dotfiles_url = Some(url);
+        }
+        if let Some(url) = env::var("TRK_GITHUB_URL").ok().filter(|u| !u.is_empty()) {
+            cx.github_url = url;
+        }
+        Ok(cx)
+    }
+
+    /// Builds a context for `home` on a machine described by `facts` that
+    /// runs every command through `runner`, without reading anything else
+    /// from the environment.
+    pub fn new(
+        home: PathBuf,
+        path: OsString,
+        runner: Arc<dyn CommandRunner>,
+        facts: Facts,
+    ) -> Result<Self> {
+        let brew_bin = facts.homebrew_prefix.join("bin");
+        let bin_dir = home.join(".local/bin");
+        let mut paths = vec![brew_bin.clone(), bin_dir.clone()];
+        paths.extend(env::split_paths(&path));
+        let path = env::join_paths(paths).map_err(|err| Error::Path {
+            path: brew_bin,
+            message: err.to_string(),
+        })?;
+
+        let mut cx = Self {
+            trk_dir: home.join(".trk"),
+            dotfiles_dir: home.join("dotfiles"),
+            dotfiles_url: None,
+            private: false,
+            stash: false,
+            no_sudo: false,
+            mas: None,
+            bin_dir,
+            github_url: "https://github.com".into(),
+            home,
+            facts,
+            manifest: Manifest::default(),
+            path,
+            runner,
+            report: Arc::default(),
+            secrets: Arc::default(),
+            op_session: Arc::default(),
+            redactor: Arc::default(),
+        };
+        cx.load_manifest()?;
+        cx.dotfiles_dir = cx.expand(cx.manifest.dotfiles.path());
+        cx.dotfiles_url = cx.manifest.dotfiles.url.as_ref().map(|u| u.to_string());
+        Ok(cx)
+    }
+
+    /// Loads the manifest again. Its secret references stay as written
+    /// until a step resolves the value that holds them.
+    pub fn load_manifest(&mut self) -> Result<()> {
+        self.manifest = Manifest::load(&self.trk_dir, |p| self.expand(p))?;
+        Ok(())
+    }
+
+    /// The secret `reference` names, fetched from its provider the first
+    /// time it is asked for in this run.
+    pub fn secret(&self, reference: &Reference) -> Result<String> {
+        let key = reference.to_string();
+        if let Some(value) = self.lock_secrets().get(&key) {
+            return Ok(value.clone());
+        }
+        let value = reference
+            .backend
+            .provider(self, &reference.vault)
+            .resolve(&reference.item, &reference.field)?;
+        self.redactor.register(&value);
+        self.lock_secrets().insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// Masks the run's secrets wherever trk writes.
+    pub fn redactor(&self) -> &Arc<Redactor> {
+        &self.redactor
+    }
+
+    /// `text` with every secret resolved so far masked.
+    pub fn redact(&self, text: &str) -> String {
+        self.redactor.redact(text)
+    }
+
+    /// `err`, or its message with the secrets masked if it held any.
+    pub fn redact_error(&self, err: Error) -> Error {
+        let message = err.to_string();
+        let redacted = self.redact(&message);
+        match redacted == message {
+            true => err,
+            false => Error::Redacted(redacted),
+        }
+    }
+
+    /// The run's 1Password CLI session.
+    pub fn op_session(&self) -> &OpSession {
+        &self.op_session
+    }
+
+    fn lock_secrets(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.secrets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The dotfiles Brewfile named in the manifest.
+    pub fn dotfiles_brewfile(&self) -> Option<PathBuf> {
+        let hook = self.manifest.dotfiles.brewfile.as_ref()?;
+        Some(self.dotfiles_dir.join(hook.as_str()))
+    }
+
+    /// The dotfiles `.tool-versions` named in the manifest.
+    pub fn dotfiles_tool_versions(&self) -> Option<PathBuf> {
+        let hook = self.manifest.dotfiles.tool_versions.as_ref()?;
+        Some(self.dotfiles_dir.join(hook.as_str()))
+    }
+
+    /// Where the releases picked for runtime aliases are locked: next to
+    /// the dotfiles `osx/trk.toml` when there is (or will be) a dotfiles
+    /// checkout, so everyone using it shares them, else in `~/.trk/state`.
+    pub fn runtimes_lock(&self) -> PathBuf {
+        if self.dotfiles_url.is_some() || self.dotfiles_dir.join(".git").exists() {
+            self.dotfiles_dir.join("osx").join(runtime::LOCK_FILE)
+        } else {
+            self.trk_dir.join("state").join(runtime::LOCK_FILE)
+        }
+    }
+
+    /// Where asdf is checked out: the `asdf` repo from the manifest, or
+    /// `~/.asdf`.
+    pub fn asdf_dir(&self) -> PathBuf {
+        match self.manifest.repos.get("asdf") {
+            Some(repo) => self.expand(repo.path.as_str()),
+            None => self.home.join(".asdf"),
+        }
+    }
+
+    /// A command, run through the context's runner, that sees Homebrew's
+    /// `bin` and then [`Context::bin_dir`] first on `$PATH`.
+    pub fn cmd(&self, program: impl Into<String>) -> Cmd {
+        Cmd::new(program)
+            .env("PATH", self.path.clone())
+            .runner(self.runner.clone())
+            .redactor(self.redactor.clone())
+    }
+
+    /// Looks `program` up on the run's `$PATH`, like `which -s`.
+    pub fn which(&self, program: &str) -> Option<PathBuf> {
+        self.runner.which(program, &self.path)
+    }
+
+    /// The run's report so far.
+    pub fn report(&self) -> Report {
+        self.report
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Changes the run's report; the journal saves it with the next step.
+    pub fn update_report(&self, update: impl FnOnce(&mut Report)) {
+        update(&mut self.report.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    /// Expands a leading `~/` to the home directory.
+    pub fn expand(&self, path: &str) -> PathBuf {
+        match path.strip_prefix("~/") {
+            Some(rest) => self.home.join(rest),
+            None if path == "~" => self.home.clone(),
+            None => PathBuf::from(path),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can stop a trk run.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("could not start `{program}`: {source}")]
+    Spawn {
+        program: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("`{command}` exited with status {status}{}", stderr_suffix(.stderr))]
+    Command {
+        command: String,
+        status: i32,
+        stderr: String,
+    },
+
+    #[error("unexpected output from `{command}`: {message}")]
+    Output { command: String, message: String },
+
+    #[error("{path}:{line}: {message}")]
+    Brewfile {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+
+    #[error("could not determine the home directory (is $HOME set?)")]
+    NoHome,
+
+    #[error("{path}: {message}")]
+    Path { path: PathBuf, message: String },
+
+    #[error("{path}: {message}")]
+    Manifest { path: PathBuf, message: String },
+
+    #[error("{path} needs attention: {problem}")]
+    Repo { path: PathBuf, problem: String },
+
+    #[error("release `{name}`: {message}")]
+    Release { name: String, message: String },
+
+    #[error("runtime `{plugin}`: {message}")]
+    Runtime { plugin: String, message: String },
+
+    #[error("{path}: task `{task}`: {message}")]
+    Task {
+        path: PathBuf,
+        task: String,
+        message: String,
+    },
+
+    #[error("sudo is unavailable for {steps}: {reason}; run with --no-sudo to leave them for a later run")]
+    Sudo { steps: String, reason: String },
+
+    #[error("App Store: {message}")]
+    Mas { message: String },
+
+    #[error("secrets ({provider}): {message}")]
+    Secret { provider: String, message: String },
+
+    /// An error whose message held a secret, with the secret masked.
+    #[error("{0}")]
+    Redacted(String),
+
+    #[error("checksum mismatch for `{asset}`: expected {expected}, got {actual}")]
+    Checksum {
+        asset: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("no repo named `{name}` in trk.toml; repos are: {}", known.join(", "))]
+    UnknownRepo { name: String, known: Vec<String> },
+
+    #[error("no step named `{name}` in this run; steps are: {}", known.join(", "))]
+    UnknownStep { name: String, known: Vec<String> },
+
+    #[error("step `{step}` failed: {source}")]
+    Step {
+        step: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn stderr_suffix(stderr: &str) -> String {
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(": {stderr}")
+    }
+}
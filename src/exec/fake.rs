@@ -0,0 +1,295 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::{Error, Result};
+
+use super::record::{self, Exchange};
+use super::{Cmd, CommandRunner, Mode, Output};
+
+/// A [`CommandRunner`] that answers from a script instead of running
+/// anything.
+///
+/// Each rule pairs an argv pattern with a [`Reply`]; a `*` in the pattern
+/// matches any single argument and a final `...` matches any remaining
+/// arguments. Rules added with [`FakeRunner::once`] answer a single time
+/// and take precedence, in the order they were added, over rules added
+/// with [`FakeRunner::on`]; among those, the first matching rule answers.
+/// A command no rule matches fails as if the program did not exist, so a
+/// test notices commands it did not expect.
+#[derive(Debug, Default)]
+pub struct FakeRunner {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    rules: Vec<Rule>,
+    programs: Vec<String>,
+    /// Recorded `which` answers, each given once in order before
+    /// `programs` is consulted.
+    lookups: Vec<Lookup>,
+    calls: Vec<Vec<String>>,
+}
+
+#[derive(Debug)]
+struct Lookup {
+    program: String,
+    found: bool,
+    used: bool,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: Vec<String>,
+    reply: Reply,
+    once: bool,
+    used: bool,
+}
+
+/// What a fake command does when it runs.
+#[derive(Clone, Default)]
+pub struct Reply {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+    provides: Vec<String>,
+    effect: Option<Effect>,
+}
+
+type Effect = Arc<dyn Fn(&Cmd) + Send + Sync>;
+
+impl FakeRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A fake that answers every command and `which` lookup of a recorded
+    /// session once, in the order they were recorded, so a program that
+    /// was missing until the session installed it is missing again at
+    /// first.
+    pub fn replay(path: &Path) -> Result<Self> {
+        let fake = Self::new();
+        for exchange in record::load(path)? {
+            match exchange {
+                Exchange::Run {
+                    argv,
+                    status,
+                    stdout,
+                    stderr,
+                } => {
+                    fake.once(
+                        argv,
+                        Reply {
+                            status,
+                            stdout,
+                            stderr,
+                            ..Reply::default()
+                        },
+                    );
+                }
+                Exchange::Which { program, found } => {
+                    fake.lock().lookups.push(Lookup {
+                        program,
+                        found,
+                        used: false,
+                    });
+                }
+            }
+        }
+        Ok(fake)
+    }
+
+    /// Answers every command matching `pattern` with `reply`.
+    pub fn on<I, S>(&self, pattern: I, reply: Reply) -> &Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.add(pattern, reply, false)
+    }
+
+    /// Answers the next command matching `pattern` with `reply`, ahead of
+    /// any rule added with [`FakeRunner::on`].
+    pub fn once<I, S>(&self, pattern: I, reply: Reply) -> &Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.add(pattern, reply, true)
+    }
+
+    /// Makes `program` visible to [`CommandRunner::which`].
+    pub fn program(&self, program: impl Into<String>) -> &Self {
+        self.lock().programs.push(program.into());
+        self
+    }
+
+    /// Every command run so far, as argv.
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.lock().calls.clone()
+    }
+
+    /// Every command run so far, as shown in error messages.
+    pub fn commands(&self) -> Vec<String> {
+        self.calls().iter().map(|argv| argv.join(" ")).collect()
+    }
+
+    fn add<I, S>(&self, pattern: I, reply: Reply, once: bool) -> &Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.lock().rules.push(Rule {
+            pattern: pattern.into_iter().map(Into::into).collect(),
+            reply,
+            once,
+            used: false,
+        });
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl CommandRunner for FakeRunner {
+    fn run(&self, cmd: &Cmd, mode: Mode) -> Result<Output> {
+        let argv = cmd.argv();
+        let reply = {
+            let mut state = self.lock();
+            state.calls.push(argv.clone());
+            let index = state
+                .rules
+                .iter()
+                .position(|rule| rule.once && !rule.used && matches(&rule.pattern, &argv))
+                .or_else(|| {
+                    state
+                        .rules
+                        .iter()
+                        .position(|rule| !rule.once && matches(&rule.pattern, &argv))
+                });
+            let rule = index.map(|i| &mut state.rules[i]);
+            let Some(rule) = rule else {
+                return Err(Error::Spawn {
+                    program: cmd.program().to_owned(),
+                    source: io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("no fake reply for `{cmd}`"),
+                    ),
+                });
+            };
+            rule.used = true;
+            let reply = rule.reply.clone();
+            state.programs.extend(reply.provides.iter().cloned());
+            reply
+        };
+        if let Some(effect) = &reply.effect {
+            effect(cmd);
+        }
+        Ok(match mode {
+            Mode::Captured => Output {
+                status: reply.status,
+                stdout: reply.stdout,
+                stderr: reply.stderr,
+            },
+            Mode::Attached => Output {
+                status: reply.status,
+                ..Output::default()
+            },
+            Mode::Prompted => Output {
+                status: reply.status,
+                stdout: reply.stdout,
+                ..Output::default()
+            },
+        })
+    }
+
+    fn which(&self, program: &str, _path: &OsStr) -> Option<PathBuf> {
+        let mut state = self.lock();
+        let found = match state
+            .lookups
+            .iter_mut()
+            .find(|lookup| !lookup.used && lookup.program == program)
+        {
+            Some(lookup) => {
+                lookup.used = true;
+                lookup.found
+            }
+            None => state.programs.iter().any(|p| p == program),
+        };
+        found.then(|| Path::new("/fake/bin").join(program))
+    }
+}
+
+fn matches(pattern: &[String], argv: &[String]) -> bool {
+    let (pattern, rest) = match pattern.split_last() {
+        Some((last, init)) if last == "..." => (init, true),
+        _ => (pattern, false),
+    };
+    let len_ok = if rest {
+        argv.len() >= pattern.len()
+    } else {
+        argv.len() == pattern.len()
+    };
+    len_ok && pattern.iter().zip(argv).all(|(p, a)| p == "*" || p == a)
+}
+
+impl Reply {
+    /// Exits zero with no output.
+    pub fn ok() -> Self {
+        Self::default()
+    }
+
+    /// Exits zero and prints `stdout`.
+    pub fn stdout(stdout: impl Into<String>) -> Self {
+        Self {
+            stdout: stdout.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Exits with `status` and no output.
+    pub fn exit(status: i32) -> Self {
+        Self {
+            status,
+            ..Self::default()
+        }
+    }
+
+    pub fn stderr(mut self, stderr: impl Into<String>) -> Self {
+        self.stderr = stderr.into();
+        self
+    }
+
+    /// After answering, `program` is visible to `which`, as after
+    /// installing it.
+    pub fn provides(mut self, program: impl Into<String>) -> Self {
+        self.provides.push(program.into());
+        self
+    }
+
+    /// Runs `effect` when the command runs, e.g. to create the directory a
+    /// real `git clone` would have created.
+    pub fn effect(mut self, effect: impl Fn(&Cmd) + Send + Sync + 'static) -> Self {
+        self.effect = Some(Arc::new(effect));
+        self
+    }
+}
+
+impl fmt::Debug for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reply")
+            .field("status", &self.status)
+            .field("stdout", &self.stdout)
+            .field("stderr", &self.stderr)
+            .field("provides", &self.provides)
+            .field("effect", &self.effect.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,355 @@
+//! Running external programs (brew, git, curl, ansible-playbook, ...).
+//!
+//! Every invocation goes through a [`CommandRunner`]. Real runs use
+//! [`SystemRunner`]; tests script a [`FakeRunner`] or replay a fixture
+//! captured with [`Recorder`], so whole runs work without a Mac or a
+//! network.
+
+mod fake;
+mod record;
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+
+use crate::error::{Error, Result};
+use crate::redact::Redactor;
+
+pub use fake::{FakeRunner, Reply};
+pub use record::{Exchange, Recorder};
+
+/// Runs commands on behalf of trk.
+pub trait CommandRunner: fmt::Debug + Send + Sync {
+    /// Runs `cmd` to completion. In [`Mode::Attached`] the command shares
+    /// the terminal and the returned output holds only the exit status.
+    fn run(&self, cmd: &Cmd, mode: Mode) -> Result<Output>;
+
+    /// Looks `program` up on `path`, like `which -s`.
+    fn which(&self, program: &str, path: &OsStr) -> Option<PathBuf> {
+        env::split_paths(path)
+            .map(|dir| dir.join(program))
+            .find(|candidate| is_executable(candidate))
+    }
+}
+
+/// How a command's standard streams are connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// stdout and stderr are captured; stdin is closed, or fed what
+    /// [`Cmd::stdin`] gave.
+    Captured,
+    /// All three are inherited, so progress output and password prompts
+    /// reach the user.
+    Attached,
+    /// stdin and stderr are inherited so the command can ask the user
+    /// for a password; stdout, which answers, is captured.
+    Prompted,
+}
+
+/// Runs commands for real with [`std::process::Command`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+/// A command line to run, built up before it is handed to [`Cmd::output`]
+/// or [`Cmd::status`].
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    program: String,
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    env: Vec<(String, OsString)>,
+    stdin: Option<String>,
+    sensitive: bool,
+    runner: Option<Arc<dyn CommandRunner>>,
+    redactor: Option<Arc<Redactor>>,
+}
+
+/// What a finished command left behind. `stdout` and `stderr` are empty for
+/// commands run with [`Cmd::status`], whose output goes to the terminal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, cmd: &Cmd, mode: Mode) -> Result<Output> {
+        let mut command = Command::new(&cmd.program);
+        command.args(&cmd.args);
+        if let Some(cwd) = &cmd.cwd {
+            command.current_dir(cwd);
+        }
+        for (key, value) in &cmd.env {
+            command.env(key, value);
+        }
+        let spawn_error = |source| Error::Spawn {
+            program: cmd.program.clone(),
+            source,
+        };
+        match mode {
+            Mode::Captured => {
+                let out = match &cmd.stdin {
+                    None => command.stdin(Stdio::null()).output(),
+                    Some(input) => command
+                        .stdin(Stdio::piped())
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::piped())
+                        .spawn()
+                        .and_then(|mut child| {
+                            let mut stdin = child.stdin.take().expect("stdin is piped");
+                            let input = input.clone();
+                            // Written from a thread so a child that fills its
+                            // stdout before reading everything cannot block us.
+                            let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+                            let out = child.wait_with_output()?;
+                            writer.join().expect("stdin writer panicked")?;
+                            Ok(out)
+                        }),
+                }
+                .map_err(spawn_error)?;
+                Ok(Output {
+                    status: out.status.code().unwrap_or(-1),
+                    stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+                    stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+                })
+            }
+            Mode::Prompted => {
+                let out = command
+                    .stdin(Stdio::inherit())
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .map_err(spawn_error)?;
+                Ok(Output {
+                    status: out.status.code().unwrap_or(-1),
+                    stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+                    ..Output::default()
+                })
+            }
+            Mode::Attached => {
+                let status = match cmd.redactor.clone().filter(|r| !r.is_empty()) {
+                    None => command.status(),
+                    Some(redactor) => attached_redacted(&mut command, redactor),
+                }
+                .map_err(spawn_error)?;
+                Ok(Output {
+                    status: status.code().unwrap_or(-1),
+                    ..Output::default()
+                })
+            }
+        }
+    }
+}
+
+impl Cmd {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            cwd: None,
+            env: Vec::new(),
+            stdin: None,
+            sensitive: false,
+            runner: None,
+            redactor: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<str>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|a| a.as_ref().to_owned()));
+        self
+    }
+
+    pub fn cwd(mut self, dir: impl AsRef<Path>) -> Self {
+        self.cwd = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<OsString>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Feeds `input` to the command's stdin, for secrets that must not show
+    /// up in its arguments. Only [`Cmd::output`] passes it on.
+    pub fn stdin(mut self, input: impl Into<String>) -> Self {
+        self.stdin = Some(input.into());
+        self
+    }
+
+    /// Marks the command's stdout as a secret, such as a password or a
+    /// session token, that [`Recorder`] must not write to its fixture.
+    pub fn sensitive(mut self) -> Self {
+        self.sensitive = true;
+        self
+    }
+
+    /// Runs the command through `runner` instead of [`SystemRunner`].
+    pub fn runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = Some(runner);
+        self
+    }
+
+    /// Masks the secrets `redactor` knows in what the command prints to
+    /// the terminal and what [`Recorder`] writes.
+    pub fn redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// `text` with the secrets of the command's redactor masked.
+    pub fn redact(&self, text: &str) -> String {
+        match &self.redactor {
+            Some(redactor) => redactor.redact(text),
+            None => text.to_owned(),
+        }
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn get_args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn get_cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    pub fn get_stdin(&self) -> Option<&str> {
+        self.stdin.as_deref()
+    }
+
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    /// The value the command will see for `key`, if it is set explicitly.
+    pub fn get_env(&self, key: &str) -> Option<&OsStr> {
+        self.env
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_os_str())
+    }
+
+    /// The program followed by its arguments.
+    pub fn argv(&self) -> Vec<String> {
+        let mut argv = vec![self.program.clone()];
+        argv.extend(self.args.iter().cloned());
+        argv
+    }
+
+    /// Runs the command with stdout and stderr captured.
+    pub fn output(&self) -> Result<Output> {
+        self.run(Mode::Captured)
+    }
+
+    /// Runs the command attached to the terminal, so progress output and
+    /// password prompts reach the user, and fails if it exits non-zero.
+    pub fn status(&self) -> Result<()> {
+        self.run(Mode::Attached)?.check(self).map(drop)
+    }
+
+    /// Runs the command so it can ask the user on the terminal, capturing
+    /// only its stdout, and fails if it exits non-zero.
+    pub fn prompt(&self) -> Result<Output> {
+        self.run(Mode::Prompted)?.check(self)
+    }
+
+    /// Runs the command quietly and reports whether it exited zero.
+    pub fn succeeds(&self) -> Result<bool> {
+        Ok(self.output()?.success())
+    }
+
+    fn run(&self, mode: Mode) -> Result<Output> {
+        match &self.runner {
+            Some(runner) => runner.run(self, mode),
+            None => SystemRunner.run(self, mode),
+        }
+    }
+}
+
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.program)?;
+        for arg in &self.args {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                write!(f, " '{arg}'")?;
+            } else {
+                write!(f, " {arg}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Output {
+    pub fn success(&self) -> bool {
+        self.status == 0
+    }
+
+    /// Turns a non-zero exit into [`Error::Command`].
+    pub fn check(self, cmd: &Cmd) -> Result<Self> {
+        if self.success() {
+            Ok(self)
+        } else {
+            Err(Error::Command {
+                command: cmd.to_string(),
+                status: self.status,
+                stderr: self.stderr,
+            })
+        }
+    }
+}
+
+/// Runs `command` on the terminal with its stdout and stderr passed
+/// through `redactor` on the way. The program sees pipes rather than a
+/// terminal, so some drop colours and progress bars; only runs that
+/// resolved a secret pay for that.
+fn attached_redacted(
+    command: &mut Command,
+    redactor: Arc<Redactor>,
+) -> io::Result<std::process::ExitStatus> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let err_redactor = redactor.clone();
+    let out = thread::spawn(move || redactor.stream(stdout, io::stdout()));
+    let err = thread::spawn(move || err_redactor.stream(stderr, io::stderr()));
+    let status = child.wait()?;
+    out.join().expect("stdout copier panicked")?;
+    err.join().expect("stderr copier panicked")?;
+    Ok(status)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,101 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+use super::{Cmd, CommandRunner, Mode, Output};
+
+/// One interaction with the outside world in a recorded session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Exchange {
+    Run {
+        argv: Vec<String>,
+        status: i32,
+        #[serde(default)]
+        stdout: String,
+        #[serde(default)]
+        stderr: String,
+    },
+    Which {
+        program: String,
+        found: bool,
+    },
+}
+
+/// A [`CommandRunner`] that passes everything to another runner and
+/// writes each exchange to a fixture file as it happens, for
+/// [`FakeRunner::replay`](super::FakeRunner::replay).
+#[derive(Debug)]
+pub struct Recorder {
+    inner: Arc<dyn CommandRunner>,
+    path: PathBuf,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl Recorder {
+    pub fn new(inner: Arc<dyn CommandRunner>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            exchanges: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn push(&self, exchange: Exchange) -> Result<()> {
+        let mut exchanges = self
+            .exchanges
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        exchanges.push(exchange);
+        let json = serde_json::to_string_pretty(&*exchanges).expect("exchanges serialize");
+        fs::write(&self.path, json + "\n").map_err(|err| Error::Path {
+            path: self.path.clone(),
+            message: err.to_string(),
+        })
+    }
+}
+
+impl CommandRunner for Recorder {
+    fn run(&self, cmd: &Cmd, mode: Mode) -> Result<Output> {
+        let output = self.inner.run(cmd, mode)?;
+        self.push(Exchange::Run {
+            argv: cmd.argv().iter().map(|arg| cmd.redact(arg)).collect(),
+            status: output.status,
+            // A replay of a secret gets an empty one.
+            stdout: if cmd.is_sensitive() {
+                String::new()
+            } else {
+                cmd.redact(&output.stdout)
+            },
+            stderr: cmd.redact(&output.stderr),
+        })?;
+        Ok(output)
+    }
+
+    fn which(&self, program: &str, path: &OsStr) -> Option<PathBuf> {
+        let found = self.inner.which(program, path);
+        // A fixture that cannot be written shows up on the next `run`.
+        let _ = self.push(Exchange::Which {
+            program: program.to_owned(),
+            found: found.is_some(),
+        });
+        found
+    }
+}
+
+/// Reads a fixture written by [`Recorder`].
+pub fn load(path: &Path) -> Result<Vec<Exchange>> {
+    let json = fs::read_to_string(path).map_err(|err| Error::Path {
+        path: path.to_owned(),
+        message: err.to_string(),
+    })?;
+    serde_json::from_str(&json).map_err(|err| Error::Path {
+        path: path.to_owned(),
+        message: format!("not a trk fixture: {err}"),
+    })
+}
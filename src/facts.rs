@@ -0,0 +1,235 @@
+//! What kind of machine trk is running on.
+//!
+//! Facts are detected once per run through the context's
+//! [`CommandRunner`], so a test can either script the probes or build a
+//! [`Facts`] directly and hand it to [`Context::new`](crate::Context::new).
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Serialize, Serializer};
+
+use crate::exec::{Cmd, CommandRunner};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Facts {
+    pub os: Os,
+    /// Linux distribution id from `/etc/os-release`, e.g. `ubuntu`.
+    pub distro: Option<String>,
+    /// `sw_vers -productVersion` on macOS, `VERSION_ID` on Linux.
+    pub os_version: Option<String>,
+    /// The hardware architecture. Under Rosetta this is `arm64` even though
+    /// `uname -m` says `x86_64`.
+    pub arch: Arch,
+    /// The process runs translated by Rosetta 2 (`sysctl.proc_translated`).
+    pub rosetta: bool,
+    pub homebrew_prefix: PathBuf,
+    /// The Xcode Command Line Tools are installed (`xcode-select -p`).
+    pub xcode_clt: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Os {
+    Macos,
+    Linux,
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arch {
+    Arm64,
+    X86_64,
+    Other(String),
+}
+
+impl Facts {
+    /// Probes the machine through `runner`. A probe that cannot run counts
+    /// as a negative answer rather than an error.
+    pub fn detect(runner: &Arc<dyn CommandRunner>) -> Self {
+        let probe = |program: &str, args: &[&str]| -> Option<String> {
+            Cmd::new(program)
+                .args(args)
+                .runner(runner.clone())
+                .output()
+                .ok()
+                .filter(|out| out.success())
+                .map(|out| out.stdout.trim().to_owned())
+        };
+
+        let os = match probe("/usr/bin/uname", &["-s"]).as_deref() {
+            Some("Darwin") => Os::Macos,
+            Some("Linux") => Os::Linux,
+            Some(other) => Os::Other(other.to_owned()),
+            None => Os::from_target(),
+        };
+        let mut arch = probe("/usr/bin/uname", &["-m"])
+            .as_deref()
+            .map_or_else(Arch::from_target, Arch::from_machine);
+
+        let (mut distro, mut os_version) = (None, None);
+        let (mut rosetta, mut xcode_clt) = (false, false);
+        match os {
+            Os::Macos => {
+                rosetta = probe("/usr/sbin/sysctl", &["-n", "sysctl.proc_translated"]).as_deref()
+                    == Some("1");
+                if rosetta {
+                    arch = Arch::Arm64;
+                }
+                os_version = probe("/usr/bin/sw_vers", &["-productVersion"]);
+                xcode_clt = probe("/usr/bin/xcode-select", &["-p"]).is_some();
+            }
+            Os::Linux => {
+                if let Ok(release) = fs::read_to_string("/etc/os-release") {
+                    (distro, os_version) = parse_os_release(&release);
+                }
+            }
+            Os::Other(_) => {}
+        }
+
+        Facts {
+            homebrew_prefix: homebrew_prefix(&os, &arch),
+            os,
+            distro,
+            os_version,
+            arch,
+            rosetta,
+            xcode_clt,
+        }
+    }
+
+    pub fn is_macos(&self) -> bool {
+        self.os == Os::Macos
+    }
+
+    pub fn is_apple_silicon(&self) -> bool {
+        self.is_macos() && self.arch == Arch::Arm64
+    }
+
+    /// The facts as `trk_*` variables for the Ansible playbook.
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("trk_os", self.os.to_string()),
+            ("trk_arch", self.arch.to_string()),
+            ("trk_rosetta", self.rosetta.to_string()),
+            ("trk_xcode_clt", self.xcode_clt.to_string()),
+            (
+                "trk_homebrew_prefix",
+                self.homebrew_prefix.to_string_lossy().into_owned(),
+            ),
+        ]
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("facts serialize")
+    }
+}
+
+/// Where Homebrew lives by default: `/opt/homebrew` on Apple Silicon (also
+/// when running under Rosetta), `/usr/local` on Intel Macs and
+/// `/home/linuxbrew/.linuxbrew` on Linux.
+pub fn homebrew_prefix(os: &Os, arch: &Arch) -> PathBuf {
+    PathBuf::from(match (os, arch) {
+        (Os::Macos, Arch::Arm64) => "/opt/homebrew",
+        (Os::Linux, _) => "/home/linuxbrew/.linuxbrew",
+        _ => "/usr/local",
+    })
+}
+
+/// `ID` and `VERSION_ID` from an `os-release` file.
+pub fn parse_os_release(release: &str) -> (Option<String>, Option<String>) {
+    let field = |name: &str| {
+        release.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_owned())
+        })
+    };
+    (field("ID"), field("VERSION_ID"))
+}
+
+impl Os {
+    fn from_target() -> Self {
+        match std::env::consts::OS {
+            "macos" => Os::Macos,
+            "linux" => Os::Linux,
+            other => Os::Other(other.to_owned()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Os::Macos => "macos",
+            Os::Linux => "linux",
+            Os::Other(other) => other,
+        }
+    }
+}
+
+impl Arch {
+    /// Parses `uname -m`.
+    pub fn from_machine(machine: &str) -> Self {
+        match machine {
+            "arm64" | "aarch64" => Arch::Arm64,
+            "x86_64" | "amd64" => Arch::X86_64,
+            other => Arch::Other(other.to_owned()),
+        }
+    }
+
+    fn from_target() -> Self {
+        Self::from_machine(std::env::consts::ARCH)
+    }
+
+    /// The name macOS uses, `arm64` or `x86_64`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Arch::Arm64 => "arm64",
+            Arch::X86_64 => "x86_64",
+            Arch::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for Os {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Os {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl Serialize for Arch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// One line for the start of a run, e.g.
+/// `macos 14.2 arm64 (Rosetta), Homebrew in /opt/homebrew`.
+impl fmt::Display for Facts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.distro.as_deref().unwrap_or(self.os.as_str()))?;
+        if let Some(version) = &self.os_version {
+            write!(f, " {version}")?;
+        }
+        write!(f, " {}", self.arch)?;
+        if self.rosetta {
+            f.write_str(" (Rosetta)")?;
+        }
+        write!(f, ", Homebrew in {}", self.homebrew_prefix.display())?;
+        if self.is_macos() && !self.xcode_clt {
+            f.write_str(", no Xcode Command Line Tools")?;
+        }
+        Ok(())
+    }
+}
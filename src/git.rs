@@ -0,0 +1,747 @@
+//! Git checkouts kept in step with their remote: `~/.trk`, `~/dotfiles`,
+//! `~/.asdf` and the other repos from the manifest.
+//!
+//! [`Checkout::status`] tells the states a checkout can be in apart, and
+//! [`Checkout::sync`] only ever clones, fast-forwards or checks out the
+//! pinned ref. Whatever it cannot do safely is [`Error::Repo`], which
+//! names the checkout and what to do about it.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use semver::{Version, VersionReq};
+use serde::Serialize;
+
+use crate::base64;
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::exec::Cmd;
+use crate::manifest::Pin;
+use crate::secret;
+
+/// A repository that should be cloned at `path` from `url`. The URL may
+/// hold secret references; they are resolved only for the git commands
+/// that reach the remote, and messages show it as written. Credentials
+/// that are secret references are sent as a header rather than in the
+/// URL, so the resolved secret never lands in `.git/config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkout {
+    path: PathBuf,
+    url: String,
+    pin: Option<Pin>,
+}
+
+/// Where a checkout stands against its remote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum Status {
+    /// Not cloned yet.
+    Missing,
+    /// `origin` is some other repository.
+    WrongRemote {
+        origin: String,
+    },
+    /// At the pinned tag or commit.
+    Pinned {
+        at: String,
+    },
+    /// Somewhere other than the pinned ref, e.g. at an older tag after the
+    /// manifest moved the pin.
+    OffPin {
+        head: String,
+        want: String,
+        files: Vec<String>,
+    },
+    /// Detached, or on a branch without an upstream, with no pin to go
+    /// to: there is nothing to follow.
+    Untracked {
+        head: String,
+    },
+    /// Tracked files have uncommitted changes.
+    Dirty {
+        files: Vec<String>,
+        ahead: u32,
+        behind: u32,
+    },
+    UpToDate,
+    /// Clean, with new commits upstream.
+    Behind {
+        commits: u32,
+    },
+    /// Local commits that are not pushed yet.
+    Ahead {
+        commits: u32,
+    },
+    Diverged {
+        ahead: u32,
+        behind: u32,
+    },
+}
+
+/// What [`Checkout::sync`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Synced {
+    Cloned,
+    FastForwarded {
+        commits: u32,
+        stashed: bool,
+    },
+    /// Checked out the pinned ref.
+    Switched {
+        to: String,
+        stashed: bool,
+    },
+    Unchanged,
+}
+
+/// Tags newer than the one a tag or version pin is at, see
+/// [`Checkout::upgrades`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Upgrades {
+    pub current: String,
+    /// Oldest first; pre-releases are left out.
+    pub newer: Vec<String>,
+    /// The newest of `newer` a version pin allows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_range: Option<String>,
+}
+
+impl Checkout {
+    pub fn new(path: impl Into<PathBuf>, url: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            url: url.into(),
+            pin: None,
+        }
+    }
+
+    /// Keep `pin` checked out instead of the remote's default branch.
+    pub fn pin(mut self, pin: Pin) -> Self {
+        self.pin = Some(pin);
+        self
+    }
+
+    /// Whether it has been cloned.
+    pub fn exists(&self) -> bool {
+        self.path.join(".git").exists()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn pinned(&self) -> Option<&Pin> {
+        self.pin.as_ref()
+    }
+
+    fn git(&self, cx: &Context) -> Cmd {
+        cx.cmd("git").cwd(&self.path)
+    }
+
+    /// `git <args>` in the checkout, trimmed stdout, or `None` if it fails.
+    fn query(&self, cx: &Context, args: &[&str]) -> Result<Option<String>> {
+        let out = self.git(cx).args(args).output()?;
+        Ok(out.success().then(|| out.stdout.trim().to_owned()))
+    }
+
+    fn read(&self, cx: &Context, args: &[&str]) -> Result<String> {
+        let cmd = self.git(cx).args(args);
+        Ok(cmd.output()?.check(&cmd)?.stdout.trim().to_owned())
+    }
+
+    /// Where `origin` points; `None` without a checkout or an `origin`.
+    pub fn origin(&self, cx: &Context) -> Result<Option<String>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        self.query(cx, &["remote", "get-url", "origin"])
+    }
+
+    /// Points `origin` at `url`, the same repository reached another way.
+    pub fn set_origin(&self, cx: &Context, url: &str) -> Result<()> {
+        self.read(cx, &["remote", "set-url", "origin", url])
+            .map(drop)
+    }
+
+    /// Where the checkout stands. With `fetch`, remote-tracking refs and
+    /// tags are brought up to date first; nothing else is touched either
+    /// way.
+    pub fn status(&self, cx: &Context, fetch: bool) -> Result<Status> {
+        if !self.exists() {
+            return Ok(Status::Missing);
+        }
+        let origin = self
+            .query(cx, &["remote", "get-url", "origin"])?
+            .unwrap_or_default();
+        if !same_repo(&origin, &self.url) {
+            return Ok(Status::WrongRemote { origin });
+        }
+        if fetch {
+            let fetch = self
+                .remote_git(cx)?
+                .cwd(&self.path)
+                .args(["fetch", "--quiet", "--tags", "origin"]);
+            fetch.output()?.check(&fetch)?;
+        }
+        let files = self.changed_files(cx)?;
+        let branch = self.query(cx, &["symbolic-ref", "--quiet", "--short", "HEAD"])?;
+
+        match &self.pin {
+            Some(Pin::Tag(tag)) => {
+                return self.at(cx, tag.as_str(), &format!("refs/tags/{tag}"), files)
+            }
+            Some(Pin::Commit(commit)) => {
+                return self.at(cx, commit.as_str(), commit.as_str(), files)
+            }
+            Some(Pin::Version(req)) => {
+                let here = self.read(cx, &["tag", "--points-at", "HEAD"])?;
+                if let Some(at) = here.lines().find(|tag| matches(req, tag)) {
+                    return Ok(Status::Pinned { at: at.to_owned() });
+                }
+                let tags = self.read(cx, &["tag", "--list"])?;
+                let want = newest(req, tags.lines()).ok_or_else(|| self.no_match(req))?;
+                return Ok(Status::OffPin {
+                    head: self.head(cx)?,
+                    want,
+                    files,
+                });
+            }
+            Some(Pin::Branch(want)) if branch.as_deref() != Some(want.as_str()) => {
+                return Ok(Status::OffPin {
+                    head: self.head(cx)?,
+                    want: want.to_string(),
+                    files,
+                });
+            }
+            Some(Pin::Branch(_)) | None => {}
+        }
+
+        let Some(branch) = branch else {
+            return Ok(Status::Untracked {
+                head: self.head(cx)?,
+            });
+        };
+        let upstream = [
+            "rev-parse",
+            "--abbrev-ref",
+            "--symbolic-full-name",
+            "@{upstream}",
+        ];
+        if self.query(cx, &upstream)?.is_none() {
+            return Ok(Status::Untracked { head: branch });
+        }
+        let counts = self.read(
+            cx,
+            &["rev-list", "--left-right", "--count", "HEAD...@{upstream}"],
+        )?;
+        let mut counts = counts
+            .split_whitespace()
+            .map(|n| n.parse::<u32>().unwrap_or(0));
+        let (ahead, behind) = (counts.next().unwrap_or(0), counts.next().unwrap_or(0));
+        Ok(match (files.is_empty(), ahead, behind) {
+            (false, ..) => Status::Dirty {
+                files,
+                ahead,
+                behind,
+            },
+            (true, 0, 0) => Status::UpToDate,
+            (true, 0, commits) => Status::Behind { commits },
+            (true, commits, 0) => Status::Ahead { commits },
+            (true, ahead, behind) => Status::Diverged { ahead, behind },
+        })
+    }
+
+    /// Tracked files with uncommitted changes.
+    fn changed_files(&self, cx: &Context) -> Result<Vec<String>> {
+        // Not trimmed: the first column of `XY path` may be a space.
+        let porcelain = self
+            .git(cx)
+            .args(["status", "--porcelain", "--untracked-files=no"]);
+        Ok(porcelain
+            .output()?
+            .check(&porcelain)?
+            .stdout
+            .lines()
+            .filter_map(|line| line.get(3..))
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// `v0.9.0`, `main`, or an abbreviated commit.
+    fn head(&self, cx: &Context) -> Result<String> {
+        self.read(cx, &["describe", "--tags", "--always"])
+    }
+
+    /// Whether HEAD is at `rev`, which the pin calls `name`.
+    fn at(&self, cx: &Context, name: &str, rev: &str, files: Vec<String>) -> Result<Status> {
+        let Some(want) = self.query(
+            cx,
+            &[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{rev}^{{commit}}"),
+            ],
+        )?
+        else {
+            return Err(self.needs_attention(format!("`{name}` is not in {}", self.url)));
+        };
+        if self.read(cx, &["rev-parse", "HEAD"])? == want {
+            return Ok(Status::Pinned { at: name.into() });
+        }
+        Ok(Status::OffPin {
+            head: self.head(cx)?,
+            want: name.into(),
+            files,
+        })
+    }
+
+    /// Clones a missing checkout, checks out the pinned ref, or
+    /// fast-forwards a clean checkout that is behind. With `stash`,
+    /// uncommitted changes are stashed while that happens and restored
+    /// after. Fails with [`Error::Repo`] when the checkout needs someone to
+    /// look at it.
+    pub fn sync(&self, cx: &Context, stash: bool) -> Result<Synced> {
+        let status = self.status(cx, true)?;
+        if let Some(problem) = status.problem(&self.url, stash) {
+            return Err(self.needs_attention(problem));
+        }
+        match status {
+            Status::Missing => {
+                self.clone(cx)?;
+                Ok(Synced::Cloned)
+            }
+            Status::OffPin { want, files, .. } => {
+                self.stashed(cx, !files.is_empty(), |cx| self.switch(cx, &want))?;
+                Ok(Synced::Switched {
+                    to: want,
+                    stashed: !files.is_empty(),
+                })
+            }
+            Status::Behind { commits } => {
+                self.fast_forward(cx)?;
+                Ok(Synced::FastForwarded {
+                    commits,
+                    stashed: false,
+                })
+            }
+            Status::Dirty { behind, .. } if behind > 0 => {
+                self.stashed(cx, true, |cx| self.fast_forward(cx))?;
+                Ok(Synced::FastForwarded {
+                    commits: behind,
+                    stashed: true,
+                })
+            }
+            _ => Ok(Synced::Unchanged),
+        }
+    }
+
+    /// Checks out `tag` of a checkout pinned to a version, for
+    /// `trk repos upgrade`. The pin has to allow it.
+    pub fn upgrade_to(&self, cx: &Context, tag: &str, stash: bool) -> Result<Synced> {
+        if let Some(Pin::Version(req)) = &self.pin {
+            if !matches(req, tag) {
+                return Err(self.needs_attention(format!("`{tag}` is outside version {req}")));
+            }
+        }
+        let status = self.status(cx, true)?;
+        if let Some(problem) = status.problem(&self.url, stash) {
+            return Err(self.needs_attention(problem));
+        }
+        match status {
+            Status::Missing => {
+                self.clone(cx)?;
+                self.switch(cx, tag)?;
+                Ok(Synced::Cloned)
+            }
+            Status::Pinned { at } if at == tag => Ok(Synced::Unchanged),
+            Status::Pinned { .. } | Status::OffPin { .. } => {
+                let files = self.changed_files(cx)?;
+                if !files.is_empty() && !stash {
+                    return Err(self.needs_attention(dirty(&files, tag)));
+                }
+                self.stashed(cx, !files.is_empty(), |cx| self.switch(cx, tag))?;
+                Ok(Synced::Switched {
+                    to: tag.into(),
+                    stashed: !files.is_empty(),
+                })
+            }
+            _ => Ok(Synced::Unchanged),
+        }
+    }
+
+    /// Newer tags on the remote than the one a tag or version pin is at;
+    /// `None` for checkouts that follow a branch or sit at a commit.
+    pub fn upgrades(&self, cx: &Context) -> Result<Option<Upgrades>> {
+        let current = match &self.pin {
+            Some(Pin::Tag(tag)) => tag.to_string(),
+            Some(Pin::Version(req)) => match self.status(cx, false)? {
+                Status::Pinned { at } => at,
+                _ => {
+                    let tags = self.remote_tags(cx)?;
+                    newest(req, tags.iter().map(String::as_str))
+                        .ok_or_else(|| self.no_match(req))?
+                }
+            },
+            _ => return Ok(None),
+        };
+        let since = tag_version(&current);
+        let mut newer: Vec<(Version, String)> = self
+            .remote_tags(cx)?
+            .into_iter()
+            .filter_map(|tag| Some((tag_version(&tag)?, tag)))
+            .filter(|(version, _)| version.pre.is_empty())
+            .filter(|(version, _)| since.as_ref().is_none_or(|since| version > since))
+            .collect();
+        newer.sort();
+        let in_range = match &self.pin {
+            Some(Pin::Version(req)) => newer
+                .iter()
+                .rev()
+                .find(|(version, _)| req.matches(version))
+                .map(|(_, tag)| tag.clone()),
+            _ => None,
+        };
+        Ok(Some(Upgrades {
+            current,
+            newer: newer.into_iter().map(|(_, tag)| tag).collect(),
+            in_range,
+        }))
+    }
+
+    /// The remote's tags, from `git ls-remote`, which works before the
+    /// checkout is cloned.
+    pub fn remote_tags(&self, cx: &Context) -> Result<Vec<String>> {
+        let ls_remote = self
+            .remote_git(cx)?
+            .args(["ls-remote", "--tags", "--refs"])
+            .arg(self.remote_url(cx)?);
+        Ok(ls_remote
+            .output()?
+            .check(&ls_remote)?
+            .stdout
+            .lines()
+            .filter_map(|line| line.split_once("refs/tags/"))
+            .map(|(_, tag)| tag.trim().to_owned())
+            .collect())
+    }
+
+    fn fast_forward(&self, cx: &Context) -> Result<()> {
+        self.git(cx)
+            .args(["merge", "--ff-only", "--quiet", "@{upstream}"])
+            .status()
+    }
+
+    /// Checks out a branch, or a tag or commit as a detached HEAD.
+    fn switch(&self, cx: &Context, want: &str) -> Result<()> {
+        let checkout = self.git(cx).args(["checkout", "--quiet"]);
+        match &self.pin {
+            Some(Pin::Branch(_)) => checkout.arg(want).status(),
+            _ => checkout.args(["--detach", want]).status(),
+        }
+    }
+
+    /// Runs `change` with uncommitted changes stashed, if `stash`, and
+    /// restores them after, whether or not `change` worked.
+    fn stashed(
+        &self,
+        cx: &Context,
+        stash: bool,
+        change: impl FnOnce(&Context) -> Result<()>,
+    ) -> Result<()> {
+        if !stash {
+            return change(cx);
+        }
+        self.git(cx)
+            .args(["stash", "push", "--quiet", "-m", "trk: before sync"])
+            .status()?;
+        let changed = change(cx);
+        let pop = self.git(cx).args(["stash", "pop", "--quiet"]);
+        let popped = pop.output().is_ok_and(|out| out.success());
+        match changed {
+            Err(err) if !popped => Err(self.needs_attention(format!(
+                "{err}; your uncommitted changes are in stash@{{0}} (\"trk: before sync\"), \
+                 `git stash pop` them once that is fixed"
+            ))),
+            Err(err) => Err(err),
+            Ok(()) if !popped => Err(self.needs_attention(
+                "the new commits conflict with your uncommitted changes; \
+                 resolve the conflicts, then `git stash drop`"
+                    .into(),
+            )),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// `~/.trk` exists before it is cloned when it holds nothing but
+    /// trk's own `state/`; that is cloned next to it, `state/` moved into
+    /// the clone and the clone moved into place, which plain `git clone`
+    /// refuses to do. Any other directory with something in it is left
+    /// alone.
+    fn clone(&self, cx: &Context) -> Result<()> {
+        let occupied = fs::read_dir(&self.path).is_ok_and(|mut dir| dir.next().is_some());
+        if occupied && !(self.path == cx.trk_dir && only_state(&self.path)?) {
+            return Err(Error::Path {
+                path: self.path.clone(),
+                message: format!(
+                    "already exists and is not a git checkout; \
+                     move it aside so {} can be cloned there",
+                    self.url
+                ),
+            });
+        }
+        let branch = match &self.pin {
+            Some(Pin::Branch(name) | Pin::Tag(name)) => Some(name.to_string()),
+            Some(Pin::Version(req)) => {
+                let tags = self.remote_tags(cx)?;
+                Some(
+                    newest(req, tags.iter().map(String::as_str))
+                        .ok_or_else(|| self.no_match(req))?,
+                )
+            }
+            Some(Pin::Commit(_)) | None => None,
+        };
+        let target = if occupied {
+            let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+            self.path.with_file_name(format!(".{name}.clone"))
+        } else {
+            self.path.clone()
+        };
+
+        let mut clone = self
+            .remote_git(cx)?
+            .arg("clone")
+            .arg(self.remote_url(cx)?)
+            .arg(target.to_string_lossy());
+        if let Some(branch) = &branch {
+            clone = clone.args(["--branch", branch]);
+        }
+        clone.status()?;
+
+        if occupied {
+            fs::rename(self.path.join("state"), target.join("state"))?;
+            fs::remove_dir(&self.path)?;
+            fs::rename(&target, &self.path)?;
+        }
+        if let Some(Pin::Commit(commit)) = &self.pin {
+            self.switch(cx, commit.as_str())?;
+        }
+        Ok(())
+    }
+
+    fn no_match(&self, req: &VersionReq) -> Error {
+        self.needs_attention(format!("no tag in {} matches version {req}", self.url))
+    }
+
+    /// `git` for a command that reaches the remote at [`Self::remote_url`],
+    /// with the credentials the URL names by reference resolved and sent
+    /// as an `Authorization` header.
+    fn remote_git(&self, cx: &Context) -> Result<Cmd> {
+        let git = cx.cmd("git");
+        let Some((_, userinfo)) = self.secret_credentials() else {
+            return Ok(git);
+        };
+        let mut credentials = secret::expand(cx, userinfo)?;
+        if !credentials.contains(':') {
+            credentials.push(':');
+        }
+        let mut encoded = base64::encode(credentials.as_bytes(), base64::STANDARD);
+        while !encoded.len().is_multiple_of(4) {
+            encoded.push('=');
+        }
+        Ok(git.args([
+            "-c".to_owned(),
+            format!("http.extraHeader=Authorization: Basic {encoded}"),
+        ]))
+    }
+
+    /// The URL git is given, less credentials that are secret references,
+    /// which [`Self::remote_git`] sends instead. Any other reference is
+    /// filled in.
+    fn remote_url(&self, cx: &Context) -> Result<String> {
+        let mut url = self.url.clone();
+        if let Some((at, userinfo)) = self.secret_credentials() {
+            url.replace_range(at..at + userinfo.len() + 1, "");
+        }
+        secret::expand(cx, &url)
+    }
+
+    /// Where the `user:password@` of an http(s) URL starts, and the
+    /// `user:password`, when it holds a secret reference. References have
+    /// slashes of their own, so the credentials end at the first `@`.
+    fn secret_credentials(&self) -> Option<(usize, &str)> {
+        let (scheme, rest) = self.url.split_once("://")?;
+        if !matches!(scheme, "http" | "https") {
+            return None;
+        }
+        let (userinfo, _) = rest.split_once('@')?;
+        let at = scheme.len() + 3;
+        (!secret::references(userinfo).is_empty()).then_some((at, userinfo))
+    }
+
+    fn needs_attention(&self, problem: String) -> Error {
+        Error::Repo {
+            path: self.path.clone(),
+            problem,
+        }
+    }
+}
+
+impl Status {
+    /// Why a sync would have to stop and leave the checkout to a human,
+    /// if it would. `url` is where `origin` should point.
+    pub fn problem(&self, url: &str, stash: bool) -> Option<String> {
+        match *self {
+            Status::WrongRemote { ref origin } => Some(format!(
+                "origin is `{origin}`, not `{url}`; fix it with `git remote set-url origin {url}`"
+            )),
+            Status::OffPin {
+                ref want,
+                ref files,
+                ..
+            } if !files.is_empty() && !stash => Some(dirty(files, want)),
+            Status::Diverged { ahead, behind } | Status::Dirty { ahead, behind, .. }
+                if ahead > 0 && behind > 0 =>
+            {
+                Some(format!(
+                    "{ahead} local commit{} and {behind} upstream commit{} have diverged; \
+                     rebase or merge by hand",
+                    plural(ahead),
+                    plural(behind)
+                ))
+            }
+            Status::Untracked { ref head } => Some(format!(
+                "`{head}` follows no upstream branch; check out a branch that tracks \
+                 origin, or pin a `tag` or `commit` for it in trk.toml"
+            )),
+            Status::Dirty {
+                ref files, behind, ..
+            } if behind > 0 && !stash => Some(format!(
+                "{} uncommitted change{} and {behind} new upstream commit{}; \
+                 commit or stash them, or run again with --stash",
+                files.len(),
+                plural(files.len() as u32),
+                plural(behind)
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `dir` holds nothing but a `state/` directory.
+fn only_state(dir: &Path) -> Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name() != "state" || !entry.file_type()?.is_dir() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn dirty(files: &[String], want: &str) -> String {
+    format!(
+        "{} uncommitted change{} in the way of checking out `{want}`; \
+         commit or stash them, or run again with --stash",
+        files.len(),
+        plural(files.len() as u32)
+    )
+}
+
+/// `up to date`, `2 commits behind`, `at v0.9.0 (pinned)`, ...
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Missing => f.write_str("not cloned"),
+            Status::WrongRemote { origin } => write!(f, "origin is {origin}"),
+            Status::Pinned { at } => write!(f, "at {at} (pinned)"),
+            Status::OffPin { head, want, .. } => write!(f, "at {head}, pinned to {want}"),
+            Status::Untracked { head } => write!(f, "at {head}, not following a branch"),
+            Status::Dirty {
+                files,
+                ahead,
+                behind,
+            } => {
+                write!(
+                    f,
+                    "{} uncommitted change{}",
+                    files.len(),
+                    plural(files.len() as u32)
+                )?;
+                if *ahead > 0 {
+                    write!(f, ", {ahead} ahead")?;
+                }
+                if *behind > 0 {
+                    write!(f, ", {behind} behind")?;
+                }
+                Ok(())
+            }
+            Status::UpToDate => f.write_str("up to date"),
+            Status::Behind { commits } => write!(f, "{commits} commit{} behind", plural(*commits)),
+            Status::Ahead { commits } => write!(f, "{commits} commit{} ahead", plural(*commits)),
+            Status::Diverged { ahead, behind } => {
+                write!(f, "diverged: {ahead} ahead, {behind} behind")
+            }
+        }
+    }
+}
+
+/// The version a tag names: `v0.9.0` and `0.9.0` are 0.9.0.
+pub fn tag_version(tag: &str) -> Option<Version> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
+
+fn matches(req: &VersionReq, tag: &str) -> bool {
+    tag_version(tag).is_some_and(|version| req.matches(&version))
+}
+
+/// The tag with the highest version `req` allows.
+fn newest<'a>(req: &VersionReq, tags: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    tags.into_iter()
+        .filter_map(|tag| Some((tag_version(tag)?, tag)))
+        .filter(|(version, _)| req.matches(version))
+        .max()
+        .map(|(_, tag)| tag.to_owned())
+}
+
+/// Whether two remote URLs name the same repository, so that
+/// `https://github.com/trkw/trk` matches `git@github.com:trkw/trk.git`.
+pub fn same_repo(a: &str, b: &str) -> bool {
+    repo_key(a) == repo_key(b)
+}
+
+/// `host/owner/repo` for URLs and scp-style remotes; local paths as they
+/// are, less a trailing `/` or `.git`. Credentials, and secret references
+/// standing in for them, are left out.
+fn repo_key(url: &str) -> String {
+    let mut url = url.trim().to_owned();
+    for (range, _) in secret::references(&url).into_iter().rev() {
+        url.replace_range(range, "");
+    }
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest.to_owned(),
+        None => match url.split_once(':') {
+            Some((host, path)) if !host.contains('/') => format!("{host}/{path}"),
+            _ => return url.to_owned(),
+        },
+    };
+    let (host, path) = rest.split_once('/').unwrap_or((&rest, ""));
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or(host);
+    format!("{}/{path}", host.to_ascii_lowercase())
+}
+
+pub(crate) fn plural(n: u32) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
@@ -0,0 +1,133 @@
+//! What each step of the last run did, kept in `~/.trk/state` so a run
+//! that stopped half way can be resumed.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::report::Report;
+use crate::steps::Outcome;
+
+/// The journal of one subcommand (`bootstrap` or `update`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Per step name, the most recent time it ran.
+    pub steps: BTreeMap<String, Entry>,
+    /// What the run decided along the way.
+    #[serde(default)]
+    pub report: Report,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The step's [`inputs`](crate::steps::Step::inputs) when it ran.
+    pub inputs: String,
+    /// Seconds since the Unix epoch.
+    pub finished_at: u64,
+    #[serde(flatten)]
+    pub result: Recorded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recorded {
+    Outcome(Outcome),
+    Error(String),
+}
+
+impl Journal {
+    /// Where the journal for `command` lives under `trk_dir`.
+    pub fn path(trk_dir: &Path, command: &str) -> PathBuf {
+        trk_dir.join("state").join(format!("{command}.json"))
+    }
+
+    /// Loads the journal at `path`, or an empty one if there is none yet.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut journal = match fs::read_to_string(&path) {
+            Ok(source) => serde_json::from_str(&source).map_err(|err| Error::Path {
+                path: path.clone(),
+                message: format!("corrupt journal ({err}); delete it to start over"),
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Journal::default(),
+            Err(err) => return Err(path_error(&path, err)),
+        };
+        journal.path = path;
+        Ok(journal)
+    }
+
+    /// Forgets every step, for a run that starts from the beginning.
+    pub fn clear(&mut self) {
+        self.steps.clear();
+        self.report = Report::default();
+    }
+
+    /// Whether `step` did its work in an earlier run with the same inputs.
+    /// Skipped and failed steps are not finished.
+    pub fn finished(&self, step: &str, inputs: &str) -> bool {
+        self.steps.get(step).is_some_and(|entry| {
+            entry.inputs == inputs
+                && matches!(
+                    entry.result,
+                    Recorded::Outcome(Outcome::Ok | Outcome::Changed)
+                )
+        })
+    }
+
+    /// Records how `step` ended and writes the journal out straight away,
+    /// so progress survives a crash or Ctrl-C.
+    pub fn record(&mut self, step: String, inputs: String, result: Recorded) -> Result<()> {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.steps.insert(
+            step,
+            Entry {
+                inputs,
+                finished_at,
+                result,
+            },
+        );
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir).map_err(|err| path_error(dir, err))?;
+        let json = serde_json::to_string_pretty(self).expect("journal serializes");
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|err| path_error(&tmp, err))?;
+        fs::rename(&tmp, &self.path).map_err(|err| path_error(&self.path, err))
+    }
+}
+
+/// SHA-256 of a file's contents, for step inputs that depend on a file.
+/// A missing file has the digest `missing`.
+pub fn file_digest(path: &Path) -> String {
+    match fs::read(path) {
+        Ok(bytes) => {
+            Sha256::digest(&bytes)
+                .iter()
+                .fold(String::with_capacity(64), |mut hex, byte| {
+                    let _ = write!(hex, "{byte:02x}");
+                    hex
+                })
+        }
+        Err(_) => "missing".into(),
+    }
+}
+
+fn path_error(path: &Path, err: io::Error) -> Error {
+    Error::Path {
+        path: path.to_owned(),
+        message: err.to_string(),
+    }
+}
@@ -0,0 +1,32 @@
+//! trk sets up a Mac from this repository (`~/.trk`) and an optional
+//! dotfiles repository (`~/dotfiles`).
+//!
+//! Everything the old `bin/bootstrap` and `bin/update` scripts did is
+//! expressed as [`steps::Step`]s, so both subcommands share one
+//! implementation of each action.
+
+mod base64;
+pub mod brewfile;
+pub mod cli;
+pub mod context;
+pub mod error;
+pub mod exec;
+pub mod facts;
+pub mod git;
+pub mod journal;
+pub mod manifest;
+pub mod plan;
+pub mod playbook;
+pub mod redact;
+pub mod report;
+pub mod runtime;
+pub mod secret;
+pub mod ssh;
+pub mod steps;
+pub mod sudo;
+pub mod template;
+
+pub use context::Context;
+pub use error::{Error, Result};
+pub use facts::Facts;
+pub use manifest::Manifest;
@@ -0,0 +1,14 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+
+fn main() -> ExitCode {
+    let cli = trk::cli::Cli::parse();
+    match trk::cli::run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("trk: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,1037 @@
+//! `trk.toml`: what a machine should have installed.
+//!
+//! The baseline lives in `~/.trk/trk.toml` (a copy is built into the binary
+//! for the very first bootstrap, before `~/.trk` is cloned). A dotfiles
+//! repository can extend or override it with its own `osx/trk.toml`.
+//!
+//! Values are checked while they are deserialized, so a bad entry is
+//! reported by toml with the line and column of that entry.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use semver::VersionReq;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::runtime::Alias;
+
+/// The manifest shipped with this version of trk.
+pub const DEFAULT: &str = include_str!("../trk.toml");
+
+/// Name of the manifest file, in `~/.trk` and in the dotfiles `osx/` dir.
+pub const FILE_NAME: &str = "trk.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// Installs and switches the versions in `runtimes`; asdf unless set.
+    pub version_manager: Option<Manager>,
+    pub homebrew: Homebrew,
+    /// Language runtimes, keyed by plugin (or mise tool) name.
+    pub runtimes: BTreeMap<String, Runtime>,
+    /// Git checkouts kept up to date, keyed by a short name.
+    pub repos: BTreeMap<String, Repo>,
+    /// Binaries installed from GitHub releases, keyed by binary name.
+    pub releases: BTreeMap<String, Release>,
+    pub dotfiles: Dotfiles,
+    pub secrets: Secrets,
+    /// `~/.ssh/config` and `known_hosts`, managed only when the table is
+    /// there.
+    pub ssh: Option<Ssh>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Homebrew {
+    pub taps: Vec<Tap>,
+    pub formulae: Vec<Package>,
+    pub casks: Vec<Package>,
+    /// What to do about installed taps Homebrew has retired; warn unless
+    /// set.
+    pub deprecated_taps: Option<DeprecatedTaps>,
+}
+
+/// A Homebrew tap, written either as `"user/repo"` or as
+/// `{ name = "user/repo", state = "absent" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tap {
+    pub name: String,
+    pub state: State,
+}
+
+/// Whether a declared item should be on the machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    #[default]
+    Present,
+    Absent,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeprecatedTaps {
+    /// Point them out on every run.
+    #[default]
+    Warn,
+    /// Untap them unless the manifest declares them present.
+    Untap,
+}
+
+/// A formula or cask, written either as `"name"` or as
+/// `{ name = "name", latest = true }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    /// Upgrade the package on every run instead of only installing it.
+    pub latest: bool,
+}
+
+/// The tool behind [`Manifest::runtimes`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Manager {
+    /// asdf: the `~/.asdf` checkout, or the Go rewrite from Homebrew.
+    #[default]
+    Asdf,
+    Mise,
+}
+
+impl fmt::Display for Manager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Manager::Asdf => "asdf",
+            Manager::Mise => "mise",
+        })
+    }
+}
+
+/// What runs `ansible/mac.yml` and the dotfiles playbook.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybookRunner {
+    /// trk's runner for the task subset in [`crate::playbook`].
+    #[default]
+    Trk,
+    /// `ansible-playbook`, for playbooks that need more; install ansible
+    /// from `[homebrew]`.
+    Ansible,
+}
+
+impl fmt::Display for PlaybookRunner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PlaybookRunner::Trk => "trk",
+            PlaybookRunner::Ansible => "ansible",
+        })
+    }
+}
+
+/// Versions of one runtime, written as `"20.11.1"`, as a list, or as
+/// `{ versions = [...], plugin = "<git url>" }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Runtime {
+    /// Every version to install, exact or an alias such as `lts`, `20` or
+    /// `~20.11`; the first is the global default.
+    pub versions: Vec<String>,
+    /// Plugin repository, for plugins the asdf plugin index does not list.
+    pub plugin: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RepoTable")]
+pub struct Repo {
+    pub url: Identifier,
+    pub path: Identifier,
+    /// The ref to keep checked out; the remote's default branch if `None`.
+    pub pin: Option<Pin>,
+    /// Sync the checkout on later runs.
+    pub pull: bool,
+}
+
+/// A repo as written, with at most one of `branch`, `tag`, `commit` and
+/// `version`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RepoTable {
+    url: Identifier,
+    path: Identifier,
+    branch: Option<Identifier>,
+    tag: Option<Identifier>,
+    commit: Option<Commit>,
+    version: Option<Range>,
+    #[serde(default = "yes")]
+    pull: bool,
+}
+
+/// Which ref of a repo trk checks out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    /// Follow the branch, fast-forwarding on every run.
+    Branch(Identifier),
+    /// Stay at the tag until `trk repos upgrade` moves it.
+    Tag(Identifier),
+    /// Stay at the commit.
+    Commit(Identifier),
+    /// Stay at the newest tag matching the requirement (`0.14`,
+    /// `>=0.10, <0.16`) when first checked out; `trk repos upgrade` moves
+    /// to a newer one.
+    Version(VersionReq),
+}
+
+/// A commit id, abbreviated or full.
+struct Commit(Identifier);
+
+/// A semver requirement on a repo's tags.
+struct Range(VersionReq);
+
+/// A prebuilt binary from a GitHub release.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Release {
+    /// `owner/repo` on GitHub.
+    pub repo: Identifier,
+    /// Release tag, or `latest` for whichever release is newest on each
+    /// run.
+    #[serde(default = "latest")]
+    pub version: Identifier,
+    /// Asset file name, e.g. `{name}_{version}_{os}_{arch}.tar.gz`.
+    pub asset: Template,
+    /// File to install from an archive asset; defaults to the release name.
+    pub bin: Option<Identifier>,
+    /// Published checksum file in the same release, e.g. `checksums.txt`.
+    pub checksums: Option<Template>,
+    /// Pinned SHA-256 per asset file name; takes precedence over
+    /// `checksums`.
+    #[serde(default)]
+    pub sha256: BTreeMap<String, Identifier>,
+    /// What the release calls each OS (`macos`, `linux`), if not `darwin`
+    /// and `linux`.
+    #[serde(default)]
+    pub os: BTreeMap<String, Identifier>,
+    /// What the release calls each architecture (`arm64`, `x86_64`), if
+    /// not `arm64` and `amd64`.
+    #[serde(default)]
+    pub arch: BTreeMap<String, Identifier>,
+}
+
+/// A string with `{placeholder}`s, see [`Template::PLACEHOLDERS`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(String);
+
+/// The user's own dotfiles repository and the hooks trk runs from it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dotfiles {
+    /// Clone URL; `$DOTFILES_URL` takes precedence.
+    pub url: Option<Identifier>,
+    pub path: Option<Identifier>,
+    /// Ansible task list included after trk's own steps, relative to `path`.
+    pub playbook: Option<Identifier>,
+    /// What runs the playbook; trk's own runner unless set.
+    pub playbook_runner: Option<PlaybookRunner>,
+    /// Brewfile installed with `brew bundle`, relative to `path`.
+    pub brewfile: Option<Identifier>,
+    /// asdf `.tool-versions` whose runtimes are installed too, relative to
+    /// `path`; `[runtimes]` wins for a plugin listed in both.
+    pub tool_versions: Option<Identifier>,
+}
+
+/// How the `op` provider signs in, and where the `pass` and `age`
+/// providers keep their secrets. This table may not itself use secret
+/// references.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Secrets {
+    /// The 1Password account `op signin` signs in to, e.g.
+    /// `my.1password.com`; op's default account unless set.
+    pub op_account: Option<Identifier>,
+    /// The password store; `~/.password-store` unless set.
+    pub pass_dir: Option<Identifier>,
+    /// Directory of age-encrypted files, `<vault>.age` for each vault;
+    /// `~/.config/trk/secrets` unless set.
+    pub age_dir: Option<Identifier>,
+    /// The age identity that decrypts those files and whose recipient they
+    /// are encrypted to; `~/.config/trk/age.txt` unless set.
+    pub age_identity: Option<Identifier>,
+}
+
+/// The hosts git reaches over ssh: a block of `~/.ssh/config` and their
+/// host keys pinned in `~/.ssh/known_hosts`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ssh {
+    /// What offers the keys; ssh-agent and the macOS keychain unless set.
+    pub agent: Option<SshAgent>,
+    /// Switch the `origin` of managed repos on these hosts from https to
+    /// ssh once the host's key is in `~/.ssh`.
+    pub rewrite_remotes: Option<bool>,
+    /// Keyed by host name. github.com is always managed, pinned to
+    /// GitHub's published keys unless listed with fingerprints of its own.
+    pub hosts: BTreeMap<String, SshHost>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SshAgent {
+    /// ssh-agent, which keys are added to on first use, with their
+    /// passphrases kept in the keychain on macOS.
+    #[default]
+    Keychain,
+    /// The 1Password SSH agent, which holds the keys itself.
+    #[serde(rename = "1password")]
+    OnePassword,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SshHost {
+    /// The key in `~/.ssh` used for the host, as `trk ssh get --name`
+    /// writes it.
+    pub identity: Option<Identifier>,
+    /// `git` unless set.
+    pub user: Option<Identifier>,
+    /// The host keys to trust, as `ssh-keygen -l` prints them.
+    pub fingerprints: Vec<Fingerprint>,
+}
+
+/// A host key's SHA-256 fingerprint, `SHA256:` and unpadded base64.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint(String);
+
+/// A non-empty string without surrounding whitespace.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Identifier(String);
+
+impl Manifest {
+    /// Parses manifest source; `path` is only used in error messages.
+    pub fn parse(source: &str, path: &Path) -> Result<Self> {
+        toml::from_str(source).map_err(|err| Error::Manifest {
+            path: path.to_owned(),
+            message: err.to_string().trim_end().to_owned(),
+        })
+    }
+
+    /// Reads the manifest at `path`, or `None` when there is no file.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(source) => Self::parse(&source, path).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::Path {
+                path: path.to_owned(),
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    /// The `~/.trk` manifest (or the built-in one), extended by the
+    /// dotfiles manifest when there is one.
+    ///
+    /// Secret references in its values, such as
+    /// `op://Private/github/token`, are left as written; the steps that use
+    /// a value resolve them with [`secret::expand`](crate::secret::expand).
+    pub fn load(trk_dir: &Path, expand: impl Fn(&str) -> PathBuf) -> Result<Self> {
+        let mut manifest = match Self::read(&trk_dir.join(FILE_NAME))? {
+            Some(manifest) => manifest,
+            None => Self::parse(DEFAULT, Path::new("<built-in trk.toml>"))?,
+        };
+        let dotfiles_dir = expand(manifest.dotfiles.path());
+        if let Some(overlay) = Self::read(&dotfiles_dir.join("osx").join(FILE_NAME))? {
+            manifest.merge(overlay);
+        }
+        Ok(manifest)
+    }
+
+    /// Applies `other` on top of `self`. List entries are added, replacing
+    /// an entry with the same name; repos and globals replace the entry
+    /// with the same key; dotfiles settings replace the ones they set.
+    pub fn merge(&mut self, other: Manifest) {
+        merge_by(&mut self.homebrew.taps, other.homebrew.taps, |t| {
+            t.name.clone()
+        });
+        self.homebrew.deprecated_taps = other
+            .homebrew
+            .deprecated_taps
+            .or(self.homebrew.deprecated_taps);
+        merge_by(&mut self.homebrew.formulae, other.homebrew.formulae, |p| {
+            p.name.clone()
+        });
+        merge_by(&mut self.homebrew.casks, other.homebrew.casks, |p| {
+            p.name.clone()
+        });
+        self.version_manager = other.version_manager.or(self.version_manager);
+        self.runtimes.extend(other.runtimes);
+        self.repos.extend(other.repos);
+        self.releases.extend(other.releases);
+
+        let dotfiles = &mut self.dotfiles;
+        dotfiles.url = other.dotfiles.url.or(dotfiles.url.take());
+        dotfiles.path = other.dotfiles.path.or(dotfiles.path.take());
+        dotfiles.playbook = other.dotfiles.playbook.or(dotfiles.playbook.take());
+        dotfiles.playbook_runner = other
+            .dotfiles
+            .playbook_runner
+            .or(dotfiles.playbook_runner.take());
+        dotfiles.brewfile = other.dotfiles.brewfile.or(dotfiles.brewfile.take());
+        dotfiles.tool_versions = other
+            .dotfiles
+            .tool_versions
+            .or(dotfiles.tool_versions.take());
+
+        let secrets = &mut self.secrets;
+        secrets.op_account = other.secrets.op_account.or(secrets.op_account.take());
+        secrets.pass_dir = other.secrets.pass_dir.or(secrets.pass_dir.take());
+        secrets.age_dir = other.secrets.age_dir.or(secrets.age_dir.take());
+        secrets.age_identity = other.secrets.age_identity.or(secrets.age_identity.take());
+
+        if let Some(other) = other.ssh {
+            let ssh = self.ssh.get_or_insert_with(Ssh::default);
+            ssh.agent = other.agent.or(ssh.agent);
+            ssh.rewrite_remotes = other.rewrite_remotes.or(ssh.rewrite_remotes);
+            ssh.hosts.extend(other.hosts);
+        }
+    }
+}
+
+impl TryFrom<RepoTable> for Repo {
+    type Error = String;
+
+    fn try_from(table: RepoTable) -> Result<Self, String> {
+        let pins = [
+            table.branch.map(Pin::Branch),
+            table.tag.map(Pin::Tag),
+            table.commit.map(|c| Pin::Commit(c.0)),
+            table.version.map(|r| Pin::Version(r.0)),
+        ];
+        let mut pins = pins.into_iter().flatten();
+        let pin = pins.next();
+        if pins.next().is_some() {
+            return Err("set only one of `branch`, `tag`, `commit` and `version`".into());
+        }
+        Ok(Repo {
+            url: table.url,
+            path: table.path,
+            pin,
+            pull: table.pull,
+        })
+    }
+}
+
+/// `branch main`, `tag v0.9.0`, `version ^0.14`.
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pin::Branch(branch) => write!(f, "branch {branch}"),
+            Pin::Tag(tag) => write!(f, "tag {tag}"),
+            Pin::Commit(commit) => write!(f, "commit {commit}"),
+            Pin::Version(req) => write!(f, "version {req}"),
+        }
+    }
+}
+
+/// Runtimes from a `.tool-versions` file: `nodejs 20.11.1 18.19.0` per
+/// line, `#` comments. Errors name the line.
+pub fn parse_tool_versions(source: &str) -> Result<BTreeMap<String, Runtime>, (usize, String)> {
+    let mut runtimes = BTreeMap::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let Some(plugin) = words.next() else {
+            continue;
+        };
+        let versions: Vec<String> = words.map(str::to_owned).collect();
+        if versions.is_empty() {
+            return Err((index + 1, format!("`{plugin}` has no version")));
+        }
+        runtimes.insert(
+            plugin.to_owned(),
+            Runtime {
+                versions,
+                plugin: None,
+            },
+        );
+    }
+    Ok(runtimes)
+}
+
+/// Moves the `tag` of repo `name` to `tag` and returns the file changed.
+///
+/// The dotfiles manifest is edited when it declares the repo. A repo only
+/// `~/.trk/trk.toml` declares is overridden in the dotfiles manifest
+/// instead, when there is a dotfiles checkout, so that `~/.trk` stays
+/// clean for fast-forwards. Only the one value is rewritten; comments and
+/// layout stay as they are.
+pub fn pin_tag(
+    trk_dir: &Path,
+    dotfiles_dir: &Path,
+    name: &str,
+    repo: &Repo,
+    tag: &str,
+) -> Result<PathBuf> {
+    let overlay = dotfiles_dir.join("osx").join(FILE_NAME);
+    let baseline = trk_dir.join(FILE_NAME);
+    let read = |path: &Path| match fs::read_to_string(path) {
+        Ok(source) => Ok(Some(source)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::Path {
+            path: path.to_owned(),
+            message: err.to_string(),
+        }),
+    };
+    let write = |path: &Path, source: String| -> Result<PathBuf> {
+        Manifest::parse(&source, path)?;
+        fs::write(path, source).map_err(|err| Error::Path {
+            path: path.to_owned(),
+            message: err.to_string(),
+        })?;
+        Ok(path.to_owned())
+    };
+
+    let overlay_source = read(&overlay)?;
+    if let Some(source) = overlay_source
+        .as_deref()
+        .and_then(|s| replace_tag(s, name, tag))
+    {
+        return write(&overlay, source);
+    }
+    if dotfiles_dir.join(".git").exists() {
+        let source = override_tag(&overlay_source.unwrap_or_default(), name, repo, tag).map_err(
+            |message| Error::Manifest {
+                path: overlay.clone(),
+                message,
+            },
+        )?;
+        fs::create_dir_all(dotfiles_dir.join("osx"))?;
+        return write(&overlay, source);
+    }
+    match read(&baseline)?.and_then(|s| replace_tag(&s, name, tag)) {
+        Some(source) => write(&baseline, source),
+        None => Err(Error::Manifest {
+            path: baseline,
+            message: format!("no `tag` under [repos.{name}] to move"),
+        }),
+    }
+}
+
+/// `source` with the `tag` of repo `name` set to `tag`, or `None` when the
+/// repo has no `tag`. The repo may be a `[repos.<name>]` table or an
+/// inline table under `[repos]`; comments and layout stay as they are.
+fn replace_tag(source: &str, name: &str, tag: &str) -> Option<String> {
+    let mut doc: toml_edit::DocumentMut = source.parse().ok()?;
+    let value = doc
+        .get_mut("repos")?
+        .get_mut(name)?
+        .as_table_like_mut()?
+        .get_mut("tag")?
+        .as_value_mut()?;
+    let decor = value.decor().clone();
+    *value = tag.into();
+    *value.decor_mut() = decor;
+    Some(doc.to_string())
+}
+
+/// `source` with repo `name` pinned to `tag`: a repo it already declares
+/// gets the `tag` in place of any other pin, and otherwise `repo` is
+/// added under `repos`, as a table or in the inline table `repos` is.
+fn override_tag(source: &str, name: &str, repo: &Repo, tag: &str) -> Result<String, String> {
+    use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+
+    let mut doc: DocumentMut = source.parse().map_err(|err| format!("{err}"))?;
+    let first = doc.is_empty();
+    let repos = doc.entry("repos").or_insert_with(|| {
+        let mut repos = Table::new();
+        repos.set_implicit(true);
+        Item::Table(repos)
+    });
+    if let Some(declared) = repos
+        .as_table_like_mut()
+        .and_then(|repos| repos.get_mut(name))
+        .and_then(Item::as_table_like_mut)
+    {
+        for pin in ["branch", "commit", "version"] {
+            declared.remove(pin);
+        }
+        declared.insert("tag", toml_edit::value(tag));
+        return Ok(doc.to_string());
+    }
+    let mut added = InlineTable::new();
+    added.insert("url", repo.url.as_str().into());
+    added.insert("path", repo.path.as_str().into());
+    added.insert("tag", tag.into());
+    if !repo.pull {
+        added.insert("pull", false.into());
+    }
+    match repos {
+        Item::Table(repos) => {
+            let mut added = added.into_table();
+            if !first {
+                added.decor_mut().set_prefix("\n");
+            }
+            repos.insert(name, Item::Table(added));
+        }
+        Item::Value(Value::InlineTable(repos)) => {
+            repos.insert(name, Value::InlineTable(added));
+        }
+        _ => return Err("`repos` is not a table".into()),
+    }
+    Ok(doc.to_string())
+}
+
+impl Manifest {
+    pub fn version_manager(&self) -> Manager {
+        self.version_manager.unwrap_or_default()
+    }
+}
+
+impl Homebrew {
+    pub fn deprecated_taps(&self) -> DeprecatedTaps {
+        self.deprecated_taps.unwrap_or_default()
+    }
+}
+
+impl Dotfiles {
+    pub fn path(&self) -> &str {
+        self.path.as_ref().map_or("~/dotfiles", Identifier::as_str)
+    }
+}
+
+impl Secrets {
+    pub fn pass_dir(&self) -> &str {
+        self.pass_dir
+            .as_ref()
+            .map_or("~/.password-store", Identifier::as_str)
+    }
+
+    pub fn age_dir(&self) -> &str {
+        self.age_dir
+            .as_ref()
+            .map_or("~/.config/trk/secrets", Identifier::as_str)
+    }
+
+    pub fn age_identity(&self) -> &str {
+        self.age_identity
+            .as_ref()
+            .map_or("~/.config/trk/age.txt", Identifier::as_str)
+    }
+}
+
+impl Ssh {
+    pub fn agent(&self) -> SshAgent {
+        self.agent.unwrap_or_default()
+    }
+
+    pub fn rewrite_remotes(&self) -> bool {
+        self.rewrite_remotes.unwrap_or(false)
+    }
+}
+
+impl SshHost {
+    pub fn user(&self) -> &str {
+        self.user.as_ref().map_or("git", Identifier::as_str)
+    }
+}
+
+impl Fingerprint {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn merge_by<T, K: PartialEq>(into: &mut Vec<T>, from: Vec<T>, key: impl Fn(&T) -> K) {
+    for item in from {
+        match into.iter_mut().find(|existing| key(existing) == key(&item)) {
+            Some(existing) => *existing = item,
+            None => into.push(item),
+        }
+    }
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn latest() -> Identifier {
+    Identifier("latest".into())
+}
+
+impl Template {
+    /// `tag` is the release tag as written, `version` the tag without a
+    /// leading `v`.
+    pub const PLACEHOLDERS: [&'static str; 5] = ["name", "tag", "version", "os", "arch"];
+
+    /// Replaces each `{placeholder}` with its value from `vars`.
+    pub fn render(&self, vars: &[(&str, &str)]) -> String {
+        vars.iter().fold(self.0.clone(), |out, (name, value)| {
+            out.replace(&format!("{{{name}}}"), value)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Tap {
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Identifier {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn check_identifier(value: &str) -> Result<Identifier, String> {
+    if value.is_empty() {
+        Err("must not be empty".into())
+    } else if value.trim() != value {
+        Err(format!("`{value}` has leading or trailing whitespace"))
+    } else {
+        Ok(Identifier(value.to_owned()))
+    }
+}
+
+fn check_fingerprint(value: &str) -> Result<Fingerprint, String> {
+    let digest = value.strip_prefix("SHA256:").unwrap_or_default();
+    if digest.len() == 43
+        && digest
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+    {
+        Ok(Fingerprint(value.to_owned()))
+    } else {
+        Err(format!(
+            "fingerprint `{value}` must look like `SHA256:` and 43 base64 characters, \
+             as `ssh-keygen -l` prints it"
+        ))
+    }
+}
+
+fn check_template(value: &str) -> Result<Template, String> {
+    check_identifier(value)?;
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            return Err(format!("unclosed `{{` in `{value}`"));
+        };
+        let name = &rest[start + 1..start + len];
+        if !Template::PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder `{{{name}}}` in `{value}`; use one of {}",
+                Template::PLACEHOLDERS
+                    .map(|p| format!("{{{p}}}"))
+                    .join(", ")
+            ));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    Ok(Template(value.to_owned()))
+}
+
+fn check_commit(value: &str) -> Result<Commit, String> {
+    if (7..=40).contains(&value.len()) && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(Commit(Identifier(value.to_owned())))
+    } else {
+        Err(format!(
+            "commit `{value}` must be 7 to 40 hex digits; use `tag` or `branch` for names"
+        ))
+    }
+}
+
+fn check_range(value: &str) -> Result<Range, String> {
+    VersionReq::parse(value)
+        .map(Range)
+        .map_err(|err| format!("version `{value}`: {err}"))
+}
+
+fn check_version(value: &str) -> Result<RuntimeVersion, String> {
+    let version = check_identifier(value)?;
+    Alias::parse(version.as_str())?;
+    Ok(RuntimeVersion(version.0))
+}
+
+fn check_tap(value: &str) -> Result<Tap, String> {
+    let mut parts = value.split('/');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(user), Some(repo), None)
+            if !user.is_empty() && !repo.is_empty() && !value.contains(char::is_whitespace) =>
+        {
+            Ok(Tap {
+                name: value.to_owned(),
+                state: State::Present,
+            })
+        }
+        _ => Err(format!("tap `{value}` must look like `user/repo`")),
+    }
+}
+
+/// Visits a string and checks it inside the visitor, so toml attributes a
+/// rejection to the string itself rather than to the enclosing array.
+struct Checked<T> {
+    expecting: &'static str,
+    check: fn(&str) -> Result<T, String>,
+}
+
+impl<T> Visitor<'_> for Checked<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        (self.check)(value).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Identifier {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(Checked {
+            expecting: "a non-empty string",
+            check: check_identifier,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(Checked {
+            expecting: "a host key fingerprint",
+            check: check_fingerprint,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Tap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Just the `user/repo` part.
+        struct Name(Tap);
+
+        impl<'de> Deserialize<'de> for Name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer
+                    .deserialize_str(Checked {
+                        expecting: "a tap name like `user/repo`",
+                        check: check_tap,
+                    })
+                    .map(Name)
+            }
+        }
+
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Table {
+            name: Name,
+            #[serde(default)]
+            state: State,
+        }
+
+        struct TapVisitor;
+
+        impl<'de> Visitor<'de> for TapVisitor {
+            type Value = Tap;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a tap name like `user/repo` or a table with `name` and `state`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Tap, E> {
+                check_tap(value).map_err(E::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Tap, A::Error> {
+                let table = Table::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(Tap {
+                    state: table.state,
+                    ..table.name.0
+                })
+            }
+        }
+
+        deserializer.deserialize_any(TapVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(Checked {
+            expecting: "a file name with {placeholders}",
+            check: check_template,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Commit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(Checked {
+            expecting: "a commit id",
+            check: check_commit,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Range {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(Checked {
+            expecting: "a semver requirement like `0.14` or `>=0.10, <0.16`",
+            check: check_range,
+        })
+    }
+}
+
+/// An exact runtime version or an alias such as `lts` or `~20.11`.
+struct RuntimeVersion(String);
+
+impl<'de> Deserialize<'de> for RuntimeVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(Checked {
+            expecting: "a version",
+            check: check_version,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Runtime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Table {
+            versions: Versions,
+            plugin: Option<Identifier>,
+        }
+
+        /// One version or a non-empty list.
+        struct Versions(Vec<String>);
+
+        impl<'de> Deserialize<'de> for Versions {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(VersionsVisitor)
+            }
+        }
+
+        struct VersionsVisitor;
+
+        impl<'de> Visitor<'de> for VersionsVisitor {
+            type Value = Versions;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a version or a list of versions")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Versions, E> {
+                let version = check_version(value).map_err(E::custom)?;
+                Ok(Versions(vec![version.0]))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Versions, A::Error> {
+                let mut versions = Vec::new();
+                while let Some(version) = seq.next_element::<RuntimeVersion>()? {
+                    versions.push(version.0);
+                }
+                if versions.is_empty() {
+                    return Err(de::Error::custom("list at least one version"));
+                }
+                Ok(Versions(versions))
+            }
+        }
+
+        struct RuntimeVisitor;
+
+        impl<'de> Visitor<'de> for RuntimeVisitor {
+            type Value = Runtime;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a version, a list of versions, or a table with `versions`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Runtime, E> {
+                Ok(Runtime {
+                    versions: VersionsVisitor.visit_str(value)?.0,
+                    plugin: None,
+                })
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Runtime, A::Error> {
+                Ok(Runtime {
+                    versions: VersionsVisitor.visit_seq(seq)?.0,
+                    plugin: None,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Runtime, A::Error> {
+                let table = Table::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(Runtime {
+                    versions: table.versions.0,
+                    plugin: table.plugin.map(|p| p.0),
+                })
+            }
+        }
+
+        deserializer.deserialize_any(RuntimeVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Package {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Table {
+            name: Identifier,
+            #[serde(default)]
+            latest: bool,
+        }
+
+        struct PackageVisitor;
+
+        impl<'de> Visitor<'de> for PackageVisitor {
+            type Value = Package;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a package name or a table with `name` and `latest`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Package, E> {
+                let name = check_identifier(value).map_err(E::custom)?;
+                Ok(Package {
+                    name: name.0,
+                    latest: false,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Package, A::Error> {
+                let table = Table::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(Package {
+                    name: table.name.0,
+                    latest: table.latest,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(PackageVisitor)
+    }
+}
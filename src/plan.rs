@@ -0,0 +1,168 @@
+//! `trk plan`: what a bootstrap or update would change, without changing
+//! anything.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::context::Context;
+use crate::steps::Step;
+
+/// Something a step would do to the machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub action: Action,
+    /// What is changed, e.g. `ansible` or `~/.trk`.
+    pub subject: String,
+    /// Versions, commit counts and the like.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Something new is installed, cloned or tapped.
+    Add,
+    /// Something that exists is upgraded or moved forward.
+    Change,
+    /// Something is removed.
+    Remove,
+    /// A command runs whose effect cannot be known in advance.
+    Run,
+    /// Nothing is done, but something is worth a look.
+    Warn,
+}
+
+/// The plan of one step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StepPlan {
+    pub step: String,
+    /// The part of the step name before `:`, e.g. `formula` or `repo`.
+    pub group: String,
+    pub changes: Vec<Change>,
+    /// Why the step could not be checked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The plan of a whole run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Plan {
+    pub command: String,
+    pub steps: Vec<StepPlan>,
+}
+
+impl Change {
+    pub fn new(action: Action, subject: impl Into<String>) -> Self {
+        Self {
+            action,
+            subject: subject.into(),
+            detail: None,
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+impl Action {
+    fn symbol(self) -> char {
+        match self {
+            Action::Add => '+',
+            Action::Change => '~',
+            Action::Remove => '-',
+            Action::Run => '>',
+            Action::Warn => '?',
+        }
+    }
+}
+
+impl Plan {
+    /// Runs the check phase of every step. A step that cannot be checked
+    /// is reported with its error rather than ending the plan. Secrets in
+    /// what the steps report are masked.
+    pub fn build(cx: &Context, command: &str, steps: &[Box<dyn Step>]) -> Self {
+        let steps = steps
+            .iter()
+            .map(|step| {
+                let name = step.name();
+                let group = name.split(':').next().unwrap_or_default().to_owned();
+                let (changes, error) = match step.plan(cx) {
+                    Ok(changes) => (changes, None),
+                    Err(err) => (Vec::new(), Some(cx.redact(&err.to_string()))),
+                };
+                let changes = changes
+                    .into_iter()
+                    .map(|change| Change {
+                        subject: cx.redact(&change.subject),
+                        detail: change.detail.map(|detail| cx.redact(&detail)),
+                        ..change
+                    })
+                    .collect();
+                StepPlan {
+                    step: name,
+                    group,
+                    changes,
+                    error,
+                }
+            })
+            .collect();
+        Self {
+            command: command.to_owned(),
+            steps,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.changes.is_empty() && step.error.is_none())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("plan serializes")
+    }
+}
+
+/// Changes grouped by step kind, one line per change.
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut groups: Vec<&str> = Vec::new();
+        for step in &self.steps {
+            if !groups.contains(&step.group.as_str()) {
+                groups.push(&step.group);
+            }
+        }
+        for group in groups {
+            let steps: Vec<&StepPlan> = self
+                .steps
+                .iter()
+                .filter(|step| step.group == group)
+                .filter(|step| !step.changes.is_empty() || step.error.is_some())
+                .collect();
+            if steps.is_empty() {
+                continue;
+            }
+            writeln!(f, "{group}")?;
+            for step in steps {
+                for change in &step.changes {
+                    write!(f, "  {} {}", change.action.symbol(), change.subject)?;
+                    match &change.detail {
+                        Some(detail) => writeln!(f, " ({detail})")?,
+                        None => writeln!(f)?,
+                    }
+                }
+                if let Some(error) = &step.error {
+                    writeln!(f, "  ! {}: {error}", step.step)?;
+                }
+            }
+        }
+        if self.is_empty() {
+            writeln!(f, "Nothing to do.")?;
+        }
+        Ok(())
+    }
+}
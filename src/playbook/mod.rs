@@ -0,0 +1,313 @@
+//! A native runner for the part of Ansible that dotfiles playbooks use,
+//! so a bootstrap does not need Python and `ansible-playbook`.
+//!
+//! Tasks may use the `homebrew`, `homebrew_tap`, `shell`, `command`, `stat`,
+//! `template` and `include_tasks` modules with `register`, `when`, `with_items`,
+//! `changed_when`, `become` and `args: { chdir }`. Every file is checked
+//! when it is loaded, before any of its tasks run, so a playbook that
+//! needs something else fails with the module's name instead of half
+//! applying.
+
+pub mod report;
+mod run;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+
+pub use report::Report;
+pub use run::Runner;
+
+/// The modules trk runs, listed in errors about the others.
+const MODULES: &str = "homebrew, homebrew_tap, shell, command, stat, template and include_tasks";
+
+/// Play keys that are accepted and have no effect on a local run.
+const IGNORED_PLAY_KEYS: [&str; 5] = ["hosts", "connection", "gather_facts", "become", "name"];
+
+/// A play: its variables and tasks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Play {
+    pub vars: Map<String, Value>,
+    pub tasks: Vec<Task>,
+}
+
+/// A module a task can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Module {
+    Homebrew,
+    HomebrewTap,
+    Shell,
+    Command,
+    Stat,
+    Template,
+    IncludeTasks,
+}
+
+impl Module {
+    /// The module for a task key, with or without its collection prefix.
+    fn from_key(key: &str) -> Option<Self> {
+        let short = key
+            .strip_prefix("ansible.builtin.")
+            .or_else(|| key.strip_prefix("community.general."))
+            .unwrap_or(key);
+        Some(match short {
+            "homebrew" => Module::Homebrew,
+            "homebrew_tap" => Module::HomebrewTap,
+            "shell" => Module::Shell,
+            "command" => Module::Command,
+            "stat" => Module::Stat,
+            "template" => Module::Template,
+            "include_tasks" | "import_tasks" => Module::IncludeTasks,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Module::Homebrew => "homebrew",
+            Module::HomebrewTap => "homebrew_tap",
+            Module::Shell => "shell",
+            Module::Command => "command",
+            Module::Stat => "stat",
+            Module::Template => "template",
+            Module::IncludeTasks => "include_tasks",
+        }
+    }
+
+    /// The argument a free-form value (`shell: echo hi`) stands for.
+    fn free_form(self) -> Option<&'static str> {
+        match self {
+            Module::Shell | Module::Command => Some("cmd"),
+            Module::IncludeTasks => Some("file"),
+            _ => None,
+        }
+    }
+
+    fn arguments(self) -> &'static [&'static str] {
+        match self {
+            Module::Homebrew => &["name", "state", "update_homebrew"],
+            Module::HomebrewTap => &["name", "state"],
+            Module::Shell => &["cmd", "chdir", "creates", "removes", "executable"],
+            Module::Command => &["cmd", "argv", "chdir", "creates", "removes"],
+            Module::Stat => &["path", "follow"],
+            Module::Template => &["src", "dest", "mode"],
+            Module::IncludeTasks => &["file"],
+        }
+    }
+
+    /// Ansible's other spellings of an argument, with the one they stand for.
+    fn aliases(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Module::Homebrew => &[("pkg", "name"), ("package", "name"), ("formula", "name")],
+            Module::HomebrewTap => &[("tap", "name")],
+            _ => &[],
+        }
+    }
+}
+
+/// One task, with its arguments still unrendered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    /// The task's `name`, or the module's when it has none.
+    pub name: String,
+    pub module: Module,
+    pub args: Map<String, Value>,
+    pub register: Option<String>,
+    /// Conditions that must all hold for the task to run.
+    pub when: Vec<Value>,
+    /// `with_items` or `loop`.
+    pub items: Option<Value>,
+    /// Conditions that must all hold for the task to count as changed.
+    pub changed_when: Option<Vec<Value>>,
+    /// Run `shell` and `command` through sudo.
+    pub sudo: bool,
+}
+
+/// Reads a playbook: a list of plays.
+pub fn load_playbook(path: &Path) -> Result<Vec<Play>> {
+    let plays = match read_yaml(path)? {
+        Value::Array(plays) => plays,
+        _ => return Err(invalid(path, "a playbook is a list of plays")),
+    };
+    plays
+        .into_iter()
+        .map(|play| parse_play(play).map_err(|message| invalid(path, message)))
+        .collect()
+}
+
+/// Reads a task file for `include_tasks`: a list of tasks.
+pub fn load_tasks(path: &Path) -> Result<Vec<Task>> {
+    match read_yaml(path)? {
+        Value::Array(tasks) => parse_tasks(tasks).map_err(|message| invalid(path, message)),
+        Value::Null => Ok(Vec::new()),
+        _ => Err(invalid(path, "a task file is a list of tasks")),
+    }
+}
+
+fn read_yaml(path: &Path) -> Result<Value> {
+    let source = fs::read_to_string(path).map_err(|err| invalid(path, err.to_string()))?;
+    serde_yaml_ng::from_str(&source).map_err(|err| invalid(path, err.to_string()))
+}
+
+fn invalid(path: &Path, message: impl Into<String>) -> Error {
+    Error::Path {
+        path: PathBuf::from(path),
+        message: message.into(),
+    }
+}
+
+fn parse_play(play: Value) -> Result<Play, String> {
+    let Value::Object(play) = play else {
+        return Err("a play is a mapping with `tasks`".into());
+    };
+    let mut parsed = Play {
+        vars: Map::new(),
+        tasks: Vec::new(),
+    };
+    for (key, value) in play {
+        match key.as_str() {
+            "vars" => match value {
+                Value::Object(vars) => parsed.vars = vars,
+                Value::Null => {}
+                _ => return Err("`vars` is a mapping".into()),
+            },
+            "tasks" => match value {
+                Value::Array(tasks) => parsed.tasks = parse_tasks(tasks)?,
+                Value::Null => {}
+                _ => return Err("`tasks` is a list".into()),
+            },
+            key if IGNORED_PLAY_KEYS.contains(&key) => {}
+            other => {
+                return Err(format!(
+                    "unsupported play keyword `{other}`; trk runs plays with vars and tasks"
+                ))
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_tasks(tasks: Vec<Value>) -> Result<Vec<Task>, String> {
+    tasks
+        .into_iter()
+        .enumerate()
+        .map(|(i, task)| parse_task(task, i + 1))
+        .collect()
+}
+
+fn parse_task(task: Value, number: usize) -> Result<Task, String> {
+    let Value::Object(task) = task else {
+        return Err(format!("task {number} is not a mapping"));
+    };
+    let name = task.get("name").and_then(Value::as_str).map(str::to_owned);
+    let label = match &name {
+        Some(name) => format!("task `{name}`"),
+        None => format!("task {number}"),
+    };
+    let mut module: Option<Module> = None;
+    let mut module_args = Value::Null;
+    let mut extra_args = Map::new();
+    let mut parsed = Task {
+        name: String::new(),
+        module: Module::Shell,
+        args: Map::new(),
+        register: None,
+        when: Vec::new(),
+        items: None,
+        changed_when: None,
+        sudo: false,
+    };
+    for (key, value) in task {
+        match key.as_str() {
+            "name" | "tags" => {}
+            "register" => match value {
+                Value::String(var) => parsed.register = Some(var),
+                _ => return Err(format!("{label}: `register` is a variable name")),
+            },
+            "when" => match value {
+                Value::Array(conditions) => parsed.when = conditions,
+                condition => parsed.when = vec![condition],
+            },
+            "with_items" | "loop" => parsed.items = Some(value),
+            "changed_when" => match value {
+                Value::Array(conditions) => parsed.changed_when = Some(conditions),
+                condition => parsed.changed_when = Some(vec![condition]),
+            },
+            "become" => parsed.sudo = crate::template::truthy(&value),
+            "args" => match value {
+                Value::Object(args) => extra_args = args,
+                _ => return Err(format!("{label}: `args` is a mapping")),
+            },
+            _ => match (Module::from_key(&key), module) {
+                (None, _) => {
+                    return Err(format!(
+                        "{label}: unsupported module `{key}`; trk runs {MODULES}"
+                    ))
+                }
+                (Some(found), Some(first)) => {
+                    return Err(format!(
+                        "{label}: more than one module (`{}` and `{}`)",
+                        first.name(),
+                        found.name()
+                    ))
+                }
+                (Some(found), None) => {
+                    module = Some(found);
+                    module_args = value;
+                }
+            },
+        }
+    }
+    let Some(module) = module else {
+        return Err(format!("{label}: no module; trk runs {MODULES}"));
+    };
+    let mut args = match (module_args, module.free_form()) {
+        (Value::Object(args), _) => args,
+        (Value::Null, _) => Map::new(),
+        (Value::String(raw), Some(key)) => Map::from_iter([(key.to_owned(), Value::String(raw))]),
+        (Value::String(raw), None) => key_values(&raw)
+            .ok_or_else(|| format!("{label}: cannot read `{}: {raw}`", module.name()))?,
+        _ => return Err(format!("{label}: `{}` takes a mapping", module.name())),
+    };
+    for (key, value) in extra_args {
+        args.entry(key).or_insert(value);
+    }
+    for &(alias, key) in module.aliases() {
+        if let Some(value) = args.remove(alias) {
+            if args.contains_key(key) {
+                return Err(format!(
+                    "{label}: `{alias}` and `{key}` are the same argument for `{}`",
+                    module.name()
+                ));
+            }
+            args.insert(key.to_owned(), value);
+        }
+    }
+    if let Some(unknown) = args
+        .keys()
+        .find(|key| !module.arguments().contains(&key.as_str()))
+    {
+        return Err(format!(
+            "{label}: unsupported argument `{unknown}` for `{}`; it takes {}",
+            module.name(),
+            module.arguments().join(", ")
+        ));
+    }
+    parsed.name = name.unwrap_or_else(|| module.name().to_owned());
+    parsed.module = module;
+    parsed.args = args;
+    Ok(parsed)
+}
+
+/// The old `homebrew: name=git state=latest` form.
+fn key_values(raw: &str) -> Option<Map<String, Value>> {
+    raw.split_whitespace()
+        .map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((key.to_owned(), Value::String(value.to_owned())))
+        })
+        .collect()
+}
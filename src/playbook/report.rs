@@ -0,0 +1,172 @@
+//! What a playbook run did, task by task, read back from the JSON lines
+//! the bundled `trk_events` callback plugin writes while `ansible-playbook`
+//! runs.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// How a task ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Changed,
+    Failed,
+    Skipped,
+    Unreachable,
+}
+
+impl Status {
+    fn failed(self) -> bool {
+        matches!(self, Status::Failed | Status::Unreachable)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "ok",
+            Status::Changed => "changed",
+            Status::Failed => "failed",
+            Status::Skipped => "skipped",
+            Status::Unreachable => "unreachable",
+        })
+    }
+}
+
+/// One task's result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskReport {
+    pub name: String,
+    /// The file the task is written in, which for a task pulled in by
+    /// `include_tasks` is the included file, and its line.
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    pub status: Status,
+    pub duration: Duration,
+    /// Ansible's `msg`, followed by the command's stderr for a failure.
+    pub message: String,
+}
+
+impl TaskReport {
+    /// `file:line`, or the task's name when Ansible gave no path.
+    pub fn location(&self) -> String {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => format!("{}:{line}", file.display()),
+            (Some(file), None) => file.display().to_string(),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// Every task of a run, in the order they finished.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub tasks: Vec<TaskReport>,
+}
+
+#[derive(Deserialize)]
+struct Event {
+    task: String,
+    #[serde(default)]
+    path: String,
+    status: Status,
+    #[serde(default)]
+    duration: f64,
+    #[serde(default)]
+    message: String,
+}
+
+impl Report {
+    /// Parses the callback plugin's output, one JSON object per line.
+    pub fn parse(events: &str) -> Result<Self, String> {
+        let mut tasks = Vec::new();
+        for (index, line) in events.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: Event =
+                serde_json::from_str(line).map_err(|err| format!("line {}: {err}", index + 1))?;
+            let (file, line) = match event.path.rsplit_once(':') {
+                Some((file, line)) if line.parse::<u32>().is_ok() => {
+                    (Some(file.into()), line.parse().ok())
+                }
+                _ if event.path.is_empty() => (None, None),
+                _ => (Some(event.path.into()), None),
+            };
+            tasks.push(TaskReport {
+                name: event.task,
+                file,
+                line,
+                status: event.status,
+                duration: Duration::from_secs_f64(event.duration.max(0.0)),
+                message: event.message.trim().to_owned(),
+            });
+        }
+        Ok(Self { tasks })
+    }
+
+    pub fn count(&self, status: Status) -> usize {
+        self.tasks
+            .iter()
+            .filter(|task| task.status == status)
+            .count()
+    }
+
+    pub fn changed(&self) -> bool {
+        self.count(Status::Changed) > 0
+    }
+
+    /// Failed and unreachable tasks.
+    pub fn failures(&self) -> impl Iterator<Item = &TaskReport> {
+        self.tasks.iter().filter(|task| task.status.failed())
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.tasks.iter().map(|task| task.duration).sum()
+    }
+}
+
+/// A table of every task with its status and time, a totals line, and each
+/// failure again with its file and message so it is not lost in the table.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    {:<11} {:>7}  task", "status", "time")?;
+        for task in &self.tasks {
+            writeln!(
+                f,
+                "    {:<11} {:>6.1}s  {}",
+                task.status.to_string(),
+                task.duration.as_secs_f64(),
+                task.name
+            )?;
+        }
+        let failed = self.count(Status::Failed) + self.count(Status::Unreachable);
+        writeln!(
+            f,
+            "    {} tasks: {} ok, {} changed, {failed} failed, {} skipped in {:.1}s",
+            self.tasks.len(),
+            self.count(Status::Ok),
+            self.count(Status::Changed),
+            self.count(Status::Skipped),
+            self.duration().as_secs_f64()
+        )?;
+        for task in self.failures() {
+            write!(
+                f,
+                "\n!!! {} `{}` at {}",
+                task.status,
+                task.name,
+                task.location()
+            )?;
+            if !task.message.is_empty() {
+                write!(f, "\n    {}", task.message.replace('\n', "\n    "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Map, Value};
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::secret;
+use crate::steps::{Kind, Outcome, Package, Step, Tap};
+use crate::template::{self, Vars};
+
+use super::{load_playbook, load_tasks, Module, Task};
+
+/// Runs playbooks on this machine, printing a line per task the way
+/// `ansible-playbook` does.
+pub struct Runner<'a> {
+    cx: &'a Context,
+    /// Variables that win over the playbook's own, like `--extra-vars`.
+    extra: Vars,
+    /// The current play's vars, extra vars and everything registered so
+    /// far in that play.
+    vars: Vars,
+    changed: bool,
+}
+
+impl<'a> Runner<'a> {
+    pub fn new(cx: &'a Context) -> Self {
+        Self {
+            cx,
+            extra: Vars::new(),
+            vars: Vars::new(),
+            changed: false,
+        }
+    }
+
+    /// Sets `name` for every play, over the play's own `vars`.
+    pub fn var(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extra.insert(name.into(), value.into());
+        self
+    }
+
+    /// Runs every play in the playbook at `path` and reports whether any
+    /// task changed something.
+    pub fn run(&mut self, path: &Path) -> Result<bool> {
+        for play in load_playbook(path)? {
+            self.vars = play.vars;
+            self.vars.extend(self.extra.clone());
+            self.tasks(&play.tasks, path)?;
+        }
+        Ok(self.changed)
+    }
+
+    /// Whether a task in the playbook at `path`, or in a file it includes,
+    /// runs with `become`. Includes named after registered results cannot
+    /// be followed before the run and are left out.
+    pub fn uses_sudo(&self, path: &Path) -> Result<bool> {
+        for play in load_playbook(path)? {
+            let mut vars = play.vars;
+            vars.extend(self.extra.clone());
+            if self.tasks_use_sudo(&play.tasks, path, &vars)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn tasks_use_sudo(&self, tasks: &[Task], file: &Path, vars: &Vars) -> Result<bool> {
+        for task in tasks {
+            if task.sudo {
+                return Ok(true);
+            }
+            if task.module != Module::IncludeTasks {
+                continue;
+            }
+            let args = Value::Object(task.args.clone());
+            let Ok(Value::Object(args)) = template::render_value(&args, vars) else {
+                continue;
+            };
+            let Ok(path) = self.include(&args, file) else {
+                continue;
+            };
+            if path.is_file() && self.tasks_use_sudo(&load_tasks(&path)?, &path, vars)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The variables as they are now, registered results included.
+    pub fn vars(&self) -> &Vars {
+        &self.vars
+    }
+
+    fn tasks(&mut self, tasks: &[Task], file: &Path) -> Result<()> {
+        for task in tasks {
+            self.task(task, file)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `task` once, or once per item, and registers the result.
+    fn task(&mut self, task: &Task, file: &Path) -> Result<()> {
+        let fail = |message: String| task_error(file, task, message);
+        let Some(items) = &task.items else {
+            let result = self.once(task, file, None)?;
+            if let Some(var) = &task.register {
+                self.vars.insert(var.clone(), result);
+            }
+            return Ok(());
+        };
+        let items = match template::render_value(items, &self.vars).map_err(fail)? {
+            Value::Array(items) => items,
+            other => return Err(fail(format!("`with_items` is not a list: {other}"))),
+        };
+        let mut results = Vec::with_capacity(items.len());
+        for item in items {
+            self.vars.insert("item".into(), item.clone());
+            let mut result = self.once(task, file, Some(&item))?;
+            result["item"] = item;
+            results.push(result);
+        }
+        self.vars.remove("item");
+        if let Some(var) = &task.register {
+            let changed = results.iter().any(|result| result["changed"] == true);
+            self.vars.insert(
+                var.clone(),
+                json!({ "changed": changed, "results": results }),
+            );
+        }
+        Ok(())
+    }
+
+    /// One run of `task`: its conditions, its module and `changed_when`.
+    fn once(&mut self, task: &Task, file: &Path, item: Option<&Value>) -> Result<Value> {
+        let fail = |message: String| task_error(file, task, message);
+        for condition in &task.when {
+            if !template::condition(condition, &self.vars).map_err(fail)? {
+                report("skipping", task, item);
+                return Ok(json!({ "changed": false, "skipped": true }));
+            }
+        }
+        let mut args = Map::new();
+        for (key, value) in &task.args {
+            let value = template::render_value(value, &self.vars).map_err(fail)?;
+            args.insert(key.clone(), self.with_secrets(value).map_err(fail)?);
+        }
+        if task.module == Module::IncludeTasks {
+            let path = self.include(&args, file).map_err(fail)?;
+            println!("    included: {}", path.display());
+            self.tasks(&load_tasks(&path)?, &path)?;
+            return Ok(json!({ "changed": false }));
+        }
+
+        let mut result = match task.module {
+            Module::Shell => self.shell(&args, task.sudo),
+            Module::Command => self.command(&args, task.sudo),
+            Module::Stat => self.stat(&args),
+            Module::Template => self.template(&args, file),
+            Module::Homebrew => self.homebrew(&args),
+            Module::HomebrewTap => self.homebrew_tap(&args),
+            Module::IncludeTasks => unreachable!("includes are run above"),
+        }
+        .map_err(fail)?;
+        if let Some(conditions) = &task.changed_when {
+            if let Some(var) = &task.register {
+                self.vars.insert(var.clone(), result.clone());
+            }
+            let mut changed = true;
+            for condition in conditions {
+                changed &= template::condition(condition, &self.vars).map_err(fail)?;
+            }
+            result["changed"] = Value::Bool(changed);
+        }
+        let changed = result["changed"] == true;
+        self.changed |= changed;
+        report(if changed { "changed" } else { "ok" }, task, item);
+        Ok(result)
+    }
+
+    /// `value` with the secret references in its strings resolved, once
+    /// rendered, so a template can build one from variables.
+    fn with_secrets(&self, value: Value) -> Result<Value, String> {
+        Ok(match value {
+            Value::String(s) => {
+                Value::String(secret::expand(self.cx, &s).map_err(|err| err.to_string())?)
+            }
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.with_secrets(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(k, v)| Ok((k, self.with_secrets(v)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            other => other,
+        })
+    }
+
+    /// An included file, relative to the file that includes it.
+    fn include(&self, args: &Vars, file: &Path) -> Result<PathBuf, String> {
+        let path = string(args, "file")?.ok_or("`include_tasks` needs a file")?;
+        let path = self.cx.expand(&path);
+        Ok(match file.parent() {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path,
+        })
+    }
+
+    fn shell(&self, args: &Vars, sudo: bool) -> Result<Value, String> {
+        let cmd = string(args, "cmd")?.ok_or("`shell` needs a command")?;
+        let shell = string(args, "executable")?.unwrap_or_else(|| "/bin/sh".into());
+        self.exec(vec![shell, "-c".into(), cmd], args, sudo)
+    }
+
+    fn command(&self, args: &Vars, sudo: bool) -> Result<Value, String> {
+        let argv = match args.get("argv") {
+            Some(Value::Array(argv)) => argv.iter().map(template::to_string).collect(),
+            Some(_) => return Err("`argv` is a list".into()),
+            None => split_words(&string(args, "cmd")?.ok_or("`command` needs a command")?)?,
+        };
+        if argv.is_empty() {
+            return Err("`command` needs a command".into());
+        }
+        self.exec(argv, args, sudo)
+    }
+
+    /// Runs `argv` for `shell` and `command`, honouring `creates`,
+    /// `removes` and `chdir`.
+    fn exec(&self, argv: Vec<String>, args: &Vars, sudo: bool) -> Result<Value, String> {
+        let cmd_line = argv.join(" ");
+        if let Some(creates) = string(args, "creates")? {
+            if self.cx.expand(&creates).exists() {
+                return Ok(not_run(
+                    &cmd_line,
+                    format!("skipped, since {creates} exists"),
+                ));
+            }
+        }
+        if let Some(removes) = string(args, "removes")? {
+            if !self.cx.expand(&removes).exists() {
+                return Ok(not_run(
+                    &cmd_line,
+                    format!("skipped, since {removes} does not exist"),
+                ));
+            }
+        }
+        // The output is captured, so sudo must not prompt: the run asked
+        // for the password before the playbook started.
+        let mut cmd = if sudo {
+            self.cx.cmd("sudo").arg("-n").args(argv)
+        } else {
+            self.cx.cmd(&argv[0]).args(&argv[1..])
+        };
+        if let Some(dir) = string(args, "chdir")? {
+            cmd = cmd.cwd(self.cx.expand(&dir));
+        }
+        let output = cmd
+            .output()
+            .and_then(|out| out.check(&cmd))
+            .map_err(message)?;
+        let stdout = output.stdout.trim_end_matches('\n');
+        let stderr = output.stderr.trim_end_matches('\n');
+        Ok(json!({
+            "changed": true,
+            "cmd": cmd_line,
+            "rc": output.status,
+            "stdout": stdout,
+            "stdout_lines": stdout.lines().collect::<Vec<_>>(),
+            "stderr": stderr,
+            "stderr_lines": stderr.lines().collect::<Vec<_>>(),
+        }))
+    }
+
+    /// `stat` does not follow symlinks unless asked to, like Ansible's.
+    fn stat(&self, args: &Vars) -> Result<Value, String> {
+        let written = string(args, "path")?.ok_or("`stat` needs a path")?;
+        let path = self.cx.expand(&written);
+        let metadata = if args.get("follow").is_some_and(template::truthy) {
+            fs::metadata(&path)
+        } else {
+            fs::symlink_metadata(&path)
+        };
+        let stat = match metadata {
+            Ok(metadata) => json!({
+                "exists": true,
+                "path": path.to_string_lossy(),
+                "isdir": metadata.is_dir(),
+                "isreg": metadata.is_file(),
+                "islnk": metadata.file_type().is_symlink(),
+                "mode": mode(&metadata),
+            }),
+            Err(_) => json!({ "exists": false }),
+        };
+        Ok(json!({ "changed": false, "stat": stat }))
+    }
+
+    /// Renders `src` (looked up in `templates/` next to the task file, then
+    /// next to it) with the task's variables and writes it to `dest` when
+    /// it differs.
+    fn template(&self, args: &Vars, file: &Path) -> Result<Value, String> {
+        let src = string(args, "src")?.ok_or("`template` needs a src")?;
+        let dest = string(args, "dest")?.ok_or("`template` needs a dest")?;
+        let src = self.cx.expand(&src);
+        let src = match file.parent() {
+            Some(dir) if src.is_relative() => {
+                let in_templates = dir.join("templates").join(&src);
+                if in_templates.exists() {
+                    in_templates
+                } else {
+                    dir.join(src)
+                }
+            }
+            _ => src,
+        };
+        let source = fs::read_to_string(&src)
+            .map_err(|err| format!("cannot read {}: {err}", src.display()))?;
+        let rendered = template::render_string(&source, &self.vars)
+            .and_then(|text| secret::expand(self.cx, &text).map_err(|err| err.to_string()))
+            .map_err(|err| format!("{}: {err}", src.display()))?;
+        let dest = self.cx.expand(&dest);
+        let changed = fs::read_to_string(&dest).ok().as_deref() != Some(rendered.as_str());
+        if changed {
+            if let Some(dir) = dest.parent() {
+                fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+            }
+            fs::write(&dest, &rendered).map_err(|err| format!("{}: {err}", dest.display()))?;
+        }
+        let mode_changed = match string(args, "mode")? {
+            Some(mode) => set_mode(&dest, &mode)?,
+            None => false,
+        };
+        Ok(json!({
+            "changed": changed || mode_changed,
+            "dest": dest.to_string_lossy(),
+            "src": src.to_string_lossy(),
+        }))
+    }
+
+    /// Formulae through the same step the manifest's packages use.
+    fn homebrew(&self, args: &Vars) -> Result<Value, String> {
+        let update = args.get("update_homebrew").is_some_and(template::truthy);
+        // `homebrew: update_homebrew=yes` on its own only updates Homebrew.
+        let names = if update && !args.contains_key("name") {
+            Vec::new()
+        } else {
+            names(args, "homebrew")?
+        };
+        let state = string(args, "state")?.unwrap_or_else(|| "present".into());
+        if update {
+            self.cx
+                .cmd("brew")
+                .arg("update")
+                .status()
+                .map_err(message)?;
+        }
+        let mut changed = update;
+        for name in &names {
+            changed |= match state.as_str() {
+                "present" | "installed" | "latest" | "upgraded" => {
+                    let latest = matches!(state.as_str(), "latest" | "upgraded");
+                    let package = Package::new(Kind::Formula, name).latest(latest);
+                    package.run(self.cx).map_err(message)? == Outcome::Changed
+                }
+                "absent" | "removed" | "uninstalled" => {
+                    let brew =
+                        |subcommand| self.cx.cmd("brew").args([subcommand, "--formula", name]);
+                    let installed = brew("list").succeeds().map_err(message)?;
+                    if installed {
+                        brew("uninstall").status().map_err(message)?;
+                    }
+                    installed
+                }
+                other => {
+                    return Err(format!(
+                        "unsupported state `{other}` for `homebrew`; use present, latest or absent"
+                    ))
+                }
+            };
+        }
+        Ok(json!({ "changed": changed }))
+    }
+
+    fn homebrew_tap(&self, args: &Vars) -> Result<Value, String> {
+        let names = names(args, "homebrew_tap")?;
+        let absent = match string(args, "state")?.as_deref() {
+            None | Some("present") => false,
+            Some("absent") => true,
+            Some(other) => {
+                return Err(format!(
+                    "unsupported state `{other}` for `homebrew_tap`; use present or absent"
+                ))
+            }
+        };
+        let mut changed = false;
+        for name in names {
+            let tap = Tap::new(name);
+            let tap = if absent { tap.absent() } else { tap };
+            changed |= tap.run(self.cx).map_err(message)? == Outcome::Changed;
+        }
+        Ok(json!({ "changed": changed }))
+    }
+}
+
+fn task_error(file: &Path, task: &Task, message: String) -> Error {
+    Error::Task {
+        path: file.to_owned(),
+        task: task.name.clone(),
+        message,
+    }
+}
+
+fn message(err: Error) -> String {
+    err.to_string()
+}
+
+fn report(status: &str, task: &Task, item: Option<&Value>) {
+    match item {
+        Some(item) => println!(
+            "    {status}: {} ({})",
+            task.name,
+            template::to_string(item)
+        ),
+        None => println!("    {status}: {}", task.name),
+    }
+}
+
+/// The result of a `shell` or `command` that `creates` or `removes` kept
+/// from running.
+fn not_run(cmd_line: &str, reason: String) -> Value {
+    json!({ "changed": false, "cmd": cmd_line, "rc": 0, "stdout": reason, "stdout_lines": [reason] })
+}
+
+/// A scalar argument as a string.
+fn string(args: &Vars, key: &str) -> Result<Option<String>, String> {
+    match args.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(value @ (Value::Number(_) | Value::Bool(_))) => Ok(Some(value.to_string())),
+        Some(_) => Err(format!("`{key}` is a string")),
+    }
+}
+
+/// `name` as a list, a string or a comma-separated string.
+fn names(args: &Vars, module: &str) -> Result<Vec<String>, String> {
+    let names: Vec<String> = match args.get("name") {
+        Some(Value::Array(names)) => names.iter().map(template::to_string).collect(),
+        Some(Value::String(names)) => names.split(',').map(|n| n.trim().to_owned()).collect(),
+        _ => return Err(format!("`{module}` needs a name")),
+    };
+    if names.iter().any(String::is_empty) {
+        return Err(format!("`{module}` has an empty name"));
+    }
+    Ok(names)
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> Value {
+    use std::os::unix::fs::PermissionsExt;
+    Value::String(format!("{:04o}", metadata.permissions().mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &fs::Metadata) -> Value {
+    Value::Null
+}
+
+/// Sets an octal `mode` such as `"0644"`, reporting whether it changed.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: &str) -> Result<bool, String> {
+    use std::os::unix::fs::PermissionsExt;
+    let bits = u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map_err(|_| format!("`mode` `{mode}` is not an octal mode such as \"0644\""))?;
+    let current = fs::metadata(path)
+        .map_err(|err| format!("{}: {err}", path.display()))?
+        .permissions()
+        .mode()
+        & 0o7777;
+    if current == bits {
+        return Ok(false);
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(bits))
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: &str) -> Result<bool, String> {
+    Ok(false)
+}
+
+/// Splits a `command` line into arguments the way a shell would, minus
+/// everything but quoting.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => word.extend(chars.next()),
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unclosed quote in `{line}`"));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
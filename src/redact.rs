@@ -0,0 +1,164 @@
+//! Keeping resolved secrets out of everything trk writes.
+//!
+//! Every secret a run resolves is registered with the run's [`Redactor`],
+//! which masks it, and the forms it is most often passed around in, in
+//! streamed command output, the journal, JSON reports, recorded fixtures
+//! and error messages.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::{PoisonError, RwLock};
+
+use crate::base64;
+
+/// What a secret is replaced with.
+pub const MASK: &str = "********";
+
+/// Secrets shorter than this are not masked: a one or two character value
+/// would blot out every occurrence of ordinary text.
+pub const MIN_LEN: usize = 4;
+
+/// The secrets of a run, in every form they are masked in.
+#[derive(Default)]
+pub struct Redactor {
+    /// Longest first, so a form that contains another is masked whole.
+    forms: RwLock<Vec<Vec<u8>>>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Masks `secret` from now on, as it is, percent encoded and base64
+    /// (standard and URL safe).
+    ///
+    /// Inside longer data, such as `user:token` for basic auth or a value
+    /// piped through `base64` with its newline, the encoding of a secret
+    /// depends on where it starts in a three byte group, and its last
+    /// characters on what follows it. So the whole groups are masked too,
+    /// for each of the three places the secret can start.
+    pub fn register(&self, secret: &str) {
+        if secret.len() < MIN_LEN {
+            return;
+        }
+        let bytes = secret.as_bytes();
+        let mut candidates = vec![secret.to_owned(), percent_encode(secret)];
+        for alphabet in [base64::STANDARD, base64::URL_SAFE] {
+            candidates.push(base64::encode(bytes, alphabet));
+            for skip in 0..3 {
+                let mut stem = base64::encode(&bytes[skip..], alphabet);
+                stem.truncate((bytes.len() - skip) / 3 * 4);
+                if stem.len() >= MIN_LEN {
+                    candidates.push(stem);
+                }
+            }
+        }
+        let mut forms = self.forms.write().unwrap_or_else(PoisonError::into_inner);
+        for form in candidates {
+            if !forms.iter().any(|known| *known == form.as_bytes()) {
+                forms.push(form.into_bytes());
+            }
+        }
+        forms.sort_by_key(|form| std::cmp::Reverse(form.len()));
+    }
+
+    /// Whether nothing has been registered yet.
+    pub fn is_empty(&self) -> bool {
+        self.forms
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_empty()
+    }
+
+    /// `text` with every registered secret replaced by [`MASK`].
+    pub fn redact(&self, text: &str) -> String {
+        if self.is_empty() {
+            return text.to_owned();
+        }
+        String::from_utf8_lossy(&self.redact_bytes(text.as_bytes())).into_owned()
+    }
+
+    /// Copies `from` to `to` as it arrives, masking secrets on the way.
+    ///
+    /// Output is passed on as soon as it is read, so prompts without a
+    /// newline still show, except for a tail that could be the start of a
+    /// secret, which waits for the next read to tell.
+    pub fn stream(&self, mut from: impl Read, mut to: impl Write) -> io::Result<()> {
+        let mut pending = Vec::new();
+        let mut chunk = [0; 8192];
+        loop {
+            let read = match from.read(&mut chunk) {
+                Ok(0) => {
+                    to.write_all(&self.redact_bytes(&pending))?;
+                    return to.flush();
+                }
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            pending.extend_from_slice(&chunk[..read]);
+            let keep = self.partial_suffix(&pending);
+            let ready = pending.len() - keep;
+            to.write_all(&self.redact_bytes(&pending[..ready]))?;
+            to.flush()?;
+            pending.drain(..ready);
+        }
+    }
+
+    fn redact_bytes(&self, text: &[u8]) -> Vec<u8> {
+        let forms = self.forms.read().unwrap_or_else(PoisonError::into_inner);
+        let mut out = Vec::with_capacity(text.len());
+        let mut at = 0;
+        'scan: while at < text.len() {
+            for form in forms.iter() {
+                if text[at..].starts_with(form) {
+                    out.extend_from_slice(MASK.as_bytes());
+                    at += form.len();
+                    continue 'scan;
+                }
+            }
+            out.push(text[at]);
+            at += 1;
+        }
+        out
+    }
+
+    /// The length of the longest tail of `text` that a secret starts with
+    /// without being complete.
+    fn partial_suffix(&self, text: &[u8]) -> usize {
+        let forms = self.forms.read().unwrap_or_else(PoisonError::into_inner);
+        let longest = forms.first().map_or(0, Vec::len);
+        (1..longest.min(text.len() + 1))
+            .rev()
+            .find(|&len| {
+                let tail = &text[text.len() - len..];
+                forms
+                    .iter()
+                    .any(|form| form.len() > len && form.starts_with(tail))
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// Never shows the secrets.
+impl fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let forms = self.forms.read().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Redactor")
+            .field("forms", &forms.len())
+            .finish()
+    }
+}
+
+/// Everything but unreserved characters as `%XX`, as in a URL.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
@@ -0,0 +1,35 @@
+//! Decisions taken during a run that step outcomes alone do not show,
+//! saved in the journal next to them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::steps::MasChoice;
+
+/// What the run decided so far. Steps read it to honour earlier
+/// decisions, such as leaving App Store apps out of the Brewfile install.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    /// The Mac App Store preflight, when it had to ask.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mas: Option<MasDecision>,
+}
+
+/// How a run went on when the App Store account was not signed in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MasDecision {
+    /// The account state that prompted the choice, e.g. `signed out`.
+    pub account: String,
+    pub choice: MasChoice,
+    /// Brewfile `mas` entries left uninstalled because of the choice.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+}
+
+impl Report {
+    /// Whether the App Store app `name` is to be left alone this run.
+    pub fn skips_mas(&self, name: &str) -> bool {
+        self.mas
+            .as_ref()
+            .is_some_and(|mas| mas.skipped.iter().any(|skipped| skipped == name))
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+
+use super::VersionManager;
+
+/// Node's release index: every release, newest first.
+pub const NODE_INDEX: &str = "https://nodejs.org/dist/index.json";
+
+/// A runtime version that names a moving target rather than a release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alias {
+    /// The newest release.
+    Latest,
+    /// The newest long-term-support release, or of the named LTS line
+    /// (`lts/iron`).
+    Lts(Option<String>),
+    /// The newest release starting with a major or major.minor (`20`,
+    /// `20.11`).
+    Prefix(String),
+    /// The newest release in a semver range (`~20.11`, `^20`, `>=18 <21`).
+    Range(VersionReq),
+}
+
+impl Alias {
+    /// The alias `version` is, or `None` for an exact version. Ranges
+    /// that do not parse are errors.
+    pub fn parse(version: &str) -> Result<Option<Alias>, String> {
+        let lower = version.to_ascii_lowercase();
+        if lower == "latest" {
+            return Ok(Some(Alias::Latest));
+        }
+        if lower == "lts" || lower == "lts/*" {
+            return Ok(Some(Alias::Lts(None)));
+        }
+        if let Some(line) = lower.strip_prefix("lts/") {
+            return Ok(Some(Alias::Lts(Some(line.to_owned()))));
+        }
+        let parts: Vec<_> = version.split('.').collect();
+        if parts.len() < 3
+            && parts
+                .iter()
+                .all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Ok(Some(Alias::Prefix(version.to_owned())));
+        }
+        if version.starts_with(['~', '^', '<', '>', '=']) {
+            return VersionReq::parse(version)
+                .map(|req| Some(Alias::Range(req)))
+                .map_err(|err| format!("version range `{version}`: {err}"));
+        }
+        Ok(None)
+    }
+
+    /// The newest release of `plugin` this alias stands for. Node is
+    /// resolved against its release index; other runtimes ask `manager`,
+    /// which only knows `latest` and prefixes.
+    pub fn resolve(
+        &self,
+        cx: &Context,
+        manager: &dyn VersionManager,
+        plugin: &str,
+    ) -> Result<String> {
+        if is_node(plugin) {
+            let cmd = cx.cmd("curl").args(["-fsSL", "--retry", "3", NODE_INDEX]);
+            let index = cmd.output()?.check(&cmd)?.stdout;
+            let releases = parse_node_index(&index).map_err(|message| Error::Output {
+                command: cmd.to_string(),
+                message,
+            })?;
+            return self.pick(&releases).ok_or_else(|| Error::Runtime {
+                plugin: plugin.to_owned(),
+                message: format!("no release matches `{self}` in {NODE_INDEX}"),
+            });
+        }
+        match self {
+            Alias::Latest => manager.latest(cx, plugin, None),
+            Alias::Prefix(prefix) => manager.latest(cx, plugin, Some(prefix)),
+            Alias::Lts(_) | Alias::Range(_) => Err(Error::Runtime {
+                plugin: plugin.to_owned(),
+                message: format!(
+                    "`{self}` needs a release index, which only nodejs has; \
+                     use `latest`, a major version or an exact version"
+                ),
+            }),
+        }
+    }
+
+    /// The newest of `releases` that matches.
+    pub fn pick(&self, releases: &[Release]) -> Option<String> {
+        releases
+            .iter()
+            .filter(|release| release.version.pre.is_empty())
+            .filter(|release| match self {
+                Alias::Latest => true,
+                Alias::Lts(None) => release.lts.is_some(),
+                Alias::Lts(Some(line)) => release
+                    .lts
+                    .as_ref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(line)),
+                Alias::Prefix(prefix) => {
+                    let version = release.version.to_string();
+                    version == *prefix || version.starts_with(&format!("{prefix}."))
+                }
+                Alias::Range(req) => req.matches(&release.version),
+            })
+            .max_by(|a, b| a.version.cmp(&b.version))
+            .map(|release| release.version.to_string())
+    }
+}
+
+impl fmt::Display for Alias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alias::Latest => f.write_str("latest"),
+            Alias::Lts(None) => f.write_str("lts"),
+            Alias::Lts(Some(line)) => write!(f, "lts/{line}"),
+            Alias::Prefix(prefix) => f.write_str(prefix),
+            Alias::Range(req) => write!(f, "{req}"),
+        }
+    }
+}
+
+/// One release from a release index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    pub version: Version,
+    /// The LTS line's codename, for long-term-support releases.
+    pub lts: Option<String>,
+}
+
+/// The plugin names Node goes by: `nodejs` in asdf, `node` in mise.
+pub fn is_node(plugin: &str) -> bool {
+    matches!(plugin, "nodejs" | "node")
+}
+
+/// Reads Node's `index.json`: `[{"version": "v22.11.0", "lts": "Jod"},
+/// {"version": "v23.1.0", "lts": false}, ...]`.
+pub fn parse_node_index(json: &str) -> Result<Vec<Release>, String> {
+    #[derive(Deserialize)]
+    struct Entry {
+        version: String,
+        #[serde(default)]
+        lts: serde_json::Value,
+    }
+
+    let entries: Vec<Entry> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| {
+            let version = entry.version.strip_prefix('v').unwrap_or(&entry.version);
+            Some(Release {
+                version: Version::parse(version).ok()?,
+                lts: entry.lts.as_str().map(str::to_owned),
+            })
+        })
+        .collect())
+}
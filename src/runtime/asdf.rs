@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::exec::Cmd;
+
+use super::VersionManager;
+
+/// How asdf is run and which command set it speaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Flavor {
+    /// A checkout before 0.16 whose `asdf.sh` is sourced into bash, the
+    /// way the playbook ran it.
+    Sourced(PathBuf),
+    /// An `asdf` on `$PATH` before 0.16 (`plugin-add`, `global`).
+    Shell,
+    /// The Go rewrite, 0.16 and later (`plugin add`, `set --home`).
+    Go,
+}
+
+/// asdf, in any of its [`Flavor`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Asdf {
+    flavor: Flavor,
+}
+
+impl Asdf {
+    pub fn new(flavor: Flavor) -> Self {
+        Self { flavor }
+    }
+
+    /// The checkout at `dir` while it has an `asdf.sh`, else the `asdf` on
+    /// `$PATH` with the command set its version calls for. Before either
+    /// exists (early in a bootstrap) it is the checkout that will be
+    /// cloned.
+    pub fn detect(cx: &Context, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        if dir.join("asdf.sh").exists() || cx.which("asdf").is_none() {
+            return Ok(Self::new(Flavor::Sourced(dir)));
+        }
+        let version = cx.cmd("asdf").arg("--version").output()?.stdout;
+        let go = parse_version(&version).is_some_and(|v| v >= Version::new(0, 16, 0));
+        Ok(Self::new(if go { Flavor::Go } else { Flavor::Shell }))
+    }
+
+    pub fn flavor(&self) -> &Flavor {
+        &self.flavor
+    }
+
+    fn cmd(&self, cx: &Context) -> Cmd {
+        match &self.flavor {
+            Flavor::Sourced(dir) => cx
+                .cmd("bash")
+                .args(["-c", r#"source "$ASDF_DIR/asdf.sh" && asdf "$@""#, "asdf"])
+                .env("ASDF_DIR", dir.as_os_str()),
+            Flavor::Shell | Flavor::Go => cx.cmd("asdf"),
+        }
+    }
+
+    /// Whether there is anything to ask yet.
+    fn present(&self) -> bool {
+        match &self.flavor {
+            Flavor::Sourced(dir) => dir.join("asdf.sh").exists(),
+            Flavor::Shell | Flavor::Go => true,
+        }
+    }
+
+    fn dir(&self) -> Option<&Path> {
+        match &self.flavor {
+            Flavor::Sourced(dir) => Some(dir),
+            Flavor::Shell | Flavor::Go => None,
+        }
+    }
+}
+
+impl VersionManager for Asdf {
+    fn name(&self) -> String {
+        "asdf".into()
+    }
+
+    fn plugins(&self, cx: &Context) -> Result<Vec<String>> {
+        if !self.present() {
+            return Ok(Vec::new());
+        }
+        let cmd = match self.flavor {
+            Flavor::Go => self.cmd(cx).args(["plugin", "list"]),
+            _ => self.cmd(cx).arg("plugin-list"),
+        };
+        let out = cmd.output()?;
+        // With no plugins, both versions complain and exit non-zero.
+        Ok(out
+            .stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.contains(' '))
+            .map(str::to_owned)
+            .collect())
+    }
+
+    fn add_plugin(&self, cx: &Context, plugin: &str, url: Option<&str>) -> Result<()> {
+        let cmd = match self.flavor {
+            Flavor::Go => self.cmd(cx).args(["plugin", "add"]),
+            _ => self.cmd(cx).arg("plugin-add"),
+        };
+        let cmd = cmd.arg(plugin).args(url);
+        cmd.output()?.check(&cmd)?;
+        Ok(())
+    }
+
+    fn installed(&self, cx: &Context, plugin: &str) -> Result<Vec<String>> {
+        if !self.present() {
+            return Ok(Vec::new());
+        }
+        let list = self.cmd(cx).args(["list", plugin]).output()?;
+        if !list.success() {
+            return Ok(Vec::new());
+        }
+        Ok(list
+            .stdout
+            .lines()
+            .map(|line| line.trim().trim_start_matches('*').trim().to_owned())
+            .filter(|version| !version.is_empty() && !version.contains(' '))
+            .collect())
+    }
+
+    fn install(&self, cx: &Context, plugin: &str, version: &str) -> Result<()> {
+        // asdf-nodejs before its Go-era releases verified downloads with
+        // the Node release team's keys, which it had to import first.
+        if let Some(dir) = self.dir() {
+            let keyring = dir
+                .join("plugins")
+                .join(plugin)
+                .join("bin/import-release-team-keyring");
+            if keyring.exists() {
+                cx.cmd("bash").arg(keyring.to_string_lossy()).status()?;
+            }
+        }
+        self.cmd(cx).args(["install", plugin, version]).status()
+    }
+
+    fn global(&self, cx: &Context, plugin: &str) -> Result<Option<String>> {
+        if !self.present() {
+            return Ok(None);
+        }
+        let current = self.cmd(cx).args(["current", plugin]).output()?;
+        if !current.success() {
+            return Ok(None);
+        }
+        // `nodejs 20.11.1 ~/.tool-versions`, below a header line in 0.16.
+        Ok(current.stdout.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next() == Some(plugin))
+                .then(|| fields.next())
+                .flatten()
+                .filter(|version| !version.starts_with('_'))
+                .map(str::to_owned)
+        }))
+    }
+
+    fn set_global(&self, cx: &Context, plugin: &str, version: &str) -> Result<()> {
+        let cmd = match self.flavor {
+            Flavor::Go => self.cmd(cx).args(["set", "--home"]),
+            _ => self.cmd(cx).arg("global"),
+        };
+        cmd.args([plugin, version]).status()
+    }
+
+    fn latest(&self, cx: &Context, plugin: &str, prefix: Option<&str>) -> Result<String> {
+        let cmd = self.cmd(cx).args(["latest", plugin]).args(prefix);
+        let latest = cmd.output()?.check(&cmd)?.stdout.trim().to_owned();
+        if latest.is_empty() || latest.contains(char::is_whitespace) {
+            return Err(Error::Output {
+                command: cmd.to_string(),
+                message: format!("expected a version, got {latest:?}"),
+            });
+        }
+        Ok(latest)
+    }
+}
+
+/// The version in `asdf --version`: `v0.15.0-31e8c93` before the
+/// rewrite, `asdf version 0.16.2` or `v0.16.2` after it.
+fn parse_version(output: &str) -> Option<Version> {
+    output.split_whitespace().find_map(|word| {
+        let word = word.strip_prefix('v').unwrap_or(word);
+        let core = word.split(['-', '+']).next()?;
+        Version::parse(core).ok()
+    })
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// Written at the top of every lock file.
+const HEADER: &str = "\
+# The releases trk picked for the version aliases in [runtimes] and
+# .tool-versions. Run `trk runtimes update` to pick again.
+";
+
+/// What each alias (`lts`, `20`, ...) was resolved to, per runtime, so
+/// every machine installs the same release until someone updates it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lock {
+    path: PathBuf,
+    runtimes: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Lock {
+    /// Loads the lock at `path`, or an empty one if there is none yet.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let runtimes = match fs::read_to_string(&path) {
+            Ok(source) => toml::from_str(&source).map_err(|err| Error::Path {
+                path: path.clone(),
+                message: err.to_string().trim_end().to_owned(),
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(path_error(&path, err)),
+        };
+        Ok(Self { path, runtimes })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The release `alias` of `plugin` is locked to.
+    pub fn get(&self, plugin: &str, alias: &str) -> Option<&str> {
+        self.runtimes.get(plugin)?.get(alias).map(String::as_str)
+    }
+
+    /// Locks `alias` of `plugin` to `version`, returning the release it
+    /// was locked to before.
+    pub fn set(&mut self, plugin: &str, alias: &str, version: &str) -> Option<String> {
+        self.runtimes
+            .entry(plugin.to_owned())
+            .or_default()
+            .insert(alias.to_owned(), version.to_owned())
+    }
+
+    /// Drops every alias of `plugin` that is not in `keep`.
+    pub fn retain(&mut self, plugin: &str, keep: &[&str]) {
+        if let Some(aliases) = self.runtimes.get_mut(plugin) {
+            aliases.retain(|alias, _| keep.contains(&alias.as_str()));
+            if aliases.is_empty() {
+                self.runtimes.remove(plugin);
+            }
+        }
+    }
+
+    /// Drops the runtimes that are not in `keep`.
+    pub fn retain_plugins(&mut self, keep: &[&str]) {
+        self.runtimes
+            .retain(|plugin, _| keep.contains(&plugin.as_str()));
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir).map_err(|err| path_error(dir, err))?;
+        let body = toml::to_string(&self.runtimes).expect("lock serializes");
+        fs::write(&self.path, format!("{HEADER}\n{body}"))
+            .map_err(|err| path_error(&self.path, err))
+    }
+}
+
+fn path_error(path: &Path, err: io::Error) -> Error {
+    Error::Path {
+        path: path.to_owned(),
+        message: err.to_string(),
+    }
+}
@@ -0,0 +1,123 @@
+use serde_json::Value;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+
+use super::VersionManager;
+
+/// mise, which installs tools from its registry without adding plugins
+/// and keeps global versions in `~/.config/mise/config.toml`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mise;
+
+impl VersionManager for Mise {
+    fn name(&self) -> String {
+        "mise".into()
+    }
+
+    fn plugins(&self, cx: &Context) -> Result<Vec<String>> {
+        if cx.which("mise").is_none() {
+            return Ok(Vec::new());
+        }
+        let cmd = cx.cmd("mise").args(["plugins", "ls"]);
+        let out = cmd.output()?.check(&cmd)?;
+        Ok(out
+            .stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// Tools in mise's registry need no plugin; others do.
+    fn has_plugin(&self, cx: &Context, plugin: &str) -> Result<bool> {
+        if cx.which("mise").is_none() {
+            return Ok(false);
+        }
+        if self.plugins(cx)?.iter().any(|p| p == plugin) {
+            return Ok(true);
+        }
+        cx.cmd("mise").args(["registry", plugin]).succeeds()
+    }
+
+    fn add_plugin(&self, cx: &Context, plugin: &str, url: Option<&str>) -> Result<()> {
+        let cmd = cx
+            .cmd("mise")
+            .args(["plugins", "install", plugin])
+            .args(url);
+        cmd.output()?.check(&cmd)?;
+        Ok(())
+    }
+
+    fn installed(&self, cx: &Context, plugin: &str) -> Result<Vec<String>> {
+        if cx.which("mise").is_none() {
+            return Ok(Vec::new());
+        }
+        let cmd = cx.cmd("mise").args(["ls", "--installed", "--json", plugin]);
+        let out = cmd.output()?;
+        if !out.success() {
+            return Ok(Vec::new());
+        }
+        let json: Value = serde_json::from_str(&out.stdout).map_err(|err| Error::Output {
+            command: cmd.to_string(),
+            message: err.to_string(),
+        })?;
+        // An array for one tool; older releases key it by the tool's name.
+        let entries = match &json {
+            Value::Object(tools) => tools.get(plugin).cloned().unwrap_or_default(),
+            other => other.clone(),
+        };
+        Ok(entries
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry["version"].as_str())
+            .map(str::to_owned)
+            .collect())
+    }
+
+    fn install(&self, cx: &Context, plugin: &str, version: &str) -> Result<()> {
+        cx.cmd("mise")
+            .args(["install", &format!("{plugin}@{version}")])
+            .status()
+    }
+
+    fn global(&self, cx: &Context, plugin: &str) -> Result<Option<String>> {
+        if cx.which("mise").is_none() {
+            return Ok(None);
+        }
+        // Run from home so a project's mise.toml does not get in the way.
+        let out = cx
+            .cmd("mise")
+            .args(["current", plugin])
+            .cwd(&cx.home)
+            .output()?;
+        Ok(out
+            .success()
+            .then(|| out.stdout.split_whitespace().next().map(str::to_owned))
+            .flatten())
+    }
+
+    fn set_global(&self, cx: &Context, plugin: &str, version: &str) -> Result<()> {
+        cx.cmd("mise")
+            .args(["use", "--global", &format!("{plugin}@{version}")])
+            .status()
+    }
+
+    fn latest(&self, cx: &Context, plugin: &str, prefix: Option<&str>) -> Result<String> {
+        let tool = match prefix {
+            Some(prefix) => format!("{plugin}@{prefix}"),
+            None => plugin.to_owned(),
+        };
+        let cmd = cx.cmd("mise").args(["latest", &tool]);
+        let latest = cmd.output()?.check(&cmd)?.stdout.trim().to_owned();
+        if latest.is_empty() || latest.contains(char::is_whitespace) {
+            return Err(Error::Output {
+                command: cmd.to_string(),
+                message: format!("expected a version, got {latest:?}"),
+            });
+        }
+        Ok(latest)
+    }
+}
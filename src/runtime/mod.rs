@@ -0,0 +1,182 @@
+//! Language runtime version managers.
+//!
+//! The runtime steps only talk to a [`VersionManager`], so `[runtimes]`
+//! and `.tool-versions` work the same whether the machine uses asdf (the
+//! shell version before 0.16 or the Go rewrite) or mise. Which one is
+//! picked with `version_manager` in the manifest.
+//!
+//! Versions may be [`Alias`]es such as `lts` or `20`. The release each
+//! one resolves to is kept in a [`Lock`], so machines agree until someone
+//! runs `trk runtimes update`.
+
+mod alias;
+mod asdf;
+mod lock;
+mod mise;
+
+use std::collections::BTreeMap;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::manifest::{self, Manager};
+
+pub use alias::{is_node, parse_node_index, Alias, Release, NODE_INDEX};
+pub use asdf::{Asdf, Flavor};
+pub use lock::Lock;
+pub use mise::Mise;
+
+/// Name of the lock file, next to the dotfiles `osx/trk.toml` or in
+/// `~/.trk/state`.
+pub const LOCK_FILE: &str = "trk.lock";
+
+/// The operations trk needs from a version manager.
+pub trait VersionManager {
+    /// The manager's name, for step descriptions.
+    fn name(&self) -> String;
+
+    /// Plugins the manager has added.
+    fn plugins(&self, cx: &Context) -> Result<Vec<String>>;
+
+    /// Whether `plugin` can install versions without adding it first.
+    fn has_plugin(&self, cx: &Context, plugin: &str) -> Result<bool> {
+        Ok(self.plugins(cx)?.iter().any(|p| p == plugin))
+    }
+
+    /// Adds `plugin`, from `url` when it is not in the manager's index.
+    /// Failures are returned with the manager's own message.
+    fn add_plugin(&self, cx: &Context, plugin: &str, url: Option<&str>) -> Result<()>;
+
+    /// Installed versions of `plugin`.
+    fn installed(&self, cx: &Context, plugin: &str) -> Result<Vec<String>>;
+
+    fn install(&self, cx: &Context, plugin: &str, version: &str) -> Result<()>;
+
+    /// The version of `plugin` a new shell gets, if one is set.
+    fn global(&self, cx: &Context, plugin: &str) -> Result<Option<String>>;
+
+    fn set_global(&self, cx: &Context, plugin: &str, version: &str) -> Result<()>;
+
+    /// The newest available version of `plugin`, optionally starting with
+    /// `prefix` (`"20"` for the newest Node 20).
+    fn latest(&self, cx: &Context, plugin: &str, prefix: Option<&str>) -> Result<String>;
+}
+
+/// The version manager the manifest asks for.
+pub fn manager(cx: &Context) -> Result<Box<dyn VersionManager>> {
+    Ok(match cx.manifest.version_manager() {
+        Manager::Asdf => Box::new(Asdf::detect(cx, cx.asdf_dir())?),
+        Manager::Mise => Box::new(Mise),
+    })
+}
+
+/// A version from the manifest with its aliases resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub version: String,
+    /// The alias as written, when the version came from one.
+    pub alias: Option<String>,
+    /// False for an alias that is not locked yet and was not looked up;
+    /// `version` is then the alias itself.
+    pub locked: bool,
+}
+
+/// Resolves the aliases among `versions` of `plugin`: from `lock` when it
+/// has them, otherwise from upstream, recording the result in `lock`.
+/// Without `upstream`, aliases that are not locked are left as written.
+pub fn resolve(
+    cx: &Context,
+    manager: &dyn VersionManager,
+    lock: &mut Lock,
+    plugin: &str,
+    versions: &[String],
+    upstream: bool,
+) -> Result<Vec<Resolved>> {
+    let mut resolved = Vec::with_capacity(versions.len());
+    for written in versions {
+        let Some(alias) = Alias::parse(written).map_err(|message| Error::Runtime {
+            plugin: plugin.to_owned(),
+            message,
+        })?
+        else {
+            resolved.push(Resolved {
+                version: written.clone(),
+                alias: None,
+                locked: true,
+            });
+            continue;
+        };
+        let version = match lock.get(plugin, written) {
+            Some(version) => Some(version.to_owned()),
+            None if upstream => {
+                let version = alias.resolve(cx, manager, plugin)?;
+                lock.set(plugin, written, &version);
+                Some(version)
+            }
+            None => None,
+        };
+        resolved.push(Resolved {
+            locked: version.is_some(),
+            version: version.unwrap_or_else(|| written.clone()),
+            alias: Some(written.clone()),
+        });
+    }
+    Ok(resolved)
+}
+
+/// One alias picked again by [`update`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Update {
+    pub plugin: String,
+    pub alias: String,
+    /// What the alias was locked to before, if anything.
+    pub before: Option<String>,
+    pub version: String,
+}
+
+/// Resolves every alias in `runtimes` (or in those named in `only`) again
+/// and saves the results to the lock, dropping entries nothing uses any
+/// more. Nothing is installed.
+pub fn update(
+    cx: &Context,
+    runtimes: &BTreeMap<String, manifest::Runtime>,
+    only: &[String],
+) -> Result<Vec<Update>> {
+    let manager = manager(cx)?;
+    let mut lock = Lock::load(cx.runtimes_lock())?;
+    let before = lock.clone();
+    if only.is_empty() {
+        let keep: Vec<_> = runtimes.keys().map(String::as_str).collect();
+        lock.retain_plugins(&keep);
+    }
+    let mut updates = Vec::new();
+    for (plugin, runtime) in runtimes {
+        if !only.is_empty() && !only.contains(plugin) {
+            continue;
+        }
+        let mut aliases = Vec::new();
+        for written in &runtime.versions {
+            let alias = Alias::parse(written).map_err(|message| Error::Runtime {
+                plugin: plugin.clone(),
+                message,
+            })?;
+            if let Some(alias) = alias {
+                aliases.push((written.as_str(), alias));
+            }
+        }
+        let keep: Vec<_> = aliases.iter().map(|(written, _)| *written).collect();
+        lock.retain(plugin, &keep);
+        for (written, alias) in aliases {
+            let version = alias.resolve(cx, manager.as_ref(), plugin)?;
+            updates.push(Update {
+                plugin: plugin.clone(),
+                alias: written.to_owned(),
+                before: lock.set(plugin, written, &version),
+                version,
+            });
+        }
+    }
+    if lock != before {
+        lock.save()?;
+    }
+    Ok(updates)
+}
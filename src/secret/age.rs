@@ -0,0 +1,127 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::exec::Cmd;
+
+use super::{missing, Items, SecretProvider};
+
+/// A local file per vault, encrypted with age to the recipient of one
+/// identity: `<age_dir>/<vault>.age`, holding the items as JSON. The
+/// paths come from `[secrets]` in trk.toml.
+#[derive(Debug, Clone)]
+pub struct AgeFile {
+    cx: Context,
+    vault: String,
+}
+
+impl AgeFile {
+    pub fn new(cx: &Context, vault: impl Into<String>) -> Self {
+        Self {
+            cx: cx.clone(),
+            vault: vault.into(),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.cx
+            .expand(self.cx.manifest.secrets.age_dir())
+            .join(format!("{}.age", self.vault))
+    }
+
+    /// The identity file, which has to exist.
+    fn identity(&self) -> Result<String> {
+        let identity = self.cx.expand(self.cx.manifest.secrets.age_identity());
+        if !identity.is_file() {
+            return Err(self.error(format!(
+                "no age identity at {0}; create one with `age-keygen -o {0}`",
+                identity.display()
+            )));
+        }
+        Ok(identity.to_string_lossy().into_owned())
+    }
+
+    /// Everything in the vault; nothing before the first store.
+    fn items(&self) -> Result<Items> {
+        let path = self.path();
+        if !path.exists() {
+            return Ok(Items::new());
+        }
+        let decrypt = self.cx.cmd("age").args([
+            "--decrypt",
+            "-i",
+            &self.identity()?,
+            &path.to_string_lossy(),
+        ]);
+        let out = self.run(decrypt)?;
+        serde_json::from_str(&out)
+            .map_err(|err| self.error(format!("{} is not a trk vault: {err}", path.display())))
+    }
+
+    /// Runs `cmd` and returns its stdout, with failures as [`Error::Secret`].
+    fn run(&self, cmd: Cmd) -> Result<String> {
+        match cmd.output().and_then(|out| out.check(&cmd)) {
+            Ok(out) => Ok(out.stdout),
+            Err(err) => Err(self.error(err.to_string())),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Secret {
+            provider: self.name().into(),
+            message,
+        }
+    }
+}
+
+impl SecretProvider for AgeFile {
+    fn name(&self) -> &str {
+        "age"
+    }
+
+    fn resolve(&self, item: &str, field: &str) -> Result<String> {
+        self.items()?
+            .get(item)
+            .and_then(|fields| fields.get(field))
+            .cloned()
+            .ok_or_else(|| missing(self.name(), item, field))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.items()?.into_keys().collect())
+    }
+
+    /// Encrypts to a file next to the vault and renames it over the vault,
+    /// so a failure leaves the old one whole. The plain text goes through
+    /// stdin and never touches the disk.
+    fn store(&self, item: &str, fields: &[(&str, &str)]) -> Result<()> {
+        let mut items = self.items()?;
+        items.insert(
+            item.into(),
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        );
+        let plain = serde_json::to_string_pretty(&items).expect("items serialize");
+
+        let path = self.path();
+        let dir = path.parent().expect("the vault is in a directory");
+        fs::create_dir_all(dir).map_err(|err| self.error(format!("{}: {err}", dir.display())))?;
+        let partial = path.with_extension("age.partial");
+        self.run(
+            self.cx
+                .cmd("age")
+                .args([
+                    "--encrypt",
+                    "-i",
+                    &self.identity()?,
+                    "-o",
+                    &partial.to_string_lossy(),
+                ])
+                .stdin(plain),
+        )?;
+        fs::rename(&partial, &path).map_err(|err| self.error(format!("{}: {err}", path.display())))
+    }
+}
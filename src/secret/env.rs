@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use crate::error::{Error, Result};
+
+use super::SecretProvider;
+
+/// Environment variables, for CI and other places that hand secrets over
+/// that way: field `token` of item `github` in vault `ci` is `$CI_GITHUB_TOKEN`.
+/// Read-only.
+#[derive(Debug, Clone)]
+pub struct Env {
+    vault: String,
+    vars: BTreeMap<String, String>,
+}
+
+impl Env {
+    /// Reads trk's own environment.
+    pub fn new(vault: impl Into<String>) -> Self {
+        Self::with_vars(vault, env::vars())
+    }
+
+    /// Reads `vars` instead of the environment.
+    pub fn with_vars(
+        vault: impl Into<String>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        Self {
+            vault: vault.into(),
+            vars: vars.into_iter().collect(),
+        }
+    }
+
+    /// `VAULT_ITEM_FIELD`, with anything but letters and digits as `_`.
+    pub fn var(&self, item: &str, field: &str) -> String {
+        variable(&[&self.vault, item, field])
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Secret {
+            provider: self.name().into(),
+            message,
+        }
+    }
+}
+
+impl SecretProvider for Env {
+    fn name(&self) -> &str {
+        "env"
+    }
+
+    fn resolve(&self, item: &str, field: &str) -> Result<String> {
+        let var = self.var(item, field);
+        self.vars
+            .get(&var)
+            .cloned()
+            .ok_or_else(|| self.error(format!("`{var}` is not set")))
+    }
+
+    /// The environment has no items, so each variable of the vault lists
+    /// as `item_field`, in lower case.
+    fn list(&self) -> Result<Vec<String>> {
+        let prefix = format!("{}_", variable(&[&self.vault]));
+        Ok(self
+            .vars
+            .keys()
+            .filter_map(|var| var.strip_prefix(&prefix))
+            .map(str::to_lowercase)
+            .collect())
+    }
+
+    fn store(&self, item: &str, _fields: &[(&str, &str)]) -> Result<()> {
+        Err(self.error(format!(
+            "environment variables are read-only; store `{item}` with another provider"
+        )))
+    }
+}
+
+fn variable(parts: &[&str]) -> String {
+    parts
+        .join("_")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
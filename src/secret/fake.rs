@@ -0,0 +1,59 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::error::Result;
+
+use super::{missing, Items, SecretProvider};
+
+/// An in-memory [`SecretProvider`] for tests.
+#[derive(Debug, Default)]
+pub struct FakeProvider {
+    items: Mutex<Items>,
+}
+
+impl FakeProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `item` with `fields`, as if stored earlier.
+    pub fn with(self, item: &str, fields: &[(&str, &str)]) -> Self {
+        self.store(item, fields).expect("the fake stores anything");
+        self
+    }
+
+    /// Everything stored so far.
+    pub fn items(&self) -> Items {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Items> {
+        self.items.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl SecretProvider for FakeProvider {
+    fn name(&self) -> &str {
+        "fake"
+    }
+
+    fn resolve(&self, item: &str, field: &str) -> Result<String> {
+        self.lock()
+            .get(item)
+            .and_then(|fields| fields.get(field))
+            .cloned()
+            .ok_or_else(|| missing(self.name(), item, field))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.lock().keys().cloned().collect())
+    }
+
+    fn store(&self, item: &str, fields: &[(&str, &str)]) -> Result<()> {
+        let fields = fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.lock().insert(item.into(), fields);
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+//! Where trk keeps secrets such as SSH keys: a password manager reached
+//! through its CLI, an age-encrypted file or the environment, behind
+//! [`SecretProvider`] so the rest of trk does not care which one.
+//!
+//! A secret is a field of a named item in a vault, like a 1Password
+//! item's fields, and is written as a [`Reference`] such as
+//! `op://Private/github/token`. Manifest values and rendered playbook
+//! templates may contain references; [`expand`] replaces them with the
+//! secrets.
+
+mod age;
+mod env;
+mod fake;
+mod op;
+mod pass;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+
+pub use age::AgeFile;
+pub use env::Env;
+pub use fake::FakeProvider;
+pub use op::{OnePassword, OpSession};
+pub use pass::Pass;
+
+/// A store of secrets. Implement it to keep secrets somewhere trk does
+/// not know about; everything that uses secrets takes a
+/// `&dyn SecretProvider`.
+pub trait SecretProvider: fmt::Debug + Send + Sync {
+    /// Short name for messages, e.g. `op`.
+    fn name(&self) -> &str;
+
+    /// The value of `field` in `item`; a missing item or field is an
+    /// error.
+    fn resolve(&self, item: &str, field: &str) -> Result<String>;
+
+    /// The names of the items, sorted.
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// Saves `fields` as `item`, replacing an item with that name.
+    fn store(&self, item: &str, fields: &[(&str, &str)]) -> Result<()>;
+}
+
+/// The providers trk can use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// 1Password, with the `op` CLI signed in.
+    #[default]
+    Op,
+    /// The standard Unix password manager, one entry per field.
+    Pass,
+    /// A file per vault encrypted with age, see `[secrets]` in trk.toml.
+    Age,
+    /// Environment variables named `VAULT_ITEM_FIELD`; read-only.
+    Env,
+}
+
+/// `op://vault/item/field`: a secret, by provider, vault, item and field.
+/// The field may contain `/`, as 1Password's `section/field` does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub backend: Backend,
+    pub vault: String,
+    pub item: String,
+    pub field: String,
+}
+
+impl Backend {
+    const ALL: [Backend; 4] = [Backend::Op, Backend::Pass, Backend::Age, Backend::Env];
+
+    /// The provider, keeping its items in `vault`.
+    pub fn provider(self, cx: &Context, vault: &str) -> Box<dyn SecretProvider> {
+        match self {
+            Backend::Op => Box::new(OnePassword::new(cx, vault)),
+            Backend::Pass => Box::new(Pass::new(cx, vault)),
+            Backend::Age => Box::new(AgeFile::new(cx, vault)),
+            Backend::Env => Box::new(Env::new(vault)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Op => "op",
+            Backend::Pass => "pass",
+            Backend::Age => "age",
+            Backend::Env => "env",
+        })
+    }
+}
+
+impl FromStr for Reference {
+    type Err = Error;
+
+    fn from_str(reference: &str) -> Result<Self> {
+        let invalid = |message: &str| Error::Secret {
+            provider: "reference".into(),
+            message: format!("`{reference}` {message}"),
+        };
+        let (scheme, path) = reference
+            .split_once("://")
+            .ok_or_else(|| invalid("is not a reference like op://vault/item/field"))?;
+        let backend = Backend::ALL
+            .into_iter()
+            .find(|backend| backend.to_string() == scheme)
+            .ok_or_else(|| invalid("names no known provider (op, pass, age, env)"))?;
+        let mut parts = path.splitn(3, '/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(vault), Some(item), Some(field))
+                if !vault.is_empty() && !item.is_empty() && !field.is_empty() =>
+            {
+                Ok(Reference {
+                    backend,
+                    vault: vault.into(),
+                    item: item.into(),
+                    field: field.into(),
+                })
+            }
+            _ => Err(invalid("needs a vault, an item and a field")),
+        }
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}/{}/{}",
+            self.backend, self.vault, self.item, self.field
+        )
+    }
+}
+
+/// The references in `text`, with where they are. A reference starts
+/// with a provider's `scheme://` after anything but a letter or digit and
+/// ends at whitespace, a quote, a bracket or one of `,;@`.
+pub fn references(text: &str) -> Vec<(Range<usize>, Reference)> {
+    let ends = |c: char| c.is_whitespace() || "\"'`<>()[]{},;@".contains(c);
+    let mut found = Vec::new();
+    let mut at = 0;
+    while let Some(offset) = text[at..].find("://") {
+        let colon = at + offset;
+        let start = text[..colon]
+            .rfind(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(0, |i| i + 1);
+        let end = text[colon..].find(ends).map_or(text.len(), |i| colon + i);
+        at = colon + 3;
+        if let Ok(reference) = text[start..end].parse() {
+            found.push((start..end, reference));
+            at = end;
+        }
+    }
+    found
+}
+
+/// `text` with every reference in it replaced by the secret it names.
+pub fn expand(cx: &Context, text: &str) -> Result<String> {
+    let found = references(text);
+    if found.is_empty() {
+        return Ok(text.to_owned());
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, reference) in found {
+        out.push_str(&text[last..range.start]);
+        out.push_str(&cx.secret(&reference)?);
+        last = range.end;
+    }
+    out.push_str(&text[last..]);
+    Ok(out)
+}
+
+/// Items by name, then fields by name, for providers that keep them
+/// together.
+type Items = BTreeMap<String, BTreeMap<String, String>>;
+
+/// The error for a field an item does not have, or an item that is not
+/// there.
+fn missing(provider: &str, item: &str, field: &str) -> Error {
+    Error::Secret {
+        provider: provider.into(),
+        message: format!("no field `{field}` in item `{item}`"),
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::exec::Cmd;
+
+use super::SecretProvider;
+
+/// How often a session trk signed in to is used to keep it alive;
+/// 1Password ends one after 30 minutes without a command.
+const REFRESH: Duration = Duration::from_secs(10 * 60);
+
+/// 1Password, through its `op` CLI. Items are Secure Notes whose fields
+/// are concealed, in one vault.
+#[derive(Debug, Clone)]
+pub struct OnePassword {
+    cx: Context,
+    vault: String,
+}
+
+impl OnePassword {
+    pub fn new(cx: &Context, vault: impl Into<String>) -> Self {
+        Self {
+            cx: cx.clone(),
+            vault: vault.into(),
+        }
+    }
+
+    fn op(&self) -> Result<Cmd> {
+        self.cx.op_session().op(&self.cx)
+    }
+
+    /// Runs `cmd` and returns its stdout, with failures as [`Error::Secret`].
+    fn run(&self, cmd: Cmd) -> Result<String> {
+        match cmd.output().and_then(|out| out.check(&cmd)) {
+            Ok(out) => Ok(out.stdout),
+            Err(err) => Err(self.error(err.to_string())),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Secret {
+            provider: self.name().into(),
+            message,
+        }
+    }
+
+    /// The id of the item called `item`, if there is one.
+    fn existing(&self, item: &str) -> Result<Option<String>> {
+        let get = self.op()?.args([
+            "item",
+            "get",
+            item,
+            "--vault",
+            &self.vault,
+            "--format",
+            "json",
+        ]);
+        let out = get.output()?;
+        if !out.success() {
+            return Ok(None);
+        }
+        let found: Value = serde_json::from_str(&out.stdout)
+            .map_err(|err| self.error(format!("unexpected output from `{get}`: {err}")))?;
+        Ok(found["id"].as_str().map(str::to_owned))
+    }
+}
+
+impl SecretProvider for OnePassword {
+    fn name(&self) -> &str {
+        "op"
+    }
+
+    fn resolve(&self, item: &str, field: &str) -> Result<String> {
+        let reference = format!("op://{}/{item}/{field}", self.vault);
+        self.run(
+            self.op()?
+                .args(["read", "--no-newline", &reference])
+                .sensitive(),
+        )
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let list = self
+            .op()?
+            .args(["item", "list", "--vault", &self.vault, "--format", "json"]);
+        let out = self.run(list.clone())?;
+        let items: Vec<Value> = serde_json::from_str(&out)
+            .map_err(|err| self.error(format!("unexpected output from `{list}`: {err}")))?;
+        let mut titles: Vec<String> = items
+            .iter()
+            .filter_map(|item| item["title"].as_str().map(str::to_owned))
+            .collect();
+        titles.sort();
+        Ok(titles)
+    }
+
+    /// Creates the new item before deleting the old one, so a failure
+    /// leaves the old one in place. Values go through stdin, never argv.
+    fn store(&self, item: &str, fields: &[(&str, &str)]) -> Result<()> {
+        let old = self.existing(item)?;
+        let template = json!({
+            "title": item,
+            "category": "SECURE_NOTE",
+            "fields": fields
+                .iter()
+                .map(|(name, value)| json!({
+                    "id": name,
+                    "label": name,
+                    "type": "CONCEALED",
+                    "value": value,
+                }))
+                .collect::<Vec<_>>(),
+        });
+        self.run(
+            self.op()?
+                .args(["item", "create", "--vault", &self.vault, "-"])
+                .stdin(template.to_string()),
+        )?;
+        if let Some(id) = old {
+            self.run(
+                self.op()?
+                    .args(["item", "delete", &id, "--vault", &self.vault]),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The run's 1Password CLI session, shared by every [`OnePassword`].
+///
+/// When `op` is not signed in already, by the 1Password app's CLI
+/// integration or an exported `OP_SESSION_*`, trk runs `op signin` the
+/// first time it needs a secret. The token stays in memory, reaches `op`
+/// through its environment rather than its arguments, and is kept alive
+/// with `op whoami` every ten minutes until this is dropped.
+pub struct OpSession {
+    state: Mutex<SessionState>,
+}
+
+struct SessionState {
+    refresh: Duration,
+    /// `op` was found signed in, or trk signed it in.
+    ready: bool,
+    /// `OP_SESSION_<account>` and its token, when trk signed in.
+    token: Option<(String, String)>,
+    stop: Option<Sender<()>>,
+    keepalive: Option<JoinHandle<()>>,
+}
+
+impl OpSession {
+    /// A session not signed in yet, kept alive every `refresh` once it is.
+    pub fn new(refresh: Duration) -> Self {
+        Self {
+            state: Mutex::new(SessionState {
+                refresh,
+                ready: false,
+                token: None,
+                stop: None,
+                keepalive: None,
+            }),
+        }
+    }
+
+    /// How often a session signed in from now on is kept alive.
+    pub fn refresh_every(&self, every: Duration) {
+        self.lock().refresh = every;
+    }
+
+    /// Whether trk signed in itself and holds a token.
+    pub fn signed_in(&self) -> bool {
+        self.lock().token.is_some()
+    }
+
+    /// An `op` command that runs in the session, signing in first if `op`
+    /// is not signed in yet.
+    pub fn op(&self, cx: &Context) -> Result<Cmd> {
+        let mut state = self.lock();
+        if !state.ready {
+            if !cx.cmd("op").arg("whoami").succeeds()? {
+                state.token = sign_in(cx)?;
+                if let Some((var, token)) = &state.token {
+                    cx.redactor().register(token);
+                    let whoami = cx.cmd("op").arg("whoami").env(var, token);
+                    let (stop, stopped) = mpsc::channel();
+                    let refresh = state.refresh;
+                    state.stop = Some(stop);
+                    state.keepalive = Some(thread::spawn(move || {
+                        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(refresh) {
+                            // An expired session shows up in the next
+                            // `op` command's error.
+                            let _ = whoami.output();
+                        }
+                    }));
+                }
+            }
+            state.ready = true;
+        }
+        let op = cx.cmd("op");
+        Ok(match &state.token {
+            Some((var, token)) => op.env(var, token),
+            None => op,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for OpSession {
+    fn default() -> Self {
+        Self::new(REFRESH)
+    }
+}
+
+/// Never shows the token.
+impl fmt::Debug for OpSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpSession")
+            .field("signed_in", &self.signed_in())
+            .finish()
+    }
+}
+
+impl Drop for OpSession {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        drop(state.stop.take());
+        if let Some(keepalive) = state.keepalive.take() {
+            let _ = keepalive.join();
+        }
+    }
+}
+
+/// Runs `op signin`, which asks for the account password on the terminal,
+/// and picks the session variable out of the `export OP_SESSION_...=`
+/// line it prints. With the app's CLI integration it prints none and there
+/// is no token to hold.
+fn sign_in(cx: &Context) -> Result<Option<(String, String)>> {
+    let mut signin = cx.cmd("op").arg("signin");
+    if let Some(account) = &cx.manifest.secrets.op_account {
+        signin = signin.args(["--account", account.as_str()]);
+    }
+    let out = signin.sensitive().prompt().map_err(|err| Error::Secret {
+        provider: "op".into(),
+        message: format!("could not sign in to 1Password: {err}"),
+    })?;
+    Ok(out.stdout.lines().find_map(|line| {
+        let (var, token) = line.trim().strip_prefix("export ")?.split_once('=')?;
+        var.starts_with("OP_SESSION_")
+            .then(|| (var.to_owned(), token.trim_matches('"').to_owned()))
+    }))
+}
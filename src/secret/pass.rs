@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::exec::Cmd;
+
+use super::SecretProvider;
+
+/// pass, the standard Unix password manager. A field is the entry
+/// `<vault>/<item>/<field>` in the password store.
+#[derive(Debug, Clone)]
+pub struct Pass {
+    cx: Context,
+    vault: String,
+}
+
+impl Pass {
+    pub fn new(cx: &Context, vault: impl Into<String>) -> Self {
+        Self {
+            cx: cx.clone(),
+            vault: vault.into(),
+        }
+    }
+
+    /// The password store, from `[secrets]` in trk.toml.
+    fn dir(&self) -> PathBuf {
+        self.cx.expand(self.cx.manifest.secrets.pass_dir())
+    }
+
+    fn pass(&self) -> Cmd {
+        self.cx.cmd("pass").env("PASSWORD_STORE_DIR", self.dir())
+    }
+
+    fn entry(&self, item: &str, field: &str) -> String {
+        format!("{}/{item}/{field}", self.vault)
+    }
+
+    /// Runs `cmd` and returns its stdout, with failures as [`Error::Secret`].
+    fn run(&self, cmd: Cmd) -> Result<String> {
+        match cmd.output().and_then(|out| out.check(&cmd)) {
+            Ok(out) => Ok(out.stdout),
+            Err(err) => Err(self.error(err.to_string())),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Secret {
+            provider: self.name().into(),
+            message,
+        }
+    }
+
+    /// The names of the entries in `dir` under the store, without `.gpg`
+    /// for files; nothing if it does not exist.
+    fn entries(&self, dir: PathBuf, files: bool) -> Result<Vec<String>> {
+        let read = match fs::read_dir(&dir) {
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(self.error(format!("{}: {err}", dir.display()))),
+        };
+        let mut names = Vec::new();
+        for entry in read {
+            let entry = entry.map_err(|err| self.error(format!("{}: {err}", dir.display())))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.path().is_dir();
+            match name.strip_suffix(".gpg") {
+                Some(field) if files && !is_dir => names.push(field.to_owned()),
+                None if !files && is_dir && !name.starts_with('.') => names.push(name),
+                _ => {}
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+impl SecretProvider for Pass {
+    fn name(&self) -> &str {
+        "pass"
+    }
+
+    /// Drops the newline `pass insert` adds to a single-line entry, and
+    /// [`Pass::store`] adds to every one.
+    fn resolve(&self, item: &str, field: &str) -> Result<String> {
+        let mut value = self.run(self.pass().args(["show", &self.entry(item, field)]))?;
+        if value.ends_with('\n') {
+            value.pop();
+        }
+        Ok(value)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        self.entries(self.dir().join(&self.vault), false)
+    }
+
+    /// Writes every field before removing the ones the item no longer
+    /// has, so a failure leaves the old values readable. Values go
+    /// through stdin, never argv.
+    fn store(&self, item: &str, fields: &[(&str, &str)]) -> Result<()> {
+        let old = self.entries(self.dir().join(&self.vault).join(item), true)?;
+        for (field, value) in fields {
+            self.run(
+                self.pass()
+                    .args(["insert", "--multiline", "--force", &self.entry(item, field)])
+                    .stdin(format!("{value}\n")),
+            )?;
+        }
+        for field in old {
+            if !fields.iter().any(|(name, _)| *name == field) {
+                self.run(
+                    self.pass()
+                        .args(["rm", "--force", &self.entry(item, &field)]),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
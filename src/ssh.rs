@@ -0,0 +1,429 @@
+//! SSH keys kept in a secret provider: generated with `trk ssh generate`,
+//! saved with `trk ssh put`, and written back to `~/.ssh` on a new machine
+//! with `trk ssh get`, instead of downloading ssh-manager for it.
+//!
+//! A key is an item named after it with the fields [`PRIVATE_KEY`] and
+//! [`PUBLIC_KEY`].
+//!
+//! With `[ssh]` in the manifest, trk also keeps a block of `~/.ssh/config`
+//! for the declared hosts and pins their host keys in
+//! `~/.ssh/known_hosts`, checked against the manifest's fingerprints.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::base64;
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::manifest::{Ssh, SshAgent};
+use crate::secret::SecretProvider;
+
+pub const PRIVATE_KEY: &str = "private_key";
+pub const PUBLIC_KEY: &str = "public_key";
+
+/// The fingerprints GitHub publishes for github.com's host keys (Ed25519,
+/// ECDSA and RSA), used unless the manifest lists its own.
+pub const GITHUB_FINGERPRINTS: [&str; 3] = [
+    "SHA256:+DiY3wvvV6TuJJhbpZisF/zLDA0zPMSvHdkr4UvCOqU",
+    "SHA256:p2QAMXNIC1TJYWeIOttrVc98/R1BUFWu3/LiyKgUfQM",
+    "SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s",
+];
+
+const BEGIN: &str = "# BEGIN trk managed block";
+const END: &str = "# END trk managed block";
+
+/// A host from `[ssh.hosts]`, with github.com's defaults filled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub name: String,
+    pub user: String,
+    /// The key in `~/.ssh` to use.
+    pub identity: Option<String>,
+    /// Host keys to trust; the host is not pinned without any.
+    pub fingerprints: Vec<String>,
+}
+
+/// `~/.ssh/<name>`; the public key is next to it with `.pub` added.
+pub fn key_path(cx: &Context, name: &str) -> PathBuf {
+    cx.home.join(".ssh").join(name)
+}
+
+/// Fetches key `name` from `provider`, writes it to `~/.ssh` and adds it
+/// to the agent. A different key already there is kept unless `force`.
+/// Returns whether the files changed.
+pub fn get(cx: &Context, provider: &dyn SecretProvider, name: &str, force: bool) -> Result<bool> {
+    let path = checked_path(cx, name)?;
+    let private = with_newline(provider.resolve(name, PRIVATE_KEY)?);
+    cx.redactor().register(private.trim_end());
+    let public = with_newline(provider.resolve(name, PUBLIC_KEY)?);
+    let public_path = public_path(&path);
+
+    let same = read(&path)?.as_deref() == Some(private.as_str())
+        && read(&public_path)?.as_deref() == Some(public.as_str());
+    if !same {
+        if path.exists() && !force {
+            return Err(path_error(
+                &path,
+                "a different key is already here; pass --force to replace it",
+            ));
+        }
+        ssh_dir(cx)?;
+        write(&path, &private, 0o600)?;
+        write(&public_path, &public, 0o644)?;
+    }
+    add_to_agent(cx, &path)?;
+    Ok(!same)
+}
+
+/// Saves the key at `~/.ssh/<name>` to `provider`, replacing what it had
+/// under that name.
+pub fn put(cx: &Context, provider: &dyn SecretProvider, name: &str) -> Result<()> {
+    let path = checked_path(cx, name)?;
+    let missing =
+        |path: &Path| path_error(path, "no such key; generate one with `trk ssh generate`");
+    let private = read(&path)?.ok_or_else(|| missing(&path))?;
+    let public_path = public_path(&path);
+    let public = read(&public_path)?.ok_or_else(|| missing(&public_path))?;
+    provider.store(name, &[(PRIVATE_KEY, &private), (PUBLIC_KEY, &public)])
+}
+
+/// Creates an ed25519 key at `~/.ssh/<name>` without a passphrase, saves
+/// it to `provider` and adds it to the agent. An existing key is kept
+/// unless `force`.
+pub fn generate(
+    cx: &Context,
+    provider: &dyn SecretProvider,
+    name: &str,
+    comment: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    let path = checked_path(cx, name)?;
+    if path.exists() {
+        if !force {
+            return Err(path_error(
+                &path,
+                "a key is already here; pass --force to replace it",
+            ));
+        }
+        // ssh-keygen would ask before overwriting.
+        for file in [&path, &public_path(&path)] {
+            match fs::remove_file(file) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(path_error(file, err))
+                }
+                _ => {}
+            }
+        }
+    }
+    ssh_dir(cx)?;
+    let path_arg = path.to_string_lossy();
+    let keygen = cx
+        .cmd("ssh-keygen")
+        .args([
+            "-q", "-t", "ed25519", "-a", "100", "-N", "", "-f", &path_arg,
+        ])
+        .args(comment.map(|comment| ["-C", comment]).into_iter().flatten());
+    keygen.output()?.check(&keygen)?;
+    put(cx, provider, name)?;
+    add_to_agent(cx, &path)
+}
+
+/// The declared hosts, github.com first.
+pub fn hosts(ssh: &Ssh) -> Vec<Host> {
+    let mut hosts = vec![Host {
+        name: "github.com".into(),
+        user: "git".into(),
+        identity: None,
+        fingerprints: GITHUB_FINGERPRINTS.map(String::from).to_vec(),
+    }];
+    for (name, host) in &ssh.hosts {
+        let declared = Host {
+            name: name.clone(),
+            user: host.user().to_owned(),
+            identity: host.identity.as_ref().map(|i| i.as_str().to_owned()),
+            fingerprints: host
+                .fingerprints
+                .iter()
+                .map(|f| f.as_str().to_owned())
+                .collect(),
+        };
+        match hosts.iter_mut().find(|host| host.name == *name) {
+            // github.com keeps GitHub's fingerprints unless given its own.
+            Some(github) => {
+                let published = std::mem::take(&mut github.fingerprints);
+                *github = declared;
+                if github.fingerprints.is_empty() {
+                    github.fingerprints = published;
+                }
+            }
+            None => hosts.push(declared),
+        }
+    }
+    hosts
+}
+
+pub fn config_path(cx: &Context) -> PathBuf {
+    cx.home.join(".ssh/config")
+}
+
+pub fn known_hosts_path(cx: &Context) -> PathBuf {
+    cx.home.join(".ssh/known_hosts")
+}
+
+/// Where the 1Password SSH agent listens.
+fn one_password_socket(cx: &Context) -> &'static str {
+    if cx.facts.is_macos() {
+        "~/Library/Group Containers/2BUA8C4S2C.com.1password/t/agent.sock"
+    } else {
+        "~/.1password/agent.sock"
+    }
+}
+
+/// The key file `IdentityFile` names: the private key for ssh-agent, the
+/// public one for 1Password, which keeps the private key itself.
+pub fn identity_path(cx: &Context, ssh: &Ssh, identity: &str) -> PathBuf {
+    let path = key_path(cx, identity);
+    match ssh.agent() {
+        SshAgent::Keychain => path,
+        SshAgent::OnePassword => public_path(&path),
+    }
+}
+
+/// trk's block of `~/.ssh/config`, markers included. It goes first in the
+/// file, where ssh takes the first value it finds for each option, and
+/// ends with `Host *` so the user's own settings after it apply to every
+/// host as before.
+pub fn config_block(cx: &Context, ssh: &Ssh) -> String {
+    let mut block =
+        format!("{BEGIN}\n# Written by trk from [ssh] in trk.toml; edits here are lost.\n");
+    if ssh.agent() == SshAgent::Keychain {
+        // Only Apple's ssh knows UseKeychain; Homebrew's would refuse it.
+        block.push_str("IgnoreUnknown UseKeychain\n");
+    }
+    for host in hosts(ssh) {
+        block.push_str(&format!("\nHost {}\n  User {}\n", host.name, host.user));
+        if let Some(identity) = &host.identity {
+            let file = identity_path(cx, ssh, identity);
+            let file = match file.strip_prefix(&cx.home) {
+                Ok(rest) => format!("~/{}", rest.display()),
+                Err(_) => file.display().to_string(),
+            };
+            block.push_str(&format!("  IdentityFile {file}\n  IdentitiesOnly yes\n"));
+        }
+        match ssh.agent() {
+            SshAgent::Keychain => {
+                block.push_str("  AddKeysToAgent yes\n");
+                if cx.facts.is_macos() {
+                    block.push_str("  UseKeychain yes\n");
+                }
+            }
+            SshAgent::OnePassword => {
+                block.push_str(&format!(
+                    "  IdentityAgent \"{}\"\n",
+                    one_password_socket(cx)
+                ));
+            }
+        }
+    }
+    block.push_str(&format!("\nHost *\n{END}\n"));
+    block
+}
+
+/// The lines between the markers in `text`, if it has them.
+pub fn managed_block(text: &str) -> Option<&str> {
+    let start = text.find(BEGIN)?;
+    let end = start + text[start..].find(END)? + END.len();
+    Some(&text[start..end])
+}
+
+/// `text` with `block` in place of its managed block, or with `block` put
+/// first or last when it has none.
+pub fn with_block(text: &str, block: &str, first: bool) -> String {
+    let block = block.trim_end_matches('\n');
+    match managed_block(text) {
+        Some(old) => text.replacen(old, block, 1),
+        None if text.is_empty() => format!("{block}\n"),
+        None if first => format!("{block}\n\n{text}"),
+        None => format!("{}\n\n{block}\n", text.trim_end_matches('\n')),
+    }
+}
+
+/// The SHA-256 fingerprint of a host key, as `ssh-keygen -l` prints it,
+/// from its base64 as in `known_hosts`.
+pub fn fingerprint(key: &str) -> Option<String> {
+    let blob = base64::decode(key)?;
+    Some(format!(
+        "SHA256:{}",
+        base64::encode(&Sha256::digest(&blob), base64::STANDARD)
+    ))
+}
+
+/// The `known_hosts` lines in `text` for `host` whose key is one of its
+/// fingerprints.
+pub fn trusted<'a>(text: &'a str, host: &Host) -> Vec<&'a str> {
+    text.lines()
+        .filter(|line| {
+            let mut fields = line.split_whitespace();
+            let (Some(names), Some(_), Some(key)) = (fields.next(), fields.next(), fields.next())
+            else {
+                return false;
+            };
+            names.split(',').any(|name| name == host.name)
+                && fingerprint(key).is_some_and(|f| host.fingerprints.contains(&f))
+        })
+        .collect()
+}
+
+/// Asks `host` for its keys with `ssh-keyscan` and keeps the ones whose
+/// fingerprint the manifest lists. Fails if none is, as trusting the
+/// others could mean trusting whoever sits between here and the host.
+pub fn scan(cx: &Context, host: &Host) -> Result<Vec<String>> {
+    let keyscan = cx.cmd("ssh-keyscan").args(["-T", "10", &host.name]);
+    let out = keyscan.output()?.check(&keyscan)?;
+    let lines: Vec<String> = trusted(&out.stdout, host)
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+    if lines.is_empty() {
+        return Err(path_error(
+            &known_hosts_path(cx),
+            format!(
+                "none of the keys {} offers has a fingerprint listed for it in trk.toml ({}); \
+                 check them against what the host's operator publishes",
+                host.name,
+                host.fingerprints.join(", ")
+            ),
+        ));
+    }
+    Ok(lines)
+}
+
+/// The host of `hosts` an https `url` points at, and the same repository
+/// as an scp-style ssh remote, credentials dropped.
+pub fn ssh_remote<'a>(url: &str, hosts: &'a [Host]) -> Option<(&'a Host, String)> {
+    let rest = url.strip_prefix("https://")?;
+    let (authority, path) = rest.split_once('/')?;
+    let name = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let name = name.split(':').next().unwrap_or(name).to_ascii_lowercase();
+    let host = hosts.iter().find(|host| host.name == name)?;
+    (!path.is_empty()).then(|| (host, format!("{}@{name}:{path}", host.user)))
+}
+
+/// The known_hosts block holding `lines`, markers included.
+pub fn known_hosts_block(lines: &[String]) -> String {
+    let mut block =
+        format!("{BEGIN}\n# Host keys trk checked against trk.toml; edits here are lost.\n");
+    for line in lines {
+        block.push_str(line);
+        block.push('\n');
+    }
+    block.push_str(END);
+    block.push('\n');
+    block
+}
+
+/// Writes a managed file such as `~/.ssh/config`, private to the user.
+pub fn write_private(cx: &Context, path: &Path, contents: &str) -> Result<()> {
+    ssh_dir(cx)?;
+    write(path, contents, 0o600)
+}
+
+/// Reads `path`, or nothing if it does not exist.
+pub fn read_or_empty(path: &Path) -> Result<String> {
+    Ok(read(path)?.unwrap_or_default())
+}
+
+/// Adds the key to the agent, and on macOS its passphrase to the
+/// keychain, so it survives a restart.
+fn add_to_agent(cx: &Context, path: &Path) -> Result<()> {
+    let mut add = cx.cmd("ssh-add");
+    if cx.facts.is_macos() {
+        add = add.arg("--apple-use-keychain");
+    }
+    let add = add.arg(path.to_string_lossy());
+    add.output()?.check(&add).map(drop)
+}
+
+/// `~/.ssh/<name>`, for a name that stays inside `~/.ssh`.
+fn checked_path(cx: &Context, name: &str) -> Result<PathBuf> {
+    let path = key_path(cx, name);
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(path_error(&path, "a key name is a plain file name"));
+    }
+    Ok(path)
+}
+
+fn public_path(path: &Path) -> PathBuf {
+    let mut public = path.as_os_str().to_owned();
+    public.push(".pub");
+    public.into()
+}
+
+/// OpenSSH refuses a private key without its final newline.
+fn with_newline(mut key: String) -> String {
+    if !key.ends_with('\n') {
+        key.push('\n');
+    }
+    key
+}
+
+/// Creates `~/.ssh`, and makes it private to the user either way.
+fn ssh_dir(cx: &Context) -> Result<()> {
+    let dir = cx.home.join(".ssh");
+    fs::create_dir_all(&dir).map_err(|err| path_error(&dir, err))?;
+    set_mode(&dir, 0o700)
+}
+
+fn read(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(path_error(path, err)),
+    }
+}
+
+/// Writes `contents` to a file that has `mode` from the moment it exists.
+#[cfg(unix)]
+fn write(path: &Path, contents: &str, mode: u32) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| path_error(path, err))?;
+    set_mode(path, mode)
+}
+
+#[cfg(not(unix))]
+fn write(path: &Path, contents: &str, _mode: u32) -> Result<()> {
+    fs::File::create(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| path_error(path, err))
+}
+
+/// Sets `mode` even on a file that existed with a looser one.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|err| path_error(path, err))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+fn path_error(path: &Path, message: impl ToString) -> Error {
+    Error::Path {
+        path: path.to_owned(),
+        message: message.to_string(),
+    }
+}
@@ -0,0 +1,218 @@
+use std::path::PathBuf;
+
+use crate::brewfile::{Brewfile, Check, Entry, Installed, Item, Kind, RestartService, Value};
+use crate::context::Context;
+use crate::error::Result;
+use crate::exec::Cmd;
+use crate::journal::file_digest;
+use crate::plan::{Action, Change};
+
+use super::{deprecated_tap, Outcome, Step};
+
+/// Installs what the dotfiles Brewfile lists and upgrades what is
+/// outdated, like `brew bundle`, but changes only what [`Check`] found.
+pub struct Bundle {
+    path: PathBuf,
+}
+
+impl Bundle {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn check(&self, cx: &Context) -> Result<(Brewfile, Check)> {
+        let brewfile = Brewfile::read(&self.path)?;
+        let check = Check::new(&brewfile, &Installed::read(cx)?);
+        Ok((brewfile, check))
+    }
+
+    fn install(cx: &Context, brewfile: &Brewfile, entry: &Entry) -> Cmd {
+        match entry.kind {
+            Kind::Tap => cx
+                .cmd("brew")
+                .args(["tap", &entry.name])
+                .args(entry.tap_url()),
+            Kind::Brew => cx
+                .cmd("brew")
+                .args(["install", "--formula", &entry.name])
+                .args(entry.args()),
+            Kind::Cask => cx
+                .cmd("brew")
+                .args(["install", "--cask", &entry.name])
+                .args(cask_flags(brewfile, entry)),
+            Kind::Mas => cx
+                .cmd("mas")
+                .arg("install")
+                .arg(entry.mas_id().unwrap_or_default().to_string()),
+            Kind::Whalebrew => cx.cmd("whalebrew").args(["install", &entry.name]),
+            Kind::Vscode => cx.cmd("code").args(["--install-extension", &entry.name]),
+        }
+    }
+
+    fn upgrade(cx: &Context, entry: &Entry) -> Cmd {
+        let kind = if entry.kind == Kind::Cask {
+            "--cask"
+        } else {
+            "--formula"
+        };
+        cx.cmd("brew").args(["upgrade", kind, &entry.name])
+    }
+
+    /// Links or restarts a formula as its entry asks, after it was
+    /// installed or upgraded (`changed`) or on every run.
+    fn after(cx: &Context, entry: &Entry, changed: bool) -> Result<bool> {
+        if entry.kind != Kind::Brew {
+            return Ok(false);
+        }
+        if changed {
+            match entry.link() {
+                Some(true) => cx.cmd("brew").args(["link", &entry.name]).status()?,
+                Some(false) => cx.cmd("brew").args(["unlink", &entry.name]).status()?,
+                None => {}
+            }
+        }
+        let restart = match entry.restart_service() {
+            Some(RestartService::Always) => true,
+            Some(RestartService::Changed) => changed,
+            None => false,
+        };
+        if restart {
+            cx.cmd("brew")
+                .args(["services", "restart", &entry.name])
+                .status()?;
+        }
+        Ok(restart)
+    }
+}
+
+/// `cask_args` and the entry's own `args:` as `--key=value` flags;
+/// `key: true` becomes `--key`.
+fn cask_flags(brewfile: &Brewfile, entry: &Entry) -> Vec<String> {
+    let mut options = brewfile.cask_args.clone();
+    if let Some(Value::Hash(own)) = entry.option("args") {
+        for (key, value) in own {
+            options.retain(|(k, _)| k != key);
+            options.push((key.clone(), value.clone()));
+        }
+    }
+    options
+        .iter()
+        .filter_map(|(key, value)| match value {
+            Value::Bool(true) => Some(format!("--{key}")),
+            Value::Bool(false) | Value::Nil => None,
+            Value::Int(i) => Some(format!("--{key}={i}")),
+            other => other.as_str().map(|v| format!("--{key}={v}")),
+        })
+        .collect()
+}
+
+fn change(action: Action, item: &Item) -> Change {
+    let subject = format!("{} {}", item.kind, item.name);
+    match (&item.installed, &item.latest) {
+        (Some(installed), Some(latest)) => {
+            Change::new(action, subject).detail(format!("{installed} -> {latest}"))
+        }
+        _ => Change::new(action, subject),
+    }
+}
+
+impl Step for Bundle {
+    fn name(&self) -> String {
+        "brewfile".into()
+    }
+
+    fn describe(&self) -> String {
+        format!("Install from {}", self.path.display())
+    }
+
+    fn inputs(&self) -> String {
+        format!("{} {}", self.path.display(), file_digest(&self.path))
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let (_, check) = self.check(cx)?;
+        let missing = check
+            .missing
+            .iter()
+            // Retired taps are never tapped.
+            .filter(|item| item.kind != Kind::Tap || deprecated_tap(&item.name).is_none())
+            .map(|item| change(Action::Add, item));
+        let outdated = check
+            .outdated
+            .iter()
+            .map(|item| change(Action::Change, item));
+        Ok(missing.chain(outdated).collect())
+    }
+
+    /// Cask installers may run as root, so a cask that is missing or
+    /// outdated needs sudo. With `--no-sudo` the step runs anyway and
+    /// leaves the casks it would touch for a later run, as it does App
+    /// Store apps the `mas` preflight skipped.
+    fn sudo(&self, cx: &Context) -> bool {
+        !cx.no_sudo
+            && self.path.exists()
+            && self.check(cx).is_ok_and(|(_, check)| {
+                check
+                    .missing
+                    .iter()
+                    .chain(&check.outdated)
+                    .any(|item| item.kind == Kind::Cask)
+            })
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        if !self.path.exists() {
+            return Ok(Outcome::Skipped(format!("no {}", self.path.display())));
+        }
+        let (brewfile, check) = self.check(cx)?;
+        let mut changed = false;
+        let report = cx.report();
+        let mut deferred = Vec::new();
+        for entry in &brewfile.entries {
+            let is = |item: &Item| item.line == Some(entry.line);
+            let pending = check.missing.iter().any(is) || check.outdated.iter().any(is);
+            let defer = match entry.kind {
+                Kind::Cask => cx.no_sudo,
+                Kind::Mas => report.skips_mas(&entry.name),
+                _ => false,
+            };
+            if pending && defer {
+                deferred.push(format!("{} {}", entry.kind, entry.name));
+                continue;
+            }
+            let retired = deprecated_tap(&entry.name).filter(|_| entry.kind == Kind::Tap);
+            if let Some(reason) = retired {
+                println!(
+                    "    warning: {} is deprecated ({reason}); not tapping it",
+                    entry.name
+                );
+                continue;
+            }
+            let touched = if check.missing.iter().any(is) {
+                Self::install(cx, &brewfile, entry).status()?;
+                true
+            } else if check.outdated.iter().any(is) {
+                Self::upgrade(cx, entry).status()?;
+                true
+            } else {
+                false
+            };
+            let restarted = Self::after(cx, entry, touched)?;
+            changed |= touched || restarted;
+        }
+        if !deferred.is_empty() {
+            return Ok(Outcome::Skipped(format!(
+                "left for later: {}",
+                deferred.join(", ")
+            )));
+        }
+        Ok(if changed {
+            Outcome::Changed
+        } else {
+            Outcome::Ok
+        })
+    }
+}
@@ -0,0 +1,422 @@
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::brewfile::{self, Brewfile};
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::exec::Cmd;
+use crate::journal::file_digest;
+use crate::manifest::{self, DeprecatedTaps, State};
+use crate::plan::{Action, Change};
+
+use super::{Outcome, Step};
+
+const INSTALL_SCRIPT: &str = "https://raw.githubusercontent.com/Homebrew/install/HEAD/install.sh";
+
+/// Installs Homebrew, or runs `brew update` when it is already there.
+pub struct Homebrew;
+
+impl Step for Homebrew {
+    fn name(&self) -> String {
+        "homebrew".into()
+    }
+
+    fn describe(&self) -> String {
+        "Installing or Updating Homebrew".into()
+    }
+
+    fn inputs(&self) -> String {
+        String::new()
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        Ok(vec![if cx.which("brew").is_some() {
+            Change::new(Action::Run, "brew update")
+        } else {
+            Change::new(Action::Add, "Homebrew").detail(INSTALL_SCRIPT)
+        }])
+    }
+
+    /// The installer creates the Homebrew prefix as root.
+    fn sudo(&self, cx: &Context) -> bool {
+        cx.which("brew").is_none()
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        if cx.which("brew").is_some() {
+            cx.cmd("brew").arg("update").status()?;
+            return Ok(Outcome::Ok);
+        }
+        let curl = cx.cmd("curl").args(["-fsSL", INSTALL_SCRIPT]);
+        let script = curl.output()?.check(&curl)?.stdout;
+        cx.cmd("/bin/bash").args(["-c", &script]).status()?;
+        Ok(Outcome::Changed)
+    }
+}
+
+/// Official taps Homebrew has retired, and why they are not needed.
+const DEPRECATED_TAPS: &[(&str, &str)] = &[
+    (
+        "homebrew/core",
+        "formulae come from the JSON API; only needed to work on formulae",
+    ),
+    (
+        "homebrew/cask",
+        "casks come from the JSON API; only needed to work on casks",
+    ),
+    ("homebrew/bundle", "`brew bundle` is part of Homebrew"),
+    ("homebrew/services", "`brew services` is part of Homebrew"),
+    ("homebrew/cask-fonts", "fonts moved to homebrew/cask"),
+    (
+        "homebrew/cask-versions",
+        "versioned casks moved to homebrew/cask",
+    ),
+    ("homebrew/cask-drivers", "drivers moved to homebrew/cask"),
+];
+
+/// Why `name` is a retired official tap, if it is one.
+pub fn deprecated_tap(name: &str) -> Option<&'static str> {
+    DEPRECATED_TAPS
+        .iter()
+        .find(|(tap, _)| tap.eq_ignore_ascii_case(name))
+        .map(|(_, reason)| *reason)
+}
+
+/// `brew tap`: what is tapped, or nothing before Homebrew is installed.
+fn tapped(cx: &Context) -> Result<Vec<String>> {
+    if cx.which("brew").is_none() {
+        return Ok(Vec::new());
+    }
+    let list = cx.cmd("brew").arg("tap");
+    let tapped = list.output()?.check(&list)?.stdout;
+    Ok(tapped
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// A tap from the manifest, tapped or untapped to match its [`State`].
+pub struct Tap {
+    name: String,
+    state: State,
+}
+
+impl Tap {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            state: State::Present,
+        }
+    }
+
+    /// Untap it instead.
+    pub fn absent(mut self) -> Self {
+        self.state = State::Absent;
+        self
+    }
+
+    fn tapped(&self, cx: &Context) -> Result<bool> {
+        Ok(tapped(cx)?
+            .iter()
+            .any(|tap| tap.eq_ignore_ascii_case(&self.name)))
+    }
+}
+
+impl Step for Tap {
+    fn name(&self) -> String {
+        format!("tap:{}", self.name)
+    }
+
+    fn describe(&self) -> String {
+        match self.state {
+            State::Present => format!("Tap {}", self.name),
+            State::Absent => format!("Untap {}", self.name),
+        }
+    }
+
+    fn inputs(&self) -> String {
+        match self.state {
+            State::Present => self.name.clone(),
+            State::Absent => format!("{} absent", self.name),
+        }
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        let tapped = self.tapped(cx)?;
+        Ok(match self.state {
+            State::Present if !tapped && deprecated_tap(&self.name).is_none() => {
+                vec![Change::new(Action::Add, &self.name)]
+            }
+            State::Absent if tapped => vec![Change::new(Action::Remove, &self.name)],
+            _ => Vec::new(),
+        })
+    }
+
+    /// A retired tap is never tapped: `brew tap` refuses most of them, and
+    /// the rest are only clones of what Homebrew already has.
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        let tapped = self.tapped(cx)?;
+        match self.state {
+            State::Present if tapped => Ok(Outcome::Ok),
+            State::Present => {
+                if let Some(reason) = deprecated_tap(&self.name) {
+                    return Ok(Outcome::Skipped(format!("deprecated: {reason}")));
+                }
+                cx.cmd("brew").args(["tap", &self.name]).status()?;
+                Ok(Outcome::Changed)
+            }
+            State::Absent if tapped => {
+                cx.cmd("brew").args(["untap", &self.name]).status()?;
+                Ok(Outcome::Changed)
+            }
+            State::Absent => Ok(Outcome::Ok),
+        }
+    }
+}
+
+/// Looks at the taps nobody declared: retired official taps are untapped
+/// or warned about, as the manifest's `deprecated_taps` says, and any
+/// other tap missing from trk.toml and the Brewfile is reported.
+pub struct TapAudit {
+    /// Taps in the manifest, in either state.
+    declared: Vec<String>,
+    /// Taps declared present, which are kept even when retired.
+    wanted: Vec<String>,
+    brewfile: Option<PathBuf>,
+    untap_deprecated: bool,
+}
+
+impl TapAudit {
+    pub fn new(taps: &[manifest::Tap], policy: DeprecatedTaps) -> Self {
+        Self {
+            declared: taps.iter().map(|tap| tap.name.clone()).collect(),
+            wanted: taps
+                .iter()
+                .filter(|tap| tap.state == State::Present)
+                .map(|tap| tap.name.clone())
+                .collect(),
+            brewfile: None,
+            untap_deprecated: policy == DeprecatedTaps::Untap,
+        }
+    }
+
+    /// Count the taps this Brewfile lists as declared.
+    pub fn brewfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.brewfile = Some(path.into());
+        self
+    }
+
+    /// Installed taps that no manifest entry mentions, with the reason
+    /// each is retired, if it is. Brewfile taps count as wanted.
+    fn undeclared(&self, cx: &Context) -> Result<Vec<(String, Option<&'static str>)>> {
+        let mut wanted = self.wanted.clone();
+        if let Some(path) = self.brewfile.as_ref().filter(|path| path.exists()) {
+            let brewfile = Brewfile::read(path)?;
+            wanted.extend(
+                brewfile
+                    .entries(brewfile::Kind::Tap)
+                    .map(|e| e.name.clone()),
+            );
+        }
+        let has = |list: &[String], tap: &str| list.iter().any(|t| t.eq_ignore_ascii_case(tap));
+        Ok(tapped(cx)?
+            .into_iter()
+            .filter(|tap| !has(&self.declared, tap) && !has(&wanted, tap))
+            .map(|tap| {
+                let reason = deprecated_tap(&tap);
+                (tap, reason)
+            })
+            .collect())
+    }
+}
+
+impl Step for TapAudit {
+    fn name(&self) -> String {
+        "taps".into()
+    }
+
+    fn describe(&self) -> String {
+        "Looking for deprecated and undeclared taps".into()
+    }
+
+    fn inputs(&self) -> String {
+        let brewfile = self
+            .brewfile
+            .as_ref()
+            .map(|path| file_digest(path))
+            .unwrap_or_default();
+        format!(
+            "{} untap={} {brewfile}",
+            self.declared.join(","),
+            self.untap_deprecated
+        )
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        Ok(self
+            .undeclared(cx)?
+            .into_iter()
+            .map(|(tap, reason)| match reason {
+                Some(reason) if self.untap_deprecated => {
+                    Change::new(Action::Remove, tap).detail(format!("deprecated: {reason}"))
+                }
+                Some(reason) => Change::new(Action::Warn, tap).detail(format!(
+                    "deprecated: {reason}; untap it or set `deprecated_taps = \"untap\"`"
+                )),
+                None => Change::new(Action::Warn, tap).detail("not declared in trk.toml"),
+            })
+            .collect())
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        let mut outcome = Outcome::Ok;
+        for (tap, reason) in self.undeclared(cx)? {
+            match reason {
+                Some(_) if self.untap_deprecated => {
+                    cx.cmd("brew").args(["untap", &tap]).status()?;
+                    outcome = Outcome::Changed;
+                }
+                Some(reason) => println!(
+                    "    warning: {tap} is deprecated ({reason}); \
+                     untap it or set `deprecated_taps = \"untap\"`"
+                ),
+                None => println!("    warning: {tap} is tapped but not declared in trk.toml"),
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+/// Whether a [`Package`] is a formula or a cask; the two share every
+/// `brew` subcommand but need `--cask` to tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Formula,
+    Cask,
+}
+
+/// A formula or cask from the manifest.
+pub struct Package {
+    kind: Kind,
+    name: String,
+    latest: bool,
+}
+
+/// Where a [`Package`] stands on this machine.
+enum PackageState {
+    Missing,
+    Current,
+    Outdated { installed: String, current: String },
+}
+
+impl Package {
+    pub fn new(kind: Kind, name: impl Into<String>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            latest: false,
+        }
+    }
+
+    /// Run `brew upgrade` when the package is already installed.
+    pub fn latest(mut self, latest: bool) -> Self {
+        self.latest = latest;
+        self
+    }
+
+    fn brew(&self, cx: &Context, subcommand: &str) -> Cmd {
+        let kind = match self.kind {
+            Kind::Formula => "--formula",
+            Kind::Cask => "--cask",
+        };
+        cx.cmd("brew").args([subcommand, kind, &self.name])
+    }
+
+    /// Outdated versions are only looked up for `latest` packages, since
+    /// nothing else would upgrade them.
+    fn state(&self, cx: &Context) -> Result<PackageState> {
+        if cx.which("brew").is_none() || !self.brew(cx, "list").succeeds()? {
+            return Ok(PackageState::Missing);
+        }
+        if !self.latest {
+            return Ok(PackageState::Current);
+        }
+        let outdated = self.brew(cx, "outdated").arg("--json=v2");
+        let json = outdated.output()?.check(&outdated)?.stdout;
+        let report: Value = serde_json::from_str(&json).map_err(|err| Error::Output {
+            command: outdated.to_string(),
+            message: err.to_string(),
+        })?;
+        let key = match self.kind {
+            Kind::Formula => "formulae",
+            Kind::Cask => "casks",
+        };
+        let entry = report[key]
+            .as_array()
+            .and_then(|entries| entries.iter().find(|e| e["name"] == self.name.as_str()));
+        Ok(match entry {
+            Some(entry) => PackageState::Outdated {
+                installed: match &entry["installed_versions"] {
+                    Value::Array(versions) => versions
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    other => other.as_str().unwrap_or("?").to_owned(),
+                },
+                current: entry["current_version"].as_str().unwrap_or("?").to_owned(),
+            },
+            None => PackageState::Current,
+        })
+    }
+}
+
+impl Step for Package {
+    fn name(&self) -> String {
+        match self.kind {
+            Kind::Formula => format!("formula:{}", self.name),
+            Kind::Cask => format!("cask:{}", self.name),
+        }
+    }
+
+    fn describe(&self) -> String {
+        if self.latest {
+            format!("Installing or Updating {}", self.name)
+        } else {
+            format!("Searching or Installing {}", self.name)
+        }
+    }
+
+    fn inputs(&self) -> String {
+        format!("{} latest={}", self.name, self.latest)
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        Ok(match self.state(cx)? {
+            PackageState::Missing => vec![Change::new(Action::Add, &self.name)],
+            PackageState::Current => Vec::new(),
+            PackageState::Outdated { installed, current } => {
+                vec![Change::new(Action::Change, &self.name)
+                    .detail(format!("{installed} -> {current}"))]
+            }
+        })
+    }
+
+    /// Cask installers may run as root; a cask that is already current
+    /// is left alone and needs nothing.
+    fn sudo(&self, cx: &Context) -> bool {
+        self.kind == Kind::Cask && !matches!(self.state(cx), Ok(PackageState::Current))
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        match self.state(cx)? {
+            PackageState::Missing => self.brew(cx, "install").status()?,
+            PackageState::Current => return Ok(Outcome::Ok),
+            PackageState::Outdated { .. } => self.brew(cx, "upgrade").status()?,
+        }
+        Ok(Outcome::Changed)
+    }
+}
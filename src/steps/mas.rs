@@ -0,0 +1,257 @@
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::brewfile::{parse_mas_list, Brewfile, Entry, Kind};
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::journal::file_digest;
+use crate::plan::{Action, Change};
+use crate::report::MasDecision;
+
+use super::{Outcome, Step};
+
+/// How often `--mas wait` checks the account again.
+const RECHECK: Duration = Duration::from_secs(10);
+
+/// How many times `--mas wait` checks again before it leaves the apps for
+/// a later run: ten minutes at [`RECHECK`].
+const MAX_RECHECKS: u32 = 60;
+
+/// What to do when App Store apps are due and the account is not signed
+/// in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MasChoice {
+    /// Wait for a sign-in to the App Store app and check again.
+    Wait,
+    /// Leave the App Store apps for a later run.
+    Skip,
+    /// Stop the run.
+    Abort,
+}
+
+/// The App Store account, as `mas account` reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Account {
+    SignedIn(String),
+    SignedOut,
+    /// `mas account` cannot tell, as on macOS 10.13 and later
+    /// (mas-cli/mas#164), or mas is missing.
+    Unknown(String),
+}
+
+impl Account {
+    pub fn check(cx: &Context) -> Result<Self> {
+        if cx.which("mas").is_none() {
+            return Ok(Account::Unknown("mas is not installed".into()));
+        }
+        let out = cx.cmd("mas").arg("account").output()?;
+        let stdout = out.stdout.trim();
+        let stderr = out.stderr.trim();
+        Ok(if out.success() && !stdout.is_empty() {
+            Account::SignedIn(stdout.to_owned())
+        } else if format!("{stdout} {stderr}")
+            .to_lowercase()
+            .contains("not signed in")
+        {
+            Account::SignedOut
+        } else {
+            Account::Unknown(if stderr.is_empty() { stdout } else { stderr }.to_owned())
+        })
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Account::SignedIn(id) => write!(f, "signed in as {id}"),
+            Account::SignedOut => f.write_str("signed out"),
+            Account::Unknown(reason) => write!(f, "unknown ({reason})"),
+        }
+    }
+}
+
+/// Checks, before the Brewfile install, that the App Store account its
+/// `mas` entries need is signed in; mas cannot sign in by itself. When it
+/// is not, waits for a sign-in, skips the apps or stops, as `--mas` says
+/// or the user answers, and records that in the run's report.
+pub struct MasPreflight {
+    brewfile: PathBuf,
+    recheck: Duration,
+    max_rechecks: u32,
+}
+
+impl MasPreflight {
+    pub fn new(brewfile: impl Into<PathBuf>) -> Self {
+        Self {
+            brewfile: brewfile.into(),
+            recheck: RECHECK,
+            max_rechecks: MAX_RECHECKS,
+        }
+    }
+
+    /// How long `--mas wait` sleeps between checks.
+    pub fn recheck(mut self, every: Duration) -> Self {
+        self.recheck = every;
+        self
+    }
+
+    /// How many times `--mas wait` checks again before it gives up.
+    pub fn max_rechecks(mut self, checks: u32) -> Self {
+        self.max_rechecks = checks;
+        self
+    }
+
+    /// The Brewfile's `mas` entries that are not installed yet.
+    fn pending(&self, cx: &Context) -> Result<Vec<Entry>> {
+        if !self.brewfile.exists() {
+            return Ok(Vec::new());
+        }
+        let brewfile = Brewfile::read(&self.brewfile)?;
+        let installed = match cx.which("mas") {
+            Some(_) => {
+                let list = cx.cmd("mas").arg("list");
+                parse_mas_list(&list.output()?.check(&list)?.stdout)
+            }
+            None => Vec::new(),
+        };
+        Ok(brewfile
+            .entries(Kind::Mas)
+            .filter(|entry| !installed.iter().any(|app| Some(app.id) == entry.mas_id()))
+            .cloned()
+            .collect())
+    }
+
+    /// Asks on the terminal; without one, `--mas` is required.
+    fn ask(&self, account: &Account, names: &str) -> Result<MasChoice> {
+        if !io::stdin().is_terminal() {
+            return Err(self.error(format!(
+                "the App Store account is {account} and {names} need it; \
+                 pass --mas wait, skip or abort"
+            )));
+        }
+        loop {
+            print!("    [w]ait and check again, [s]kip these apps, or [a]bort? ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().lock().read_line(&mut answer)?;
+            match answer.trim().to_lowercase().chars().next() {
+                Some('w') => return Ok(MasChoice::Wait),
+                Some('s') => return Ok(MasChoice::Skip),
+                Some('a') => return Ok(MasChoice::Abort),
+                _ => {}
+            }
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Mas { message }
+    }
+}
+
+impl Step for MasPreflight {
+    fn name(&self) -> String {
+        "mas".into()
+    }
+
+    fn describe(&self) -> String {
+        "Checking the Mac App Store sign-in".into()
+    }
+
+    fn inputs(&self) -> String {
+        format!(
+            "{} {}",
+            self.brewfile.display(),
+            file_digest(&self.brewfile)
+        )
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        let pending = self.pending(cx)?;
+        if pending.is_empty() || matches!(Account::check(cx)?, Account::SignedIn(_)) {
+            return Ok(Vec::new());
+        }
+        let names: Vec<&str> = pending.iter().map(|entry| entry.name.as_str()).collect();
+        Ok(vec![Change::new(Action::Run, "App Store sign-in")
+            .detail(format!("needed for {}", names.join(", ")))])
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        if !cx.facts.is_macos() {
+            return Ok(Outcome::Skipped(format!("not macOS but {}", cx.facts.os)));
+        }
+        let pending = self.pending(cx)?;
+        if pending.is_empty() {
+            return Ok(Outcome::Ok);
+        }
+        let names: Vec<String> = pending.iter().map(|entry| entry.name.clone()).collect();
+        let listed = names.join(", ");
+
+        let mut account = Account::check(cx)?;
+        let first = account.to_string();
+        let mut rechecks = 0;
+        loop {
+            if let Account::SignedIn(_) = account {
+                break;
+            }
+            println!("    App Store account: {account}; {listed} need it");
+            let choice = match cx.mas {
+                Some(MasChoice::Wait) if rechecks == self.max_rechecks => {
+                    // Nobody is there to sign in; do not hold the run up.
+                    println!("    Gave up waiting after {rechecks} checks");
+                    MasChoice::Skip
+                }
+                Some(choice) => choice,
+                None => self.ask(&account, &listed)?,
+            };
+            let decision = |choice, skipped: &[String]| {
+                cx.update_report(|report| {
+                    report.mas = Some(MasDecision {
+                        account: first.clone(),
+                        choice,
+                        skipped: skipped.to_vec(),
+                    })
+                })
+            };
+            match choice {
+                MasChoice::Skip => {
+                    decision(choice, &names);
+                    return Ok(Outcome::Skipped(format!(
+                        "App Store account {account}; left for later: {listed}"
+                    )));
+                }
+                MasChoice::Abort => {
+                    decision(choice, &[]);
+                    return Err(self.error(format!(
+                        "the App Store account is {account} and {listed} need it; \
+                         sign in to the App Store app and run again with --resume"
+                    )));
+                }
+                MasChoice::Wait => {
+                    decision(choice, &[]);
+                    if cx.mas.is_some() {
+                        rechecks += 1;
+                        thread::sleep(self.recheck);
+                    } else {
+                        print!("    Sign in to the App Store app, then press Enter ");
+                        io::stdout().flush()?;
+                        io::stdin().lock().read_line(&mut String::new())?;
+                    }
+                }
+            }
+            account = Account::check(cx)?;
+            if let Account::Unknown(_) = account {
+                // mas cannot tell, so the wait is all there is to go on.
+                println!("    App Store account: {account}; carrying on");
+                break;
+            }
+        }
+        Ok(Outcome::Ok)
+    }
+}
@@ -0,0 +1,360 @@
+//! The actions a bootstrap or update is made of.
+//!
+//! Each step is idempotent: running it against a machine that is already
+//! set up leaves the machine alone and reports [`Outcome::Ok`].
+
+mod bundle;
+mod homebrew;
+mod mas;
+mod playbook;
+mod release;
+mod repo;
+mod rosetta;
+mod runtime;
+mod ssh;
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::journal::{Journal, Recorded};
+use crate::manifest::State;
+use crate::plan::Change;
+use crate::sudo::{self, Sudo};
+
+pub use bundle::Bundle;
+pub use homebrew::{deprecated_tap, Homebrew, Kind, Package, Tap, TapAudit};
+pub use mas::{Account, MasChoice, MasPreflight};
+pub use playbook::Playbook;
+pub use release::ReleaseBinary;
+pub use repo::Repo;
+pub use rosetta::Rosetta;
+pub use runtime::{Runtime, ToolVersions};
+pub use ssh::{SshConfig, SshRemotes};
+
+/// One unit of work in a run.
+pub trait Step {
+    /// Stable identifier, e.g. `formula:git` or `repo:trk`.
+    fn name(&self) -> String;
+
+    /// What the step does, shown as the run reaches it.
+    fn describe(&self) -> String;
+
+    /// Everything the step's result depends on. A resumed run skips a step
+    /// that finished before with the same inputs.
+    fn inputs(&self) -> String;
+
+    /// The check phase: what [`Step::run`] would change, found without
+    /// changing anything. An empty list means the step has nothing to do.
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>>;
+
+    /// Whether [`Step::run`] would need root. The run asks for the sudo
+    /// password once before the first such step, or skips them all with
+    /// `--no-sudo`.
+    fn sudo(&self, _cx: &Context) -> bool {
+        false
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome>;
+}
+
+/// How a step left the machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Nothing needed doing.
+    Ok,
+    /// The step changed something.
+    Changed,
+    /// The step does not apply to this machine.
+    Skipped(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Ok => f.write_str("ok"),
+            Outcome::Changed => f.write_str("changed"),
+            Outcome::Skipped(reason) => write!(f, "skipped ({reason})"),
+        }
+    }
+}
+
+/// Everything `trk bootstrap` does, in order.
+pub fn bootstrap(cx: &Context) -> Vec<Box<dyn Step>> {
+    let mut steps: Vec<Box<dyn Step>> = vec![Box::new(Rosetta), Box::new(Homebrew)];
+    steps.extend(packages(cx));
+    steps.extend(releases(cx));
+    steps.extend(ssh_config(cx));
+    steps.extend(
+        repos(cx)
+            .into_iter()
+            .map(|repo| -> Box<dyn Step> { Box::new(repo) }),
+    );
+    steps.extend(ssh_remotes(cx));
+    steps.extend(runtimes(cx));
+    steps.extend(mas_preflight(cx));
+    steps.push(Box::new(playbook(cx)));
+    steps.extend(brewfile(cx));
+    steps
+}
+
+/// Everything `trk update` does, in order.
+pub fn update(cx: &Context) -> Vec<Box<dyn Step>> {
+    let mut steps: Vec<Box<dyn Step>> = vec![Box::new(Rosetta)];
+    steps.extend(ssh_config(cx));
+    steps.extend(
+        repos(cx)
+            .into_iter()
+            .filter(|repo| matches!(repo.repo_name(), "trk" | "dotfiles"))
+            .map(|repo| -> Box<dyn Step> { Box::new(repo) }),
+    );
+    steps.extend(ssh_remotes(cx));
+    steps.push(Box::new(Homebrew));
+    steps.extend(packages(cx));
+    steps.extend(releases(cx));
+    steps.extend(runtimes(cx));
+    steps.extend(mas_preflight(cx));
+    steps.push(Box::new(playbook(cx)));
+    steps.extend(brewfile(cx));
+    steps
+}
+
+fn packages(cx: &Context) -> Vec<Box<dyn Step>> {
+    let homebrew = &cx.manifest.homebrew;
+    let mut steps: Vec<Box<dyn Step>> = Vec::new();
+    for tap in &homebrew.taps {
+        let step = Tap::new(tap.as_str());
+        steps.push(Box::new(match tap.state {
+            State::Present => step,
+            State::Absent => step.absent(),
+        }));
+    }
+    let mut audit = TapAudit::new(&homebrew.taps, homebrew.deprecated_taps());
+    if let Some(path) = cx.dotfiles_brewfile() {
+        audit = audit.brewfile(path);
+    }
+    steps.push(Box::new(audit));
+    for (kind, packages) in [
+        (Kind::Formula, &homebrew.formulae),
+        (Kind::Cask, &homebrew.casks),
+    ] {
+        for package in packages {
+            steps.push(Box::new(
+                Package::new(kind, &package.name).latest(package.latest),
+            ));
+        }
+    }
+    steps
+}
+
+fn releases(cx: &Context) -> Vec<Box<dyn Step>> {
+    cx.manifest
+        .releases
+        .iter()
+        .map(|(name, release)| -> Box<dyn Step> {
+            Box::new(ReleaseBinary::new(name, release.clone()))
+        })
+        .collect()
+}
+
+/// The manifest's repos and the dotfiles checkout.
+pub fn repos(cx: &Context) -> Vec<Repo> {
+    let mut steps = Vec::new();
+    for (name, repo) in &cx.manifest.repos {
+        let mut step = Repo::new(name, repo.url.as_str(), cx.expand(repo.path.as_str()));
+        if let Some(pin) = &repo.pin {
+            step = step.pin(pin.clone());
+        }
+        if !repo.pull {
+            step = step.no_pull();
+        }
+        steps.push(step);
+    }
+    if let Some(url) = &cx.dotfiles_url {
+        steps.push(Repo::new("dotfiles", url, &cx.dotfiles_dir));
+    }
+    steps
+}
+
+/// `~/.ssh/config` and `known_hosts`, when the manifest has `[ssh]`.
+fn ssh_config(cx: &Context) -> Option<Box<dyn Step>> {
+    Some(Box::new(SshConfig::new(cx.manifest.ssh.clone()?)))
+}
+
+/// Every managed checkout's remote switched to ssh, when `[ssh]` asks for
+/// it.
+fn ssh_remotes(cx: &Context) -> Option<Box<dyn Step>> {
+    let ssh = cx
+        .manifest
+        .ssh
+        .clone()
+        .filter(|ssh| ssh.rewrite_remotes())?;
+    let checkouts = repos(cx)
+        .iter()
+        .map(|repo| repo.checkout().clone())
+        .collect();
+    Some(Box::new(SshRemotes::new(ssh, checkouts)))
+}
+
+/// `[runtimes]` from the manifest, then the dotfiles `.tool-versions`.
+fn runtimes(cx: &Context) -> Vec<Box<dyn Step>> {
+    let manager = cx.manifest.version_manager();
+    let runtimes = &cx.manifest.runtimes;
+    let lock = cx.runtimes_lock();
+    let mut steps: Vec<Box<dyn Step>> = Vec::new();
+    for (plugin, runtime) in runtimes {
+        steps.push(Box::new(
+            Runtime::new(manager, plugin, runtime.clone()).lock(&lock),
+        ));
+    }
+    if let Some(path) = cx.dotfiles_tool_versions() {
+        steps.push(Box::new(
+            ToolVersions::new(manager, path)
+                .declared(runtimes.keys())
+                .lock(&lock),
+        ));
+    }
+    steps
+}
+
+/// `ansible/mac.yml`, pointed at the dotfiles hooks from the manifest.
+fn playbook(cx: &Context) -> Playbook {
+    let dotfiles = &cx.manifest.dotfiles;
+    let mut playbook = Playbook::new(cx.trk_dir.join("ansible/mac.yml"))
+        .runner(dotfiles.playbook_runner.unwrap_or_default());
+    for (name, value) in cx.facts.vars() {
+        playbook = playbook.var(name, value);
+    }
+    if let Some(hook) = &dotfiles.playbook {
+        let path = cx.dotfiles_dir.join(hook.as_str());
+        playbook = playbook.var("dotfiles_playbook", path.to_string_lossy());
+    }
+    playbook
+}
+
+/// The App Store sign-in check for the dotfiles Brewfile's `mas` entries.
+fn mas_preflight(cx: &Context) -> Option<Box<dyn Step>> {
+    Some(Box::new(MasPreflight::new(cx.dotfiles_brewfile()?)))
+}
+
+/// The dotfiles Brewfile, when the manifest names one.
+fn brewfile(cx: &Context) -> Option<Box<dyn Step>> {
+    Some(Box::new(Bundle::new(cx.dotfiles_brewfile()?)))
+}
+
+/// Which of a run's steps to execute.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Selection {
+    /// Every step, starting over.
+    #[default]
+    All,
+    /// Every step that has not finished with the same inputs before.
+    Resume,
+    /// The named step and everything after it.
+    From(String),
+    /// Just the named steps.
+    Only(Vec<String>),
+}
+
+impl Selection {
+    /// Fails on step names that are not part of the run.
+    fn validate(&self, steps: &[Box<dyn Step>]) -> Result<()> {
+        let names: Vec<String> = steps.iter().map(|step| step.name()).collect();
+        let requested = match self {
+            Selection::All | Selection::Resume => &[][..],
+            Selection::From(name) => std::slice::from_ref(name),
+            Selection::Only(only) => only.as_slice(),
+        };
+        match requested.iter().find(|name| !names.contains(name)) {
+            Some(unknown) => Err(Error::UnknownStep {
+                name: unknown.clone(),
+                known: names,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Runs the selected `steps` in order, recording each outcome in `journal`
+/// and stopping at the first failure.
+pub fn run_all(
+    cx: &Context,
+    steps: &[Box<dyn Step>],
+    selection: &Selection,
+    journal: &mut Journal,
+) -> Result<()> {
+    selection.validate(steps)?;
+    if *selection == Selection::All {
+        journal.clear();
+    } else {
+        // A resumed run goes on with the decisions it made before.
+        let earlier = journal.report.clone();
+        cx.update_report(|report| *report = earlier);
+    }
+    let mut reached = false;
+    let mut selected = Vec::new();
+    for step in steps {
+        let name = step.name();
+        // Inputs such as a repo URL may hold a secret; the journal keeps
+        // them masked, and compares them masked on resume.
+        let inputs = cx.redact(&step.inputs());
+        reached |= matches!(selection, Selection::From(from) if *from == name);
+        let skip = match selection {
+            Selection::All => None,
+            Selection::Resume => journal
+                .finished(&name, &inputs)
+                .then_some("finished in an earlier run"),
+            Selection::From(_) => (!reached).then_some("before --from"),
+            Selection::Only(only) => (!only.contains(&name)).then_some("not in --only"),
+        };
+        if let Some(reason) = skip {
+            if *selection == Selection::Resume {
+                println!("--> {name}: {reason}");
+            }
+            continue;
+        }
+        let sudo = step.sudo(cx);
+        selected.push((step, name, inputs, sudo));
+    }
+
+    let privileged: Vec<String> = selected
+        .iter()
+        .filter(|(.., sudo)| *sudo)
+        .map(|(_, name, ..)| name.clone())
+        .collect();
+    let _sudo = match privileged.is_empty() || cx.no_sudo {
+        true => None,
+        false => Some(Sudo::acquire(cx, &privileged, sudo::REFRESH)?),
+    };
+
+    for (step, name, inputs, sudo) in selected {
+        if sudo && cx.no_sudo {
+            let outcome = Outcome::Skipped("needs sudo; left for a run without --no-sudo".into());
+            println!("--> {name}: {outcome}");
+            journal.record(name, inputs, Recorded::Outcome(outcome))?;
+            continue;
+        }
+
+        println!("==> {}", cx.redact(&step.describe()));
+        match step.run(cx) {
+            Ok(outcome) => {
+                println!("    {name}: {}\n", cx.redact(&outcome.to_string()));
+                journal.report = cx.report();
+                journal.record(name, inputs, Recorded::Outcome(outcome))?;
+            }
+            Err(err) => {
+                journal.report = cx.report();
+                let err = cx.redact_error(err);
+                journal.record(name.clone(), inputs, Recorded::Error(err.to_string()))?;
+                return Err(Error::Step {
+                    step: name,
+                    source: Box::new(err),
+                });
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::journal::file_digest;
+use crate::manifest::PlaybookRunner;
+use crate::plan::{Action, Change};
+use crate::playbook::{Report, Runner};
+
+use super::{Outcome, Step};
+
+/// Runs a playbook against the local machine, with trk's own runner or
+/// with `ansible-playbook`.
+pub struct Playbook {
+    path: PathBuf,
+    vars: Vec<(String, String)>,
+    runner: PlaybookRunner,
+}
+
+impl Playbook {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            vars: Vec::new(),
+            runner: PlaybookRunner::default(),
+        }
+    }
+
+    pub fn runner(mut self, runner: PlaybookRunner) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    fn native<'a>(&self, cx: &'a Context) -> Runner<'a> {
+        let mut runner = Runner::new(cx);
+        for (name, value) in &self.vars {
+            runner = runner.var(name, value.as_str());
+        }
+        runner
+    }
+
+    /// Passes `name=value` to the playbook as an extra variable.
+    pub fn var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl Step for Playbook {
+    fn name(&self) -> String {
+        "playbook".into()
+    }
+
+    fn describe(&self) -> String {
+        match self.runner {
+            PlaybookRunner::Trk => "Running Playbook".into(),
+            PlaybookRunner::Ansible => "Handing Playbook to Ansible".into(),
+        }
+    }
+
+    /// The runner, the playbook and every hook file it is given, by content.
+    fn inputs(&self) -> String {
+        let mut inputs = format!(
+            "{} {} {}",
+            self.runner,
+            self.path.display(),
+            file_digest(&self.path)
+        );
+        for (name, value) in &self.vars {
+            inputs += &format!(" {name}={value} {}", file_digest(Path::new(value)));
+        }
+        inputs
+    }
+
+    fn plan(&self, _cx: &Context) -> Result<Vec<Change>> {
+        Ok(vec![Change::new(
+            Action::Run,
+            self.path.display().to_string(),
+        )])
+    }
+
+    /// Ansible may become root in any task; trk's runner only for tasks
+    /// with `become`.
+    fn sudo(&self, cx: &Context) -> bool {
+        match self.runner {
+            PlaybookRunner::Ansible => true,
+            PlaybookRunner::Trk => self.native(cx).uses_sudo(&self.path).unwrap_or(false),
+        }
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        if self.runner == PlaybookRunner::Trk {
+            return Ok(match self.native(cx).run(&self.path)? {
+                true => Outcome::Changed,
+                false => Outcome::Ok,
+            });
+        }
+        if cx.which("ansible-playbook").is_none() {
+            return Err(Error::Path {
+                path: self.path.clone(),
+                message: "playbook_runner is \"ansible\" but ansible-playbook is not \
+                          installed; add ansible to [homebrew] formulae"
+                    .into(),
+            });
+        }
+        // The bundled callback plugin records each task as a JSON line while
+        // the default callback keeps streaming to the terminal.
+        let events = cx.trk_dir.join("state/ansible-events.jsonl");
+        let io = |err: io::Error| Error::Path {
+            path: events.clone(),
+            message: err.to_string(),
+        };
+        fs::create_dir_all(events.parent().unwrap_or(&cx.trk_dir)).map_err(io)?;
+        match fs::remove_file(&events) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(io(err)),
+            _ => {}
+        }
+        let mut playbook = cx
+            .cmd("ansible-playbook")
+            .arg(self.path.to_string_lossy())
+            .args(["-i", "127.0.0.1,"])
+            .env(
+                "ANSIBLE_CALLBACK_PLUGINS",
+                cx.trk_dir.join("ansible/callback_plugins"),
+            )
+            .env("ANSIBLE_CALLBACKS_ENABLED", "trk_events")
+            .env("TRK_ANSIBLE_EVENTS", &events);
+        for (name, value) in &self.vars {
+            playbook = playbook.args(["--extra-vars", &format!("{name}={value}")]);
+        }
+        let status = playbook.status();
+        let report = match fs::read_to_string(&events) {
+            Ok(text) => {
+                let text = cx.redact(&text);
+                fs::write(&events, &text).map_err(io)?;
+                Report::parse(&text).map_err(|message| Error::Path {
+                    path: events.clone(),
+                    message,
+                })?
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Report::default(),
+            Err(err) => return Err(io(err)),
+        };
+        if !report.tasks.is_empty() {
+            println!("\n{report}");
+        }
+        if let Err(err) = status {
+            return Err(match report.failures().next() {
+                Some(task) => Error::Task {
+                    path: task.location().into(),
+                    task: task.name.clone(),
+                    message: match task.message.as_str() {
+                        "" => task.status.to_string(),
+                        message => message.to_owned(),
+                    },
+                },
+                None => err,
+            });
+        }
+        Ok(match report.changed() || report.tasks.is_empty() {
+            true => Outcome::Changed,
+            false => Outcome::Ok,
+        })
+    }
+}
@@ -0,0 +1,291 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::facts::{Arch, Os};
+use crate::journal::file_digest;
+use crate::manifest::Release;
+use crate::plan::{Action, Change};
+use crate::secret;
+
+use super::{Outcome, Step};
+
+/// A prebuilt binary from a GitHub release, picked for this OS and
+/// architecture, checked against its SHA-256 and installed into
+/// [`Context::bin_dir`].
+pub struct ReleaseBinary {
+    name: String,
+    release: Release,
+}
+
+impl ReleaseBinary {
+    pub fn new(name: impl Into<String>, release: Release) -> Self {
+        Self {
+            name: name.into(),
+            release,
+        }
+    }
+
+    /// The tag to install: `version`, or for `latest` the tag GitHub's
+    /// `releases/latest` redirects to now, so that a new release replaces
+    /// the binary installed from an older one.
+    fn tag(&self, cx: &Context) -> Result<String> {
+        let version = self.release.version.as_str();
+        if version != "latest" {
+            return Ok(version.to_owned());
+        }
+        let latest = format!("{}/latest", self.releases(cx));
+        let curl = cx
+            .cmd("curl")
+            .args(["-fsSLI", "-o", "/dev/null", "-w", "%{url_effective}"])
+            .arg(secret::expand(cx, &latest)?);
+        let found = curl.output()?.check(&curl)?.stdout;
+        found
+            .trim()
+            .rsplit_once("/tag/")
+            .map(|(_, tag)| tag.to_owned())
+            .filter(|tag| !tag.is_empty())
+            .ok_or_else(|| self.error(format!("{latest} does not lead to a release tag")))
+    }
+
+    /// The asset's file name for this machine.
+    fn asset(&self, cx: &Context, tag: &str) -> Result<String> {
+        let os = match self.release.os.get(cx.facts.os.as_str()) {
+            Some(name) => name.to_string(),
+            None => match &cx.facts.os {
+                Os::Macos => "darwin".into(),
+                other => other.to_string(),
+            },
+        };
+        let arch = match self.release.arch.get(cx.facts.arch.as_str()) {
+            Some(name) => name.to_string(),
+            None => match &cx.facts.arch {
+                Arch::X86_64 => "amd64".into(),
+                other => other.to_string(),
+            },
+        };
+        Ok(self.release.asset.render(&[
+            ("name", &self.name),
+            ("tag", tag),
+            ("version", tag.strip_prefix('v').unwrap_or(tag)),
+            ("os", &os),
+            ("arch", &arch),
+        ]))
+    }
+
+    fn releases(&self, cx: &Context) -> String {
+        format!(
+            "{}/{}/releases",
+            cx.github_url.trim_end_matches('/'),
+            self.release.repo
+        )
+    }
+
+    /// Download URL of a file attached to the release `tag`.
+    fn url(&self, cx: &Context, tag: &str, file: &str) -> String {
+        format!("{}/download/{tag}/{file}", self.releases(cx))
+    }
+
+    fn bin(&self) -> &str {
+        self.release.bin.as_ref().map_or(&self.name, |b| b.as_str())
+    }
+
+    fn dest(&self, cx: &Context) -> PathBuf {
+        cx.bin_dir.join(self.bin())
+    }
+
+    /// Records which URL the installed binary came from, so a later run
+    /// can tell whether the manifest asks for something else.
+    fn receipt(&self, cx: &Context) -> PathBuf {
+        cx.trk_dir.join("state/releases").join(&self.name)
+    }
+
+    fn installed(&self, cx: &Context, url: &str) -> bool {
+        self.dest(cx).is_file()
+            && fs::read_to_string(self.receipt(cx))
+                .is_ok_and(|receipt| receipt.lines().next() == Some(url))
+    }
+
+    /// The pinned hash, or the one published next to the asset.
+    fn expected_sha256(&self, cx: &Context, tag: &str, asset: &str, dir: &Path) -> Result<String> {
+        if let Some(sha256) = self.release.sha256.get(asset) {
+            return Ok(sha256.as_str().to_ascii_lowercase());
+        }
+        let Some(checksums) = &self.release.checksums else {
+            return Err(self.error(format!(
+                "nothing to verify `{asset}` against; set `checksums` or `sha256.\"{asset}\"`"
+            )));
+        };
+        let file = checksums.render(&[("name", &self.name)]);
+        let path = dir.join("checksums");
+        download(cx, &self.url(cx, tag, &file), &path)?;
+        let text = fs::read_to_string(&path).map_err(|err| Error::Path {
+            path: path.clone(),
+            message: err.to_string(),
+        })?;
+        published_sha256(&text, asset)
+            .map(|sha256| sha256.to_ascii_lowercase())
+            .ok_or_else(|| self.error(format!("`{file}` has no checksum for `{asset}`")))
+    }
+
+    /// The binary itself: the download, or the file named [`Self::bin`]
+    /// inside it when it is an archive.
+    fn unpack(&self, cx: &Context, archive: &Path, dir: &Path) -> Result<PathBuf> {
+        let name = archive.to_string_lossy();
+        let into = dir.join("unpacked");
+        let unpack = if name.ends_with(".zip") {
+            cx.cmd("unzip").args(["-q", "-o", &name, "-d"])
+        } else if [".tar.gz", ".tgz", ".tar.xz", ".tar.bz2", ".tar"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+        {
+            cx.cmd("tar").args(["-xf", &name, "-C"])
+        } else {
+            return Ok(archive.to_owned());
+        };
+        fs::create_dir_all(&into).map_err(|err| Error::Path {
+            path: into.clone(),
+            message: err.to_string(),
+        })?;
+        let unpack = unpack.arg(into.to_string_lossy());
+        unpack.output()?.check(&unpack)?;
+        find_file(&into, self.bin()).ok_or_else(|| {
+            self.error(format!(
+                "`{}` has no file named `{}`",
+                archive.file_name().unwrap_or_default().to_string_lossy(),
+                self.bin()
+            ))
+        })
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Release {
+            name: self.name.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Step for ReleaseBinary {
+    fn name(&self) -> String {
+        format!("release:{}", self.name)
+    }
+
+    fn describe(&self) -> String {
+        format!("Install {} from {}", self.name, self.release.repo)
+    }
+
+    fn inputs(&self) -> String {
+        format!("{:?}", self.release)
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        let tag = self.tag(cx)?;
+        let url = self.url(cx, &tag, &self.asset(cx, &tag)?);
+        let dest = self.dest(cx).display().to_string();
+        Ok(if self.installed(cx, &url) {
+            Vec::new()
+        } else if self.dest(cx).exists() {
+            vec![Change::new(Action::Change, dest).detail(url)]
+        } else {
+            vec![Change::new(Action::Add, dest).detail(url)]
+        })
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        let tag = self.tag(cx)?;
+        let asset = self.asset(cx, &tag)?;
+        let url = self.url(cx, &tag, &asset);
+        if self.installed(cx, &url) {
+            return Ok(Outcome::Ok);
+        }
+
+        let dir = cx.trk_dir.join("state/downloads").join(&self.name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        let archive = dir.join(&asset);
+        download(cx, &url, &archive)?;
+
+        let expected = self.expected_sha256(cx, &tag, &asset, &dir)?;
+        let actual = file_digest(&archive);
+        if actual != expected {
+            return Err(Error::Checksum {
+                asset,
+                expected,
+                actual,
+            });
+        }
+
+        let binary = self.unpack(cx, &archive, &dir)?;
+        install(&binary, &self.dest(cx))?;
+        let receipt = self.receipt(cx);
+        fs::create_dir_all(receipt.parent().expect("receipt is in state/releases"))?;
+        fs::write(&receipt, format!("{url}\n{actual}\n"))?;
+        fs::remove_dir_all(&dir)?;
+        Ok(Outcome::Changed)
+    }
+}
+
+fn download(cx: &Context, url: &str, to: &Path) -> Result<()> {
+    let curl = cx
+        .cmd("curl")
+        .args(["-fsSL", "--retry", "3", "-o"])
+        .arg(to.to_string_lossy())
+        .arg(secret::expand(cx, url)?);
+    curl.output()?.check(&curl).map(drop)
+}
+
+/// Finds `asset` in a checksum file: `sha256sum` lines (`<hash>  <file>`),
+/// or a lone hash for per-asset `.sha256` files.
+pub fn published_sha256(text: &str, asset: &str) -> Option<String> {
+    let lines: Vec<Vec<&str>> = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|fields| !fields.is_empty())
+        .collect();
+    let listed = lines.iter().find_map(|fields| match fields[..] {
+        [hash, file, ..] if file.trim_start_matches(['*', '.', '/']) == asset => Some(hash),
+        _ => None,
+    });
+    match (listed, &lines[..]) {
+        (Some(hash), _) => Some(hash.to_owned()),
+        (None, [only]) if only.len() == 1 => Some(only[0].to_owned()),
+        _ => None,
+    }
+}
+
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir).ok()?.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    let (files, dirs): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|entry| entry.file_type().is_ok_and(|t| !t.is_dir()));
+    files
+        .iter()
+        .find(|entry| entry.file_name() == name)
+        .map(|entry| entry.path())
+        .or_else(|| dirs.iter().find_map(|entry| find_file(&entry.path(), name)))
+}
+
+/// Copies `binary` to `dest` as an executable, replacing it atomically.
+fn install(binary: &Path, dest: &Path) -> Result<()> {
+    let dir = dest.parent().expect("bin dir");
+    fs::create_dir_all(dir)?;
+    let tmp = dir.join(format!(
+        ".{}.tmp",
+        dest.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::copy(binary, &tmp)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::rename(&tmp, dest).map_err(|err| Error::Path {
+        path: dest.to_owned(),
+        message: err.to_string(),
+    })
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::git::{plural, Checkout, Status, Synced};
+use crate::manifest::Pin;
+use crate::plan::{Action, Change};
+
+use super::{Outcome, Step};
+
+/// A git repository that is cloned on first run and fast-forwarded
+/// afterwards, see [`Checkout::sync`].
+pub struct Repo {
+    name: String,
+    checkout: Checkout,
+    pull: bool,
+}
+
+impl Repo {
+    pub fn new(name: impl Into<String>, url: impl Into<String>, path: impl AsRef<Path>) -> Self {
+        Self {
+            name: name.into(),
+            checkout: Checkout::new(path.as_ref(), url),
+            pull: true,
+        }
+    }
+
+    /// Keep `pin` checked out instead of the remote's default branch.
+    pub fn pin(mut self, pin: Pin) -> Self {
+        self.checkout = self.checkout.pin(pin);
+        self
+    }
+
+    /// Leave an existing checkout alone instead of pulling it.
+    pub fn no_pull(mut self) -> Self {
+        self.pull = false;
+        self
+    }
+
+    /// The name from the manifest, e.g. `trk`.
+    pub fn repo_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn checkout(&self) -> &Checkout {
+        &self.checkout
+    }
+}
+
+impl Step for Repo {
+    fn name(&self) -> String {
+        format!("repo:{}", self.name)
+    }
+
+    fn describe(&self) -> String {
+        format!("Checking out or Pull {} repo", self.name)
+    }
+
+    fn inputs(&self) -> String {
+        let pin = self.checkout.pinned().map(Pin::to_string);
+        format!(
+            "{} {} {} pull={}",
+            self.checkout.url(),
+            self.checkout.path().display(),
+            pin.as_deref().unwrap_or("default branch"),
+            self.pull
+        )
+    }
+
+    /// Fetches, which only touches remote-tracking refs, and compares
+    /// with the upstream branch. A checkout that needs a human is an
+    /// error here too.
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        let subject = self.checkout.path().display().to_string();
+        if !self.checkout.exists() {
+            return Ok(vec![
+                Change::new(Action::Add, subject).detail(format!("clone {}", self.checkout.url()))
+            ]);
+        }
+        if !self.pull {
+            return Ok(Vec::new());
+        }
+        let status = self.checkout.status(cx, true)?;
+        if let Some(problem) = status.problem(self.checkout.url(), cx.stash) {
+            return Err(Error::Repo {
+                path: self.checkout.path().to_owned(),
+                problem,
+            });
+        }
+        Ok(match status {
+            Status::OffPin { head, want, .. } => {
+                vec![Change::new(Action::Change, subject).detail(format!("{head} -> {want}"))]
+            }
+            Status::Behind { commits } => vec![Change::new(Action::Change, subject)
+                .detail(format!("fast-forward {commits} commit{}", plural(commits)))],
+            Status::Dirty {
+                files,
+                behind: commits,
+                ..
+            } if commits > 0 => vec![Change::new(Action::Change, subject).detail(format!(
+                "stash {} change{}, fast-forward {commits} commit{}",
+                files.len(),
+                plural(files.len() as u32),
+                plural(commits)
+            ))],
+            _ => Vec::new(),
+        })
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        if !self.pull && self.checkout.exists() {
+            return Ok(Outcome::Ok);
+        }
+        Ok(match self.checkout.sync(cx, cx.stash)? {
+            Synced::Cloned | Synced::FastForwarded { .. } | Synced::Switched { .. } => {
+                Outcome::Changed
+            }
+            Synced::Unchanged => Outcome::Ok,
+        })
+    }
+}
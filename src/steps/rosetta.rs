@@ -0,0 +1,72 @@
+use crate::context::Context;
+use crate::error::Result;
+use crate::plan::{Action, Change};
+
+use super::{Outcome, Step};
+
+/// Rosetta 2, installed on Apple Silicon when `$PRIVATE=y`.
+pub struct Rosetta;
+
+impl Rosetta {
+    fn applies(cx: &Context) -> Result<(), String> {
+        if !cx.facts.is_macos() {
+            Err(format!("not macOS but {}", cx.facts.os))
+        } else if !cx.facts.is_apple_silicon() {
+            Err(format!("architecture is {}", cx.facts.arch))
+        } else if !cx.private {
+            Err("PRIVATE is not y".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Rosetta is there when trk itself runs translated, or when an Intel
+    /// binary runs.
+    fn installed(cx: &Context) -> Result<bool> {
+        if cx.facts.rosetta {
+            return Ok(true);
+        }
+        cx.cmd("/usr/bin/arch")
+            .args(["-x86_64", "/usr/bin/true"])
+            .succeeds()
+    }
+}
+
+impl Step for Rosetta {
+    fn name(&self) -> String {
+        "rosetta".into()
+    }
+
+    fn describe(&self) -> String {
+        "Install Rosetta".into()
+    }
+
+    fn inputs(&self) -> String {
+        String::new()
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        Ok(if Self::applies(cx).is_ok() && !Self::installed(cx)? {
+            vec![Change::new(Action::Add, "Rosetta 2")]
+        } else {
+            Vec::new()
+        })
+    }
+
+    fn sudo(&self, cx: &Context) -> bool {
+        Self::applies(cx).is_ok() && !Self::installed(cx).unwrap_or(false)
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        if let Err(reason) = Self::applies(cx) {
+            return Ok(Outcome::Skipped(reason));
+        }
+        if Self::installed(cx)? {
+            return Ok(Outcome::Ok);
+        }
+        cx.cmd("sudo")
+            .args(["softwareupdate", "--install-rosetta"])
+            .status()?;
+        Ok(Outcome::Changed)
+    }
+}
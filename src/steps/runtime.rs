@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::journal::file_digest;
+use crate::manifest::{self, Manager};
+use crate::plan::{Action, Change};
+use crate::runtime::{self as manager, Lock, VersionManager};
+use crate::secret;
+
+use super::{Outcome, Step};
+
+/// What a version manager has of one runtime: whether its plugin is
+/// there, the installed versions and the global version.
+struct Installed {
+    plugin: bool,
+    versions: Vec<String>,
+    global: Option<String>,
+}
+
+impl Installed {
+    fn read(cx: &Context, manager: &dyn VersionManager, plugin: &str) -> Result<Self> {
+        if !manager.has_plugin(cx, plugin)? {
+            return Ok(Installed {
+                plugin: false,
+                versions: Vec::new(),
+                global: None,
+            });
+        }
+        Ok(Installed {
+            plugin: true,
+            versions: manager.installed(cx, plugin)?,
+            global: manager.global(cx, plugin)?,
+        })
+    }
+
+    fn has(&self, version: &str) -> bool {
+        self.versions.iter().any(|v| v == version)
+    }
+}
+
+/// What is missing of `runtime`, as plan changes. Aliases that are not
+/// locked yet are shown as written: planning does not go online.
+fn pending(
+    cx: &Context,
+    lock: &mut Lock,
+    plugin: &str,
+    runtime: &manifest::Runtime,
+) -> Result<Vec<Change>> {
+    let manager = manager::manager(cx)?;
+    let versions = manager::resolve(cx, manager.as_ref(), lock, plugin, &runtime.versions, false)?;
+    let installed = Installed::read(cx, manager.as_ref(), plugin)?;
+    let mut changes = Vec::new();
+    if !installed.plugin {
+        let mut change = Change::new(Action::Add, format!("plugin {plugin}"));
+        if let Some(url) = &runtime.plugin {
+            change = change.detail(url);
+        }
+        changes.push(change);
+    }
+    for resolved in &versions {
+        if !installed.has(&resolved.version) {
+            let mut change = Change::new(Action::Add, format!("{plugin} {}", resolved.version));
+            if !resolved.locked {
+                change = change.detail("not locked yet; resolved when installed");
+            } else if let Some(alias) = &resolved.alias {
+                change = change.detail(alias);
+            }
+            changes.push(change);
+        }
+    }
+    let global = &versions[0].version;
+    if installed.global.as_ref() != Some(global) {
+        let from = installed.global.as_deref().unwrap_or("none");
+        changes.push(
+            Change::new(Action::Change, format!("global {plugin}"))
+                .detail(format!("{from} -> {global}")),
+        );
+    }
+    Ok(changes)
+}
+
+/// Adds the plugin, installs the missing versions and sets the global one.
+/// Returns whether anything changed.
+fn apply(cx: &Context, lock: &mut Lock, plugin: &str, runtime: &manifest::Runtime) -> Result<bool> {
+    let manager = manager::manager(cx)?;
+    let versions = manager::resolve(cx, manager.as_ref(), lock, plugin, &runtime.versions, true)?;
+    let installed = Installed::read(cx, manager.as_ref(), plugin)?;
+    let global = &versions[0].version;
+    let missing: Vec<_> = versions
+        .iter()
+        .map(|resolved| &resolved.version)
+        .filter(|version| !installed.has(version))
+        .collect();
+    if installed.plugin && missing.is_empty() && installed.global.as_ref() == Some(global) {
+        return Ok(false);
+    }
+    if !installed.plugin {
+        // The plugin is only added when it is not there, so any failure is
+        // real: an unknown name, a bad URL or no network.
+        let url = match &runtime.plugin {
+            Some(url) => Some(secret::expand(cx, url)?),
+            None => None,
+        };
+        manager.add_plugin(cx, plugin, url.as_deref())?;
+    }
+    for version in missing {
+        manager.install(cx, plugin, version)?;
+    }
+    if installed.global.as_ref() != Some(global) {
+        manager.set_global(cx, plugin, global)?;
+    }
+    Ok(true)
+}
+
+/// Runs `f` with the lock at `path`, saving it if `f` locked new aliases
+/// and `save` is set. Without a path, aliases are resolved afresh.
+fn locked<T>(path: Option<&Path>, save: bool, f: impl FnOnce(&mut Lock) -> Result<T>) -> Result<T> {
+    let Some(path) = path else {
+        return f(&mut Lock::default());
+    };
+    let mut lock = Lock::load(path)?;
+    let before = lock.clone();
+    let result = f(&mut lock)?;
+    if save && lock != before {
+        lock.save()?;
+    }
+    Ok(result)
+}
+
+/// The part of a step's inputs that depends on the lock.
+fn lock_inputs(path: Option<&Path>) -> String {
+    path.map_or_else(|| "unlocked".into(), file_digest)
+}
+
+/// A runtime from `[runtimes]` in the manifest, installed through the
+/// manifest's version manager.
+pub struct Runtime {
+    manager: Manager,
+    plugin: String,
+    runtime: manifest::Runtime,
+    lock: Option<PathBuf>,
+}
+
+impl Runtime {
+    pub fn new(manager: Manager, plugin: impl Into<String>, runtime: manifest::Runtime) -> Self {
+        Self {
+            manager,
+            plugin: plugin.into(),
+            runtime,
+            lock: None,
+        }
+    }
+
+    /// Keeps the releases aliases resolve to in the lock file at `path`.
+    pub fn lock(mut self, path: impl Into<PathBuf>) -> Self {
+        self.lock = Some(path.into());
+        self
+    }
+}
+
+impl Step for Runtime {
+    fn name(&self) -> String {
+        format!("runtime:{}", self.plugin)
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Install {} {}",
+            self.plugin,
+            self.runtime.versions.join(", ")
+        )
+    }
+
+    fn inputs(&self) -> String {
+        let mut inputs = format!(
+            "{} {} {} {}",
+            self.manager,
+            self.plugin,
+            self.runtime.versions.join(" "),
+            lock_inputs(self.lock.as_deref())
+        );
+        if let Some(url) = &self.runtime.plugin {
+            inputs.push_str(&format!(" {url}"));
+        }
+        inputs
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        locked(self.lock.as_deref(), false, |lock| {
+            pending(cx, lock, &self.plugin, &self.runtime)
+        })
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        let changed = locked(self.lock.as_deref(), true, |lock| {
+            apply(cx, lock, &self.plugin, &self.runtime)
+        })?;
+        Ok(if changed {
+            Outcome::Changed
+        } else {
+            Outcome::Ok
+        })
+    }
+}
+
+/// The runtimes in a dotfiles `.tool-versions`, except those `[runtimes]`
+/// declares. The file is read when the step runs, so a bootstrap sees the
+/// dotfiles it just cloned.
+pub struct ToolVersions {
+    manager: Manager,
+    path: PathBuf,
+    declared: Vec<String>,
+    lock: Option<PathBuf>,
+}
+
+impl ToolVersions {
+    pub fn new(manager: Manager, path: impl Into<PathBuf>) -> Self {
+        Self {
+            manager,
+            path: path.into(),
+            declared: Vec::new(),
+            lock: None,
+        }
+    }
+
+    /// Keeps the releases aliases resolve to in the lock file at `path`.
+    pub fn lock(mut self, path: impl Into<PathBuf>) -> Self {
+        self.lock = Some(path.into());
+        self
+    }
+
+    /// Plugins `[runtimes]` already manages; their lines are ignored.
+    pub fn declared(mut self, plugins: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.declared = plugins.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The runtimes the file lists, less the declared ones.
+    pub fn runtimes(&self) -> Result<BTreeMap<String, manifest::Runtime>> {
+        let source = match fs::read_to_string(&self.path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => {
+                return Err(Error::Path {
+                    path: self.path.clone(),
+                    message: err.to_string(),
+                })
+            }
+        };
+        let mut runtimes =
+            manifest::parse_tool_versions(&source).map_err(|(line, message)| Error::Path {
+                path: self.path.clone(),
+                message: format!("line {line}: {message}"),
+            })?;
+        runtimes.retain(|plugin, _| !self.declared.contains(plugin));
+        Ok(runtimes)
+    }
+}
+
+impl Step for ToolVersions {
+    fn name(&self) -> String {
+        "tool-versions".into()
+    }
+
+    fn describe(&self) -> String {
+        format!("Install the runtimes in {}", self.path.display())
+    }
+
+    fn inputs(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.manager,
+            file_digest(&self.path),
+            self.declared.join(" "),
+            lock_inputs(self.lock.as_deref())
+        )
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        let runtimes = self.runtimes()?;
+        locked(self.lock.as_deref(), false, |lock| {
+            let mut changes = Vec::new();
+            for (plugin, runtime) in &runtimes {
+                changes.extend(pending(cx, lock, plugin, runtime)?);
+            }
+            Ok(changes)
+        })
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        let runtimes = self.runtimes()?;
+        if runtimes.is_empty() {
+            return Ok(Outcome::Skipped(format!(
+                "no runtimes in {}",
+                self.path.display()
+            )));
+        }
+        let changed = locked(self.lock.as_deref(), true, |lock| {
+            let mut changed = false;
+            for (plugin, runtime) in &runtimes {
+                changed |= apply(cx, lock, plugin, runtime)?;
+            }
+            Ok(changed)
+        })?;
+        Ok(if changed {
+            Outcome::Changed
+        } else {
+            Outcome::Ok
+        })
+    }
+}
@@ -0,0 +1,228 @@
+use crate::context::Context;
+use crate::error::Result;
+use crate::git::Checkout;
+use crate::manifest::{Ssh, SshAgent};
+use crate::plan::{Action, Change};
+use crate::ssh::{self, Host};
+
+use super::{Outcome, Step};
+
+/// trk's block of `~/.ssh/config` and the host keys of the declared hosts
+/// in `~/.ssh/known_hosts`, see [`crate::ssh`].
+pub struct SshConfig {
+    ssh: Ssh,
+}
+
+/// What is to change in the two files.
+struct Pending {
+    config: Option<String>,
+    /// Hosts whose keys are yet to be scanned.
+    unpinned: Vec<Host>,
+    /// The trusted lines already in the known_hosts block.
+    pinned: Vec<String>,
+    known_hosts: String,
+}
+
+impl SshConfig {
+    pub fn new(ssh: Ssh) -> Self {
+        Self { ssh }
+    }
+
+    fn pending(&self, cx: &Context) -> Result<Pending> {
+        let current = ssh::read_or_empty(&ssh::config_path(cx))?;
+        let config = ssh::with_block(&current, &ssh::config_block(cx, &self.ssh), true);
+        let known_hosts = ssh::read_or_empty(&ssh::known_hosts_path(cx))?;
+        let block = ssh::managed_block(&known_hosts).unwrap_or_default();
+        let mut unpinned = Vec::new();
+        let mut pinned = Vec::new();
+        for host in ssh::hosts(&self.ssh) {
+            if host.fingerprints.is_empty() {
+                continue;
+            }
+            let trusted = ssh::trusted(block, &host);
+            if trusted.is_empty() {
+                unpinned.push(host);
+            } else {
+                pinned.extend(trusted.into_iter().map(str::to_owned));
+            }
+        }
+        Ok(Pending {
+            config: (config != current).then_some(config),
+            unpinned,
+            pinned,
+            known_hosts,
+        })
+    }
+}
+
+impl Step for SshConfig {
+    fn name(&self) -> String {
+        "ssh:config".into()
+    }
+
+    fn describe(&self) -> String {
+        let hosts: Vec<String> = ssh::hosts(&self.ssh).into_iter().map(|h| h.name).collect();
+        format!("Configure ssh for {}", hosts.join(", "))
+    }
+
+    fn inputs(&self) -> String {
+        format!("{:?} {:?}", self.ssh.agent(), ssh::hosts(&self.ssh))
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        let pending = self.pending(cx)?;
+        let mut changes = Vec::new();
+        if pending.config.is_some() {
+            changes.push(
+                Change::new(Action::Change, "~/.ssh/config").detail("write trk's managed block"),
+            );
+        }
+        for host in &pending.unpinned {
+            changes.push(
+                Change::new(Action::Add, "~/.ssh/known_hosts")
+                    .detail(format!("pin {}'s host keys", host.name)),
+            );
+        }
+        let rebuilt = ssh::with_block(
+            &pending.known_hosts,
+            &ssh::known_hosts_block(&pending.pinned),
+            false,
+        );
+        if pending.unpinned.is_empty() && rebuilt != pending.known_hosts {
+            changes.push(
+                Change::new(Action::Remove, "~/.ssh/known_hosts")
+                    .detail("drop host keys no longer declared"),
+            );
+        }
+        Ok(changes)
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        let pending = self.pending(cx)?;
+        let mut changed = false;
+        if let Some(config) = &pending.config {
+            ssh::write_private(cx, &ssh::config_path(cx), config)?;
+            changed = true;
+        }
+        let mut lines = pending.pinned;
+        for host in &pending.unpinned {
+            lines.extend(ssh::scan(cx, host)?);
+        }
+        let known_hosts =
+            ssh::with_block(&pending.known_hosts, &ssh::known_hosts_block(&lines), false);
+        if known_hosts != pending.known_hosts {
+            ssh::write_private(cx, &ssh::known_hosts_path(cx), &known_hosts)?;
+            changed = true;
+        }
+        Ok(match changed {
+            true => Outcome::Changed,
+            false => Outcome::Ok,
+        })
+    }
+}
+
+/// Points the `origin` of managed checkouts on a declared host at its ssh
+/// remote once the host's key is in place, with `rewrite_remotes = true`.
+pub struct SshRemotes {
+    ssh: Ssh,
+    checkouts: Vec<Checkout>,
+}
+
+/// The remotes to rewrite, and why others still wait.
+struct Rewrites {
+    ready: Vec<(Checkout, String)>,
+    waiting: Vec<String>,
+}
+
+impl SshRemotes {
+    pub fn new(ssh: Ssh, checkouts: Vec<Checkout>) -> Self {
+        Self { ssh, checkouts }
+    }
+
+    fn rewrites(&self, cx: &Context) -> Result<Rewrites> {
+        let hosts = ssh::hosts(&self.ssh);
+        let mut rewrites = Rewrites {
+            ready: Vec::new(),
+            waiting: Vec::new(),
+        };
+        for checkout in &self.checkouts {
+            let Some(origin) = checkout.origin(cx)? else {
+                continue;
+            };
+            let Some((host, remote)) = ssh::ssh_remote(&origin, &hosts) else {
+                continue;
+            };
+            // Without a pinned key the first fetch would stop to ask.
+            if host.fingerprints.is_empty() {
+                continue;
+            }
+            match &host.identity {
+                Some(identity) => {
+                    let key = ssh::identity_path(cx, &self.ssh, identity);
+                    if !key.exists() {
+                        let waiting = format!("{} for {}", key.display(), host.name);
+                        if !rewrites.waiting.contains(&waiting) {
+                            rewrites.waiting.push(waiting);
+                        }
+                        continue;
+                    }
+                }
+                // The 1Password agent offers its keys without a file.
+                None if self.ssh.agent() == SshAgent::OnePassword => {}
+                None => continue,
+            }
+            rewrites.ready.push((checkout.clone(), remote));
+        }
+        Ok(rewrites)
+    }
+}
+
+impl Step for SshRemotes {
+    fn name(&self) -> String {
+        "ssh:remotes".into()
+    }
+
+    fn describe(&self) -> String {
+        "Switch repo remotes from https to ssh".into()
+    }
+
+    fn inputs(&self) -> String {
+        let paths: Vec<String> = self
+            .checkouts
+            .iter()
+            .map(|checkout| checkout.path().display().to_string())
+            .collect();
+        format!(
+            "{:?} {:?} {}",
+            self.ssh.agent(),
+            ssh::hosts(&self.ssh),
+            paths.join(" ")
+        )
+    }
+
+    fn plan(&self, cx: &Context) -> Result<Vec<Change>> {
+        Ok(self
+            .rewrites(cx)?
+            .ready
+            .into_iter()
+            .map(|(checkout, remote)| {
+                Change::new(Action::Change, checkout.path().display().to_string())
+                    .detail(format!("origin -> {remote}"))
+            })
+            .collect())
+    }
+
+    fn run(&self, cx: &Context) -> Result<Outcome> {
+        let rewrites = self.rewrites(cx)?;
+        for (checkout, remote) in &rewrites.ready {
+            checkout.set_origin(cx, remote)?;
+        }
+        Ok(if !rewrites.ready.is_empty() {
+            Outcome::Changed
+        } else if !rewrites.waiting.is_empty() {
+            Outcome::Skipped(format!("waiting for {}", rewrites.waiting.join(", ")))
+        } else {
+            Outcome::Ok
+        })
+    }
+}
@@ -0,0 +1,68 @@
+//! One sudo prompt per run.
+//!
+//! Before the first step that needs root, trk asks for the password once
+//! with `sudo -v` and then refreshes sudo's timestamp in the background, so
+//! the Homebrew installer, Rosetta, cask installers and `become` tasks find
+//! it cached however long the run takes.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::context::Context;
+use crate::error::{Error, Result};
+
+/// How often the timestamp is refreshed; sudo's default timeout is five
+/// minutes.
+pub const REFRESH: Duration = Duration::from_secs(60);
+
+/// A cached sudo timestamp, kept fresh until this is dropped.
+#[derive(Debug)]
+pub struct Sudo {
+    stop: Option<Sender<()>>,
+    keepalive: Option<JoinHandle<()>>,
+}
+
+impl Sudo {
+    /// Asks for the password, unless sudo has it cached already, for the
+    /// `steps` that need it, and refreshes it every `refresh` from then on.
+    pub fn acquire(cx: &Context, steps: &[String], refresh: Duration) -> Result<Self> {
+        let unavailable = |reason: String| Error::Sudo {
+            steps: steps.join(", "),
+            reason,
+        };
+        if cx.which("sudo").is_none() {
+            return Err(unavailable("sudo is not installed".into()));
+        }
+        if !cx.cmd("sudo").args(["-n", "true"]).succeeds()? {
+            let prompt = format!("[trk] sudo password for %u ({}): ", steps.join(", "));
+            cx.cmd("sudo")
+                .args(["-v", "-p", &prompt])
+                .status()
+                .map_err(|err| unavailable(err.to_string()))?;
+        }
+
+        let (stop, stopped) = mpsc::channel();
+        let refresh_cmd = cx.cmd("sudo").args(["-n", "-v"]);
+        let keepalive = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(refresh) {
+                // A failed refresh surfaces as a prompt or error in the
+                // step that needs it next.
+                let _ = refresh_cmd.output();
+            }
+        });
+        Ok(Self {
+            stop: Some(stop),
+            keepalive: Some(keepalive),
+        })
+    }
+}
+
+impl Drop for Sudo {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(keepalive) = self.keepalive.take() {
+            let _ = keepalive.join();
+        }
+    }
+}
@@ -0,0 +1,667 @@
+//! Expressions: what goes inside `{{ }}` and in `when:`.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use super::filters::{filter, method, test};
+use super::{to_string, truthy, Vars};
+
+/// Why an expression has no value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Fault {
+    /// A variable, attribute or item that does not exist. `default` and
+    /// `is defined` turn these into values.
+    Undefined(String),
+    Invalid(String),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Undefined(message) | Fault::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for Fault {
+    fn from(message: String) -> Self {
+        Fault::Invalid(message)
+    }
+}
+
+impl From<&str> for Fault {
+    fn from(message: &str) -> Self {
+        Fault::Invalid(message.to_owned())
+    }
+}
+
+pub(super) type Eval<T> = Result<T, Fault>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{name}`"),
+            Token::Str(s) => write!(f, "{s:?}"),
+            Token::Num(n) => write!(f, "`{n}`"),
+            Token::Op(op) => write!(f, "`{op}`"),
+        }
+    }
+}
+
+/// Longest first, so `==` is not read as `=`.
+const OPERATORS: [&str; 22] = [
+    "==", "!=", "<=", ">=", "//", "<", ">", "(", ")", "[", "]", "{", "}", ".", ",", ":", "-", "+",
+    "*", "/", "~", "|",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(format!("unclosed string in `{}`", expr.trim())),
+                    Some(&q) if q == c => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        s.push(match chars[i + 1] {
+                            'n' => '\n',
+                            't' => '\t',
+                            other => other,
+                        });
+                        i += 1;
+                    }
+                    Some(&ch) => s.push(ch),
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(Token::Str(s));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                // `list.0` is attribute access, not a float.
+                if chars[i] == '.' && !chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+                    break;
+                }
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| format!("bad number `{text}` in `{}`", expr.trim()))?;
+            tokens.push(Token::Num(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) else {
+                return Err(format!("unexpected `{c}` in `{}`", expr.trim()));
+            };
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Expr {
+    Literal(Value),
+    Var(String),
+    List(Vec<Expr>),
+    Dict(Vec<(Expr, Expr)>),
+    Attr(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    /// `target.name(args)`, such as `stdout.startswith('v')`.
+    Method(Box<Expr>, String, Vec<Expr>),
+    /// `target | name(args)`.
+    Filter(Box<Expr>, String, Vec<Expr>),
+    /// `target is [not] name`.
+    Test(Box<Expr>, String, bool),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    /// `then if condition else otherwise`.
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parses a whole expression.
+    pub(super) fn parse(expr: &str) -> Result<Expr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(expr)?,
+            pos: 0,
+        };
+        let ast = parser.expr()?;
+        match parser.peek() {
+            None => Ok(ast),
+            Some(token) => Err(format!("unexpected {token} in `{}`", expr.trim())),
+        }
+    }
+}
+
+/// Splits `for name in expr` into its parts.
+pub(super) fn parse_for(tag: &str) -> Result<(Vec<String>, Expr), String> {
+    let mut parser = Parser {
+        tokens: tokenize(tag)?,
+        pos: 0,
+    };
+    let mut names = Vec::new();
+    loop {
+        match parser.tokens.get(parser.pos).cloned() {
+            Some(Token::Ident(name)) if name != "in" => {
+                names.push(name);
+                parser.pos += 1;
+            }
+            _ => return Err(format!("expected `for <name> in <list>`, got `for {tag}`")),
+        }
+        if !parser.is_op(",") {
+            break;
+        }
+        parser.pos += 1;
+    }
+    if !parser.is_word("in") {
+        return Err(format!("expected `in` in `for {tag}`"));
+    }
+    parser.pos += 1;
+    let iterable = parser.expr()?;
+    match parser.peek() {
+        None => Ok((names, iterable)),
+        Some(token) => Err(format!("unexpected {token} in `for {tag}`")),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(w)) if w == word)
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token::Op(o)) if *o == op)
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), String> {
+        if self.is_op(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(match self.peek() {
+                Some(token) => format!("expected `{op}`, found {token}"),
+                None => format!("expected `{op}` at the end"),
+            })
+        }
+    }
+
+    fn name(&mut self, after: &str) -> Result<String, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(format!("expected a name after `{after}`")),
+        }
+    }
+
+    /// Arguments after an opening `(`, up to and including the `)`.
+    fn arguments(&mut self) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        while !self.is_op(")") {
+            args.push(self.expr()?);
+            if !self.is_op(")") {
+                self.expect_op(",")?;
+            }
+        }
+        self.pos += 1;
+        Ok(args)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let then = self.or()?;
+        if !self.is_word("if") {
+            return Ok(then);
+        }
+        self.pos += 1;
+        let condition = self.or()?;
+        let otherwise = if self.is_word("else") {
+            self.pos += 1;
+            Some(Box::new(self.expr()?))
+        } else {
+            None
+        };
+        Ok(Expr::If(Box::new(then), Box::new(condition), otherwise))
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.is_word("or") {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.is_word("and") {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.is_word("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.concat()?;
+        if self.is_word("is") {
+            self.pos += 1;
+            let negated = self.is_word("not");
+            if negated {
+                self.pos += 1;
+            }
+            let name = self.name("is")?;
+            return Ok(Expr::Test(Box::new(left), name, negated));
+        }
+        let op = match self.peek() {
+            Some(Token::Op(op @ ("==" | "!=" | "<" | ">" | "<=" | ">="))) => *op,
+            Some(Token::Ident(w)) if w == "in" => "in",
+            Some(Token::Ident(w))
+                if w == "not"
+                    && matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(i)) if i == "in") =>
+            {
+                self.pos += 1;
+                "not in"
+            }
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.concat()?;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn concat(&mut self) -> Result<Expr, String> {
+        let mut left = self.sum()?;
+        while self.is_op("~") {
+            self.pos += 1;
+            left = Expr::Binary("~", Box::new(left), Box::new(self.sum()?));
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        while let Some(Token::Op(op @ ("+" | "-"))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Op(op @ ("*" | "/" | "//"))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.is_op("-") {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.filtered()
+    }
+
+    fn filtered(&mut self) -> Result<Expr, String> {
+        let mut expr = self.postfix()?;
+        while self.is_op("|") {
+            self.pos += 1;
+            let name = self.name("|")?;
+            let args = if self.is_op("(") {
+                self.pos += 1;
+                self.arguments()?
+            } else {
+                Vec::new()
+            };
+            expr = Expr::Filter(Box::new(expr), name, args);
+        }
+        Ok(expr)
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.atom()?;
+        loop {
+            if self.is_op(".") {
+                self.pos += 1;
+                match self.tokens.get(self.pos).cloned() {
+                    Some(Token::Ident(name)) => {
+                        self.pos += 1;
+                        if self.is_op("(") {
+                            self.pos += 1;
+                            let args = self.arguments()?;
+                            expr = Expr::Method(Box::new(expr), name, args);
+                        } else {
+                            expr = Expr::Attr(Box::new(expr), name);
+                        }
+                    }
+                    Some(Token::Num(n)) if n.fract() == 0.0 => {
+                        self.pos += 1;
+                        expr = Expr::Index(Box::new(expr), Box::new(Expr::Literal(number(n))));
+                    }
+                    _ => return Err("expected an attribute name after `.`".into()),
+                }
+            } else if self.is_op("[") {
+                self.pos += 1;
+                let index = self.expr()?;
+                self.expect_op("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            return Err("expected a value at the end".into());
+        };
+        self.pos += 1;
+        Ok(match token {
+            Token::Str(s) => Expr::Literal(Value::String(s)),
+            Token::Num(n) => Expr::Literal(number(n)),
+            Token::Ident(word) => match word.as_str() {
+                "true" | "True" => Expr::Literal(Value::Bool(true)),
+                "false" | "False" => Expr::Literal(Value::Bool(false)),
+                "none" | "None" => Expr::Literal(Value::Null),
+                _ => Expr::Var(word),
+            },
+            Token::Op("(") => {
+                let inner = self.expr()?;
+                self.expect_op(")")?;
+                inner
+            }
+            Token::Op("[") => {
+                let mut items = Vec::new();
+                while !self.is_op("]") {
+                    items.push(self.expr()?);
+                    if !self.is_op("]") {
+                        self.expect_op(",")?;
+                    }
+                }
+                self.pos += 1;
+                Expr::List(items)
+            }
+            Token::Op("{") => {
+                let mut entries = Vec::new();
+                while !self.is_op("}") {
+                    let key = self.expr()?;
+                    self.expect_op(":")?;
+                    entries.push((key, self.expr()?));
+                    if !self.is_op("}") {
+                        self.expect_op(",")?;
+                    }
+                }
+                self.pos += 1;
+                Expr::Dict(entries)
+            }
+            other => return Err(format!("unexpected {other}")),
+        })
+    }
+}
+
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        Value::from(n)
+    }
+}
+
+impl Expr {
+    pub(super) fn eval(&self, vars: &Vars) -> Eval<Value> {
+        Ok(match self {
+            Expr::Literal(value) => value.clone(),
+            // Variables are rendered when they are used, like Ansible's,
+            // so play vars can refer to each other in any order.
+            Expr::Var(name) => match vars.get(name) {
+                Some(Value::String(s)) if s.contains("{{") || s.contains("{%") => {
+                    let mut others = vars.clone();
+                    others.remove(name);
+                    super::render(s, &others).map_err(Fault::Invalid)?
+                }
+                Some(value) => value.clone(),
+                None => return Err(Fault::Undefined(format!("`{name}` is undefined"))),
+            },
+            Expr::List(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| item.eval(vars))
+                    .collect::<Eval<_>>()?,
+            ),
+            Expr::Dict(entries) => {
+                let mut map = Map::new();
+                for (key, value) in entries {
+                    map.insert(to_string(&key.eval(vars)?), value.eval(vars)?);
+                }
+                Value::Object(map)
+            }
+            Expr::Attr(target, name) => {
+                let value = target.eval(vars)?;
+                match &value {
+                    Value::Object(map) => map.get(name).cloned(),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    Fault::Undefined(format!("`{}` has no attribute `{name}`", target.path()))
+                })?
+            }
+            Expr::Index(target, index) => {
+                let container = target.eval(vars)?;
+                let index = index.eval(vars)?;
+                let found = match (&container, &index) {
+                    (Value::Array(items), Value::Number(n)) => n.as_i64().and_then(|n| {
+                        let n = if n < 0 { items.len() as i64 + n } else { n };
+                        items.get(usize::try_from(n).ok()?).cloned()
+                    }),
+                    (Value::Object(map), Value::String(key)) => map.get(key).cloned(),
+                    _ => None,
+                };
+                found.ok_or_else(|| {
+                    Fault::Undefined(format!("`{}` has no item {index}", target.path()))
+                })?
+            }
+            Expr::Method(target, name, args) => {
+                let target = target.eval(vars)?;
+                let args = eval_all(args, vars)?;
+                method(&target, name, &args)?
+            }
+            Expr::Filter(target, name, args) => {
+                let target = match target.eval(vars) {
+                    Ok(value) => Some(value),
+                    Err(Fault::Undefined(_)) if matches!(name.as_str(), "default" | "d") => None,
+                    Err(fault) => return Err(fault),
+                };
+                let args = eval_all(args, vars)?;
+                filter(target, name, &args)?
+            }
+            Expr::Test(target, name, negated) => {
+                let target = match target.eval(vars) {
+                    Ok(value) => Some(value),
+                    Err(Fault::Undefined(_)) => None,
+                    Err(fault) => return Err(fault),
+                };
+                Value::Bool(test(target.as_ref(), name)? != *negated)
+            }
+            Expr::Neg(inner) => match inner.eval(vars)? {
+                Value::Number(n) => match n.as_i64() {
+                    Some(n) => Value::from(-n),
+                    None => Value::from(-n.as_f64().unwrap_or_default()),
+                },
+                other => return Err(format!("cannot negate {other}").into()),
+            },
+            Expr::Not(inner) => Value::Bool(!truthy(&inner.eval(vars)?)),
+            Expr::And(left, right) => {
+                let left = left.eval(vars)?;
+                if truthy(&left) {
+                    right.eval(vars)?
+                } else {
+                    left
+                }
+            }
+            Expr::Or(left, right) => {
+                let left = left.eval(vars)?;
+                if truthy(&left) {
+                    left
+                } else {
+                    right.eval(vars)?
+                }
+            }
+            Expr::If(then, condition, otherwise) => {
+                if truthy(&condition.eval(vars)?) {
+                    then.eval(vars)?
+                } else {
+                    match otherwise {
+                        Some(otherwise) => otherwise.eval(vars)?,
+                        None => Value::String(String::new()),
+                    }
+                }
+            }
+            Expr::Binary(op, left, right) => binary(op, left.eval(vars)?, right.eval(vars)?)?,
+        })
+    }
+
+    /// The expression as written, for error messages.
+    fn path(&self) -> String {
+        match self {
+            Expr::Var(name) => name.clone(),
+            Expr::Attr(target, name) => format!("{}.{name}", target.path()),
+            Expr::Index(target, _) => format!("{}[...]", target.path()),
+            _ => "value".into(),
+        }
+    }
+}
+
+fn eval_all(exprs: &[Expr], vars: &Vars) -> Eval<Vec<Value>> {
+    exprs.iter().map(|expr| expr.eval(vars)).collect()
+}
+
+fn binary(op: &str, left: Value, right: Value) -> Eval<Value> {
+    let numbers = || Some((left.as_f64()?, right.as_f64()?));
+    let both_ints = left.is_i64() && right.is_i64();
+    Ok(match op {
+        "==" => Value::Bool(equal(&left, &right)),
+        "!=" => Value::Bool(!equal(&left, &right)),
+        "in" => Value::Bool(contains(&right, &left)?),
+        "not in" => Value::Bool(!contains(&right, &left)?),
+        "~" => Value::String(to_string(&left) + &to_string(&right)),
+        "+" => match (&left, &right) {
+            (Value::String(a), Value::String(b)) => Value::String(format!("{a}{b}")),
+            (Value::Array(a), Value::Array(b)) => Value::Array([a.clone(), b.clone()].concat()),
+            _ => arithmetic(op, numbers(), both_ints, &left, &right)?,
+        },
+        "-" | "*" | "/" | "//" => arithmetic(op, numbers(), both_ints, &left, &right)?,
+        _ => {
+            let ordering = match (&left, &right) {
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                _ => numbers().and_then(|(a, b)| a.partial_cmp(&b)),
+            };
+            let Some(ordering) = ordering else {
+                return Err(format!("cannot compare {left} and {right} with `{op}`").into());
+            };
+            Value::Bool(match op {
+                "<" => ordering.is_lt(),
+                ">" => ordering.is_gt(),
+                "<=" => ordering.is_le(),
+                _ => ordering.is_ge(),
+            })
+        }
+    })
+}
+
+fn arithmetic(
+    op: &str,
+    numbers: Option<(f64, f64)>,
+    both_ints: bool,
+    left: &Value,
+    right: &Value,
+) -> Eval<Value> {
+    let Some((a, b)) = numbers else {
+        return Err(format!("cannot use `{op}` on {left} and {right}").into());
+    };
+    if matches!(op, "/" | "//") && b == 0.0 {
+        return Err("division by zero".into());
+    }
+    let result = match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => return Ok(Value::from(a / b)),
+        _ => (a / b).floor(),
+    };
+    Ok(if both_ints {
+        number(result)
+    } else {
+        Value::from(result)
+    })
+}
+
+pub(super) fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => left == right,
+    }
+}
+
+fn contains(haystack: &Value, needle: &Value) -> Eval<bool> {
+    Ok(match haystack {
+        Value::String(s) => s.contains(
+            needle
+                .as_str()
+                .ok_or_else(|| format!("`in` a string needs a string on the left, not {needle}"))?,
+        ),
+        Value::Array(items) => items.iter().any(|item| equal(item, needle)),
+        Value::Object(map) => needle.as_str().is_some_and(|key| map.contains_key(key)),
+        other => return Err(format!("cannot look for a value in {other}").into()),
+    })
+}
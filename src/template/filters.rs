@@ -0,0 +1,269 @@
+//! Filters (`x | lower`), tests (`x is defined`) and the string and dict
+//! methods Ansible users call on values (`x.startswith('v')`).
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::expr::{equal, Eval, Fault};
+use super::{to_string, truthy};
+
+const FILTERS: &str = "default, lower, upper, capitalize, trim, join, split, replace, length, \
+                       first, last, unique, sort, reverse, int, float, bool, string, list, \
+                       basename and dirname";
+
+const TESTS: &str = "defined, undefined, none, string, number, sequence, mapping, true, false, \
+                     changed, skipped, failed and succeeded";
+
+/// Applies filter `name`. `target` is `None` only for an undefined value
+/// given to `default`.
+pub(super) fn filter(target: Option<Value>, name: &str, args: &[Value]) -> Eval<Value> {
+    let arity = |max: usize| -> Eval<()> {
+        if args.len() > max {
+            Err(format!("`{name}` takes at most {max} arguments").into())
+        } else {
+            Ok(())
+        }
+    };
+    if matches!(name, "default" | "d") {
+        arity(2)?;
+        let fallback = args
+            .first()
+            .cloned()
+            .unwrap_or(Value::String(String::new()));
+        let falsy_too = args.get(1).is_some_and(truthy);
+        return Ok(match target {
+            Some(value) if !falsy_too || truthy(&value) => value,
+            _ => fallback,
+        });
+    }
+    let value = target.expect("only `default` sees undefined values");
+    let string_arg = |i: usize, default: &str| -> String {
+        args.get(i)
+            .map(to_string)
+            .unwrap_or_else(|| default.to_owned())
+    };
+    Ok(match name {
+        "lower" | "upper" | "capitalize" | "trim" => {
+            arity(0)?;
+            let s = to_string(&value);
+            Value::String(match name {
+                "lower" => s.to_lowercase(),
+                "upper" => s.to_uppercase(),
+                "capitalize" => {
+                    let mut chars = s.chars();
+                    match chars.next() {
+                        Some(first) => first
+                            .to_uppercase()
+                            .chain(chars.flat_map(char::to_lowercase))
+                            .collect(),
+                        None => s,
+                    }
+                }
+                _ => s.trim().to_owned(),
+            })
+        }
+        "join" => {
+            arity(1)?;
+            let items = sequence(&value, name)?;
+            Value::String(
+                items
+                    .iter()
+                    .map(to_string)
+                    .collect::<Vec<_>>()
+                    .join(&string_arg(0, "")),
+            )
+        }
+        "split" => {
+            arity(1)?;
+            let s = to_string(&value);
+            let parts: Vec<Value> = match args.first() {
+                Some(sep) => s.split(to_string(sep).as_str()).map(Value::from).collect(),
+                None => s.split_whitespace().map(Value::from).collect(),
+            };
+            Value::Array(parts)
+        }
+        "replace" => {
+            arity(2)?;
+            if args.len() < 2 {
+                return Err("`replace` needs the text to replace and its replacement".into());
+            }
+            Value::String(to_string(&value).replace(&string_arg(0, ""), &string_arg(1, "")))
+        }
+        "length" | "count" => {
+            arity(0)?;
+            Value::from(match &value {
+                Value::String(s) => s.chars().count(),
+                Value::Array(items) => items.len(),
+                Value::Object(map) => map.len(),
+                other => return Err(format!("`{name}` of {other}").into()),
+            })
+        }
+        "first" | "last" => {
+            arity(0)?;
+            let items = sequence(&value, name)?;
+            let item = if name == "first" {
+                items.first()
+            } else {
+                items.last()
+            };
+            item.cloned()
+                .ok_or_else(|| Fault::Undefined(format!("`{name}` of an empty list")))?
+        }
+        "unique" => {
+            arity(0)?;
+            let mut unique: Vec<Value> = Vec::new();
+            for item in sequence(&value, name)? {
+                if !unique.iter().any(|seen| equal(seen, &item)) {
+                    unique.push(item);
+                }
+            }
+            Value::Array(unique)
+        }
+        "sort" => {
+            arity(0)?;
+            let mut items = sequence(&value, name)?;
+            items.sort_by(|a, b| match (a.as_f64(), b.as_f64()) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                _ => to_string(a).cmp(&to_string(b)),
+            });
+            Value::Array(items)
+        }
+        "reverse" => {
+            arity(0)?;
+            match value {
+                Value::String(s) => Value::String(s.chars().rev().collect()),
+                other => {
+                    let mut items = sequence(&other, name)?;
+                    items.reverse();
+                    Value::Array(items)
+                }
+            }
+        }
+        "int" => {
+            arity(1)?;
+            let fallback = args.first().cloned().unwrap_or(Value::from(0));
+            match &value {
+                Value::Number(n) => Value::from(n.as_f64().unwrap_or_default().trunc() as i64),
+                Value::Bool(b) => Value::from(i64::from(*b)),
+                Value::String(s) => s
+                    .trim()
+                    .parse::<i64>()
+                    .map(Value::from)
+                    .or_else(|_| {
+                        s.trim()
+                            .parse::<f64>()
+                            .map(|f| Value::from(f.trunc() as i64))
+                    })
+                    .unwrap_or(fallback),
+                _ => fallback,
+            }
+        }
+        "float" => {
+            arity(0)?;
+            Value::from(match &value {
+                Value::Number(n) => n.as_f64().unwrap_or_default(),
+                Value::String(s) => s.trim().parse().unwrap_or_default(),
+                Value::Bool(b) => f64::from(u8::from(*b)),
+                _ => 0.0,
+            })
+        }
+        "bool" => {
+            arity(0)?;
+            Value::Bool(match &value {
+                Value::String(s) => {
+                    matches!(
+                        s.trim().to_ascii_lowercase().as_str(),
+                        "yes" | "on" | "true" | "1" | "y"
+                    )
+                }
+                other => truthy(other),
+            })
+        }
+        "string" => {
+            arity(0)?;
+            Value::String(to_string(&value))
+        }
+        "list" => {
+            arity(0)?;
+            Value::Array(sequence(&value, name)?)
+        }
+        "basename" | "dirname" => {
+            arity(0)?;
+            let s = to_string(&value);
+            let path = Path::new(&s);
+            let part = if name == "basename" {
+                path.file_name().map(|n| n.to_string_lossy().into_owned())
+            } else {
+                path.parent().map(|p| p.to_string_lossy().into_owned())
+            };
+            Value::String(part.unwrap_or_default())
+        }
+        other => {
+            return Err(format!("unsupported filter `{other}`; trk has {FILTERS}").into());
+        }
+    })
+}
+
+/// Whether `target` passes test `name`; `None` is an undefined value.
+pub(super) fn test(target: Option<&Value>, name: &str) -> Eval<bool> {
+    if let "defined" | "undefined" = name {
+        return Ok(target.is_some() == (name == "defined"));
+    }
+    let Some(value) = target else {
+        return Err(Fault::Undefined(format!(
+            "`is {name}` on an undefined value"
+        )));
+    };
+    let field = |key: &str| value.get(key).is_some_and(truthy);
+    Ok(match name {
+        "none" => value.is_null(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "sequence" | "iterable" => value.is_array() || value.is_string() || value.is_object(),
+        "mapping" => value.is_object(),
+        "true" => *value == Value::Bool(true),
+        "false" => *value == Value::Bool(false),
+        "changed" | "change" => field("changed"),
+        "skipped" | "skip" => field("skipped"),
+        "failed" | "failure" => field("failed"),
+        "succeeded" | "success" | "successful" => !field("failed"),
+        other => return Err(format!("unsupported test `{other}`; trk has {TESTS}").into()),
+    })
+}
+
+/// The Python string and dict methods templates use.
+pub(super) fn method(target: &Value, name: &str, args: &[Value]) -> Eval<Value> {
+    let arg = |i: usize| args.get(i).map(to_string);
+    Ok(match (target, name) {
+        (Value::String(s), "startswith") => Value::Bool(s.starts_with(&arg(0).unwrap_or_default())),
+        (Value::String(s), "endswith") => Value::Bool(s.ends_with(&arg(0).unwrap_or_default())),
+        (Value::String(s), "strip") => Value::String(s.trim().to_owned()),
+        (Value::String(s), "lower") => Value::String(s.to_lowercase()),
+        (Value::String(s), "upper") => Value::String(s.to_uppercase()),
+        (Value::String(_), "split" | "replace") => filter(Some(target.clone()), name, args)?,
+        (Value::Object(map), "get") => map
+            .get(&arg(0).unwrap_or_default())
+            .cloned()
+            .unwrap_or_else(|| args.get(1).cloned().unwrap_or(Value::Null)),
+        (Value::Object(map), "keys") => {
+            Value::Array(map.keys().cloned().map(Value::from).collect())
+        }
+        (Value::Object(map), "values") => Value::Array(map.values().cloned().collect()),
+        (Value::Object(map), "items") => Value::Array(
+            map.iter()
+                .map(|(k, v)| Value::Array(vec![Value::from(k.clone()), v.clone()]))
+                .collect(),
+        ),
+        (other, _) => return Err(format!("unsupported method `{name}` on {other}").into()),
+    })
+}
+
+fn sequence(value: &Value, filter: &str) -> Eval<Vec<Value>> {
+    Ok(match value {
+        Value::Array(items) => items.clone(),
+        Value::Object(map) => map.keys().cloned().map(Value::from).collect(),
+        Value::String(s) => s.chars().map(|c| Value::from(c.to_string())).collect(),
+        other => return Err(format!("`{filter}` needs a list, not {other}").into()),
+    })
+}
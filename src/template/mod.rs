@@ -0,0 +1,314 @@
+//! The Jinja that Ansible users write, shared by task arguments, `when:`
+//! and `changed_when:` conditions, loops and `template` files.
+//!
+//! Expressions have variables with attribute and index access, literals,
+//! arithmetic and `~`, comparisons, `in`, `and`/`or`/`not`, inline `if`,
+//! `is` tests such as `defined` and filters such as `default`, `lower` and
+//! `join`. Templates add `{% if %}`/`{% elif %}`/`{% else %}`, `{% for %}`
+//! (with `loop.index`, `loop.first` and `loop.last`), `{# comments #}` and
+//! `-` whitespace control; like Ansible's, a newline after a block tag is
+//! dropped.
+//!
+//! Values are JSON values, so registered task results can be used exactly
+//! as Ansible exposes them.
+
+mod expr;
+mod filters;
+
+use serde_json::{json, Map, Value};
+
+use expr::{parse_for, Eval, Expr};
+
+/// Variables visible to an expression.
+pub type Vars = Map<String, Value>;
+
+/// Renders `template`. A template that is a single `{{ expr }}` keeps the
+/// expression's type (a loop's list stays a list); anything else renders
+/// to a string.
+pub fn render(template: &str, vars: &Vars) -> Result<Value, String> {
+    let trimmed = template.trim();
+    if let Some(inner) = trimmed
+        .strip_prefix("{{")
+        .and_then(|rest| rest.strip_suffix("}}"))
+    {
+        if !inner.contains("{{") && !inner.contains("}}") && !inner.contains("{%") {
+            return eval(inner, vars);
+        }
+    }
+    render_string(template, vars).map(Value::String)
+}
+
+/// Renders `template` to text, such as a `template` task's file.
+pub fn render_string(template: &str, vars: &Vars) -> Result<String, String> {
+    let nodes = parse(template)?;
+    let mut out = String::new();
+    render_nodes(&nodes, vars, &mut out).map_err(|fault| fault.to_string())?;
+    Ok(out)
+}
+
+/// Renders every string inside `value`, recursively.
+pub fn render_value(value: &Value, vars: &Vars) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(s) => render(s, vars)?,
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_value(item, vars))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| Ok((k.clone(), render_value(v, vars)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// Evaluates a bare expression such as `result.rc == 0`.
+pub fn eval(expr: &str, vars: &Vars) -> Result<Value, String> {
+    Expr::parse(expr)?
+        .eval(vars)
+        .map_err(|fault| fault.to_string())
+}
+
+/// A `when:` condition: a bare expression, a `{{ }}` template, or a YAML
+/// boolean.
+pub fn condition(value: &Value, vars: &Vars) -> Result<bool, String> {
+    match value {
+        Value::String(s) if s.contains("{{") => render(s, vars).map(|v| truthy(&v)),
+        Value::String(s) => eval(s, vars).map(|v| truthy(&v)),
+        other => Ok(truthy(other)),
+    }
+}
+
+/// Python's truthiness, plus YAML 1.1's `yes`/`no` spelled as strings.
+pub fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !matches!(
+            s.to_ascii_lowercase().as_str(),
+            "" | "false" | "no" | "off" | "0"
+        ),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// How a value is written into a string template.
+pub fn to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        Value::Bool(true) => "True".into(),
+        Value::Bool(false) => "False".into(),
+        other => other.to_string(),
+    }
+}
+
+/// A piece of a template.
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Expr(Expr),
+    /// `(condition, body)` for the `if` and each `elif`, then the `else`.
+    If(Vec<(Expr, Vec<Node>)>, Vec<Node>),
+    For(Vec<String>, Expr, Vec<Node>),
+}
+
+/// A template split at its delimiters, before blocks are matched up.
+enum Segment<'a> {
+    Text(&'a str),
+    Expr(&'a str),
+    Tag(&'a str),
+}
+
+fn segments(template: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    // Set by `-}}`-style delimiters and by block tags respectively.
+    let mut trim_next = false;
+    let mut after_tag = false;
+    loop {
+        let next = ["{{", "{%", "{#"]
+            .into_iter()
+            .filter_map(|open| rest.find(open).map(|at| (at, open)))
+            .min();
+        let (mut text, tail) = match next {
+            Some((at, _)) => rest.split_at(at),
+            None => (rest, ""),
+        };
+        if trim_next {
+            text = text.trim_start();
+        } else if after_tag {
+            text = text.strip_prefix('\n').unwrap_or(text);
+        }
+        let Some((_, open)) = next else {
+            if !text.is_empty() {
+                segments.push(Segment::Text(text));
+            }
+            return Ok(segments);
+        };
+        let close = match open {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        let Some(len) = tail[2..].find(close) else {
+            return Err(format!("unclosed `{open}` in `{}`", template.trim()));
+        };
+        let mut inner = &tail[2..2 + len];
+        if let Some(stripped) = inner.strip_prefix('-') {
+            inner = stripped;
+            text = text.trim_end();
+        }
+        trim_next = match inner.strip_suffix('-') {
+            Some(stripped) => {
+                inner = stripped;
+                true
+            }
+            None => false,
+        };
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        after_tag = open == "{%";
+        match open {
+            "{{" => segments.push(Segment::Expr(inner)),
+            "{%" => segments.push(Segment::Tag(inner.trim())),
+            _ => {}
+        }
+        rest = &tail[2 + len + 2..];
+    }
+}
+
+fn parse(template: &str) -> Result<Vec<Node>, String> {
+    let mut segments = segments(template)?.into_iter();
+    let (nodes, end) = parse_nodes(&mut segments, &[])?;
+    match end {
+        None => Ok(nodes),
+        Some(tag) => Err(format!("unexpected `{{% {tag} %}}`")),
+    }
+}
+
+/// Parses nodes up to a tag whose keyword is in `ends`, which is returned.
+fn parse_nodes<'a>(
+    segments: &mut impl Iterator<Item = Segment<'a>>,
+    ends: &[&str],
+) -> Result<(Vec<Node>, Option<&'a str>), String> {
+    let mut nodes = Vec::new();
+    while let Some(segment) = segments.next() {
+        let tag = match segment {
+            Segment::Text(text) => {
+                nodes.push(Node::Text(text.to_owned()));
+                continue;
+            }
+            Segment::Expr(expr) => {
+                nodes.push(Node::Expr(Expr::parse(expr)?));
+                continue;
+            }
+            Segment::Tag(tag) => tag,
+        };
+        let (keyword, rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        if ends.contains(&keyword) {
+            return Ok((nodes, Some(tag)));
+        }
+        match keyword {
+            "if" => {
+                let mut branches = Vec::new();
+                let mut condition = Expr::parse(rest)?;
+                let otherwise = loop {
+                    let (body, end) = parse_nodes(segments, &["elif", "else", "endif"])?;
+                    branches.push((condition, body));
+                    match end.map(|end| end.split_once(char::is_whitespace).unwrap_or((end, ""))) {
+                        Some(("elif", next)) => condition = Expr::parse(next)?,
+                        Some(("else", _)) => {
+                            let (body, end) = parse_nodes(segments, &["endif"])?;
+                            if end.is_none() {
+                                return Err("`{% if %}` without `{% endif %}`".into());
+                            }
+                            break body;
+                        }
+                        Some(_) => break Vec::new(),
+                        None => return Err("`{% if %}` without `{% endif %}`".into()),
+                    }
+                };
+                nodes.push(Node::If(branches, otherwise));
+            }
+            "for" => {
+                let (names, iterable) = parse_for(rest)?;
+                let (body, end) = parse_nodes(segments, &["endfor"])?;
+                if end.is_none() {
+                    return Err("`{% for %}` without `{% endfor %}`".into());
+                }
+                nodes.push(Node::For(names, iterable, body));
+            }
+            other => {
+                return Err(format!(
+                    "unsupported tag `{{% {other} %}}`; trk has if, elif, else and for"
+                ))
+            }
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn render_nodes(nodes: &[Node], vars: &Vars, out: &mut String) -> Eval<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Expr(expr) => out.push_str(&to_string(&expr.eval(vars)?)),
+            Node::If(branches, otherwise) => {
+                let mut body = otherwise;
+                for (condition, branch) in branches {
+                    if truthy(&condition.eval(vars)?) {
+                        body = branch;
+                        break;
+                    }
+                }
+                render_nodes(body, vars, out)?;
+            }
+            Node::For(names, iterable, body) => {
+                let items = match iterable.eval(vars)? {
+                    Value::Array(items) => items,
+                    Value::Object(map) => map.into_iter().map(|(k, _)| Value::String(k)).collect(),
+                    Value::Null => Vec::new(),
+                    other => return Err(format!("cannot loop over {other}").into()),
+                };
+                let mut scope = vars.clone();
+                let length = items.len();
+                for (index, item) in items.into_iter().enumerate() {
+                    match (names.as_slice(), item) {
+                        ([name], item) => {
+                            scope.insert(name.clone(), item);
+                        }
+                        (names, Value::Array(parts)) if parts.len() == names.len() => {
+                            for (name, part) in names.iter().zip(parts) {
+                                scope.insert(name.clone(), part);
+                            }
+                        }
+                        (names, item) => {
+                            return Err(
+                                format!("cannot unpack {item} into {}", names.join(", ")).into()
+                            )
+                        }
+                    }
+                    scope.insert(
+                        "loop".into(),
+                        json!({
+                            "index": index + 1,
+                            "index0": index,
+                            "first": index == 0,
+                            "last": index + 1 == length,
+                            "length": length,
+                        }),
+                    );
+                    render_nodes(body, &scope, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
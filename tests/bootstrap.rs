@@ -0,0 +1,281 @@
+//! The whole bootstrap flow against a scripted fresh Mac.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use trk::exec::{FakeRunner, Reply};
+use trk::facts::Arch;
+use trk::journal::{Journal, Recorded};
+use trk::plan::{Action, Plan};
+use trk::runtime;
+use trk::steps::{self, Outcome, Selection};
+use trk::sudo::Sudo;
+
+mod common;
+
+/// Creates the `.git` directory a real clone would have, in the clone's
+/// target directory (the argument after the URL), and the playbook a
+/// clone of trk has.
+fn clone_effect() -> Reply {
+    Reply::ok().effect(|cmd| {
+        let target = Path::new(&cmd.get_args()[2]);
+        fs::create_dir_all(target.join(".git")).unwrap();
+        if cmd.get_args()[1].ends_with("/trk") {
+            fs::create_dir_all(target.join("ansible")).unwrap();
+            fs::write(
+                target.join("ansible/mac.yml"),
+                include_str!("../ansible/mac.yml"),
+            )
+            .unwrap();
+        }
+    })
+}
+
+/// A Mac with nothing installed, whose user can sudo.
+fn fresh_mac() -> Arc<FakeRunner> {
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("sudo")
+        .on(["sudo", "-n", "true"], Reply::exit(1))
+        .on(["sudo", "-v", "-p", "*"], Reply::ok())
+        .on(["sudo", "-n", "-v"], Reply::ok())
+        .on(
+            ["curl", "-fsSL", "--retry", "3", runtime::NODE_INDEX],
+            Reply::stdout(include_str!("fixtures/node-index.json")),
+        )
+        .on(["curl", "-fsSL", "..."], Reply::stdout("echo install brew"))
+        .on(
+            ["/bin/bash", "-c", "echo install brew"],
+            Reply::ok().provides("brew"),
+        )
+        .on(["brew", "tap"], Reply::ok())
+        .on(["brew", "tap", "*"], Reply::ok())
+        .on(["brew", "list", "*", "*"], Reply::exit(1))
+        .on(["brew", "install", "*", "*"], Reply::ok())
+        .on(["git", "clone", "..."], clone_effect())
+        .on(["bash", "-c", "*", "asdf", "..."], Reply::ok());
+    fake
+}
+
+fn count(fake: &FakeRunner, command: &str) -> usize {
+    fake.commands().iter().filter(|c| *c == command).count()
+}
+
+#[test]
+fn bootstrap_installs_everything_on_a_fresh_mac() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    let cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap();
+
+    steps::run_all(&cx, &steps::bootstrap(&cx), &Selection::All, &mut journal).unwrap();
+
+    let commands = fake.commands();
+    for expected in [
+        "/bin/bash -c echo install brew",
+        "brew install --formula git",
+        "brew install --formula mas",
+    ] {
+        assert!(
+            commands.iter().any(|c| c == expected),
+            "missing `{expected}` in {commands:#?}"
+        );
+    }
+    // The retired taps are declared absent, so nothing is tapped.
+    assert!(!commands.iter().any(|c| c.starts_with("brew tap ")));
+    let asdf = home.path().join(".asdf");
+    assert!(commands.iter().any(|c| *c
+        == format!(
+            "git clone https://github.com/asdf-vm/asdf.git {} --branch v0.9.0",
+            asdf.display()
+        )));
+    assert!(commands
+        .iter()
+        .any(|c| c.starts_with("bash -c") && c.ends_with("asdf install nodejs 24.11.0")));
+    // The playbook runs without Ansible.
+    assert!(!commands.iter().any(|c| c.contains("ansible")));
+
+    // SSH keys come from `trk ssh get`, not a downloaded ssh-manager.
+    assert!(!commands.iter().any(|c| c.contains("ssh-manager")));
+
+    // ~/.trk already held the journal, so the checkout was moved in next to it.
+    assert!(cx.trk_dir.join(".git").is_dir());
+    assert!(cx.trk_dir.join("state/bootstrap.json").is_file());
+
+    let journal = Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap();
+    assert_eq!(
+        journal.steps["formula:git"].result,
+        Recorded::Outcome(Outcome::Changed)
+    );
+    assert_eq!(
+        journal.steps["rosetta"].result,
+        Recorded::Outcome(Outcome::Skipped("architecture is x86_64".into()))
+    );
+    // There is no dotfiles playbook to include.
+    assert_eq!(
+        journal.steps["playbook"].result,
+        Recorded::Outcome(Outcome::Ok)
+    );
+}
+
+#[test]
+fn resume_skips_steps_that_finished() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    fake.once(["brew", "install", "--formula", "mas"], Reply::exit(1));
+    let cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+    let path = Journal::path(&cx.trk_dir, "bootstrap");
+
+    let mut journal = Journal::load(path.clone()).unwrap();
+    let err =
+        steps::run_all(&cx, &steps::bootstrap(&cx), &Selection::All, &mut journal).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "step `formula:mas` failed: `brew install --formula mas` exited with status 1"
+    );
+    assert!(matches!(
+        journal.steps["formula:mas"].result,
+        Recorded::Error(_)
+    ));
+
+    let mut journal = Journal::load(path).unwrap();
+    steps::run_all(
+        &cx,
+        &steps::bootstrap(&cx),
+        &Selection::Resume,
+        &mut journal,
+    )
+    .unwrap();
+
+    assert_eq!(count(&fake, "brew install --formula git"), 1);
+    assert_eq!(count(&fake, "brew install --formula mas"), 2);
+    // Both taps and the audit listed taps in the first run only.
+    assert_eq!(count(&fake, "brew tap"), 3);
+}
+
+#[test]
+fn only_runs_the_named_steps() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    fake.program("brew");
+    let cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap();
+
+    let only = Selection::Only(vec!["formula:git".into()]);
+    steps::run_all(&cx, &steps::bootstrap(&cx), &only, &mut journal).unwrap();
+
+    assert_eq!(
+        fake.commands(),
+        ["brew list --formula git", "brew install --formula git"]
+    );
+
+    let unknown = Selection::From("formula:nope".into());
+    let err = steps::run_all(&cx, &steps::bootstrap(&cx), &unknown, &mut journal).unwrap_err();
+    assert!(
+        err.to_string().starts_with("no step named `formula:nope`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn plan_lists_changes_without_running_them() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    let cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+
+    let plan = Plan::build(&cx, "bootstrap", &steps::bootstrap(&cx));
+
+    assert!(fake.commands().is_empty());
+    let git = plan.steps.iter().find(|s| s.step == "formula:git").unwrap();
+    assert_eq!(git.changes[0].action, Action::Add);
+    let text = plan.to_string();
+    assert!(text.contains("formula\n  + git\n  + mas\n"), "{text}");
+}
+
+#[test]
+fn asks_for_sudo_once_before_the_first_step() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    let cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap();
+
+    steps::run_all(&cx, &steps::bootstrap(&cx), &Selection::All, &mut journal).unwrap();
+
+    let calls = fake.calls();
+    assert_eq!(calls[0], ["sudo", "-n", "true"]);
+    assert_eq!(
+        calls[1],
+        [
+            "sudo",
+            "-v",
+            "-p",
+            "[trk] sudo password for %u (homebrew): "
+        ]
+    );
+    let prompts = calls.iter().filter(|call| call[..2] == ["sudo", "-v"]);
+    assert_eq!(prompts.count(), 1);
+}
+
+#[test]
+fn fails_early_when_sudo_is_unavailable() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    fake.once(["sudo", "-v", "..."], Reply::exit(1));
+    let cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap();
+
+    let err =
+        steps::run_all(&cx, &steps::bootstrap(&cx), &Selection::All, &mut journal).unwrap_err();
+
+    assert!(
+        err.to_string()
+            .starts_with("sudo is unavailable for homebrew: "),
+        "{err}"
+    );
+    assert!(fake.commands().iter().all(|c| c.starts_with("sudo ")));
+    assert!(journal.steps.is_empty());
+}
+
+#[test]
+fn no_sudo_leaves_privileged_steps_for_later() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    let mut cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+    cx.no_sudo = true;
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap();
+
+    let only = Selection::Only(vec!["homebrew".into(), "repo:trk".into()]);
+    steps::run_all(&cx, &steps::bootstrap(&cx), &only, &mut journal).unwrap();
+
+    assert!(!fake.commands().iter().any(|c| c.starts_with("sudo")));
+    assert!(!fake.commands().iter().any(|c| c.contains("install brew")));
+    assert_eq!(
+        journal.steps["homebrew"].result,
+        Recorded::Outcome(Outcome::Skipped(
+            "needs sudo; left for a run without --no-sudo".into()
+        ))
+    );
+    assert!(!journal.finished("homebrew", ""));
+    assert!(journal.finished("repo:trk", &journal.steps["repo:trk"].inputs));
+}
+
+#[test]
+fn sudo_stays_fresh_until_dropped() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = fresh_mac();
+    fake.once(["sudo", "-n", "true"], Reply::ok());
+    let cx = common::mac_on(Arch::X86_64, home.path(), fake.clone());
+
+    let sudo = Sudo::acquire(&cx, &["rosetta".into()], Duration::from_millis(5)).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    drop(sudo);
+
+    let refreshes = count(&fake, "sudo -n -v");
+    assert!(refreshes > 0);
+    // Cached already, so nothing prompted.
+    assert_eq!(count(&fake, "sudo -n true"), 1);
+    assert!(!fake.commands().iter().any(|c| c.starts_with("sudo -v")));
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(count(&fake, "sudo -n -v"), refreshes);
+}
@@ -0,0 +1,336 @@
+//! Brewfile parsing, `brew bundle check` and the Brewfile step.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use trk::brewfile::{capture, Brewfile, Check, Installed, Kind, RestartService, Value};
+use trk::exec::{FakeRunner, Reply};
+use trk::steps::{Bundle, Kind as PackageKind, Outcome, Package, Step};
+
+mod common;
+
+const BREWFILE: &str = r#"# Taps
+tap "homebrew/cask-fonts"
+tap "acme/private", "git@github.com:acme/homebrew-private.git"
+cask_args appdir: "~/Applications", require_sha: true
+
+# Tools
+brew "git"
+brew "postgresql@16", restart_service: :changed, link: true
+brew("emacs-plus", args: ["with-native-comp",
+                          "with-modern-icon"])
+brew "mysql", :restart_service => true
+cask "firefox", args: { appdir: "/Applications" }
+cask 'font-fira-code' # comment after an entry
+mas "Xcode", id: 497799835
+whalebrew "whalebrew/wget"
+vscode "rust-lang.rust-analyzer"
+"#;
+
+/// `brew info --json=v2 --installed` for a machine with git, an outdated
+/// postgresql, jq (not in the Brewfile) and firefox.
+const BREW_INFO: &str = r#"{
+  "formulae": [
+    {"name": "git", "full_name": "git", "aliases": [], "oldnames": [],
+     "versions": {"stable": "2.43.0"}, "outdated": false,
+     "installed": [{"version": "2.43.0", "installed_on_request": true}]},
+    {"name": "postgresql@16", "full_name": "postgresql@16", "aliases": ["postgresql"],
+     "versions": {"stable": "16.2"}, "outdated": true,
+     "installed": [{"version": "16.1", "installed_on_request": true}]},
+    {"name": "jq", "full_name": "jq", "versions": {"stable": "1.7.1"}, "outdated": false,
+     "installed": [{"version": "1.7.1", "installed_on_request": true}]},
+    {"name": "oniguruma", "full_name": "oniguruma", "versions": {"stable": "6.9.9"},
+     "outdated": false,
+     "installed": [{"version": "6.9.9", "installed_on_request": false}]}
+  ],
+  "casks": [
+    {"token": "firefox", "full_token": "firefox", "installed": "122.0",
+     "version": "122.0", "outdated": false}
+  ]
+}"#;
+
+fn parse(source: &str) -> trk::Result<Brewfile> {
+    Brewfile::parse(source, Path::new("Brewfile"))
+}
+
+#[test]
+fn parses_the_bundle_dsl() {
+    let brewfile = parse(BREWFILE).unwrap();
+
+    let names: Vec<_> = brewfile
+        .entries
+        .iter()
+        .map(|e| format!("{} {}", e.kind, e.name))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "tap homebrew/cask-fonts",
+            "tap acme/private",
+            "brew git",
+            "brew postgresql@16",
+            "brew emacs-plus",
+            "brew mysql",
+            "cask firefox",
+            "cask font-fira-code",
+            "mas Xcode",
+            "whalebrew whalebrew/wget",
+            "vscode rust-lang.rust-analyzer",
+        ]
+    );
+    let e = &brewfile.entries;
+    assert_eq!(
+        e[1].tap_url(),
+        Some("git@github.com:acme/homebrew-private.git")
+    );
+    assert_eq!(e[3].restart_service(), Some(RestartService::Changed));
+    assert_eq!(e[3].link(), Some(true));
+    assert_eq!(e[4].args(), ["--with-native-comp", "--with-modern-icon"]);
+    assert_eq!(e[4].line, 9);
+    assert_eq!(e[5].restart_service(), Some(RestartService::Always));
+    assert_eq!(e[8].mas_id(), Some(497799835));
+    assert_eq!(
+        brewfile.cask_args,
+        [
+            ("appdir".into(), Value::Str("~/Applications".into())),
+            ("require_sha".into(), Value::Bool(true)),
+        ]
+    );
+    assert_eq!(
+        e[1].to_string(),
+        r#"tap "acme/private", "git@github.com:acme/homebrew-private.git""#
+    );
+    assert_eq!(
+        e[3].to_string(),
+        r#"brew "postgresql@16", restart_service: :changed, link: true"#
+    );
+}
+
+#[test]
+fn reports_what_it_does_not_understand_by_line() {
+    let err = parse("brew \"git\"\nif OS.mac?\n  cask \"iterm2\"\nend\n").unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("Brewfile:2: `if`: conditionals are not supported"),
+        "{err}"
+    );
+
+    let err = parse("\n\nmas \"Xcode\"\n").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Brewfile:3: mas `Xcode` needs `id:` with its App Store id"
+    );
+
+    let err = parse("brew \"#{name}\"\n").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Brewfile:1: string interpolation is not supported"
+    );
+}
+
+#[test]
+fn check_finds_missing_outdated_and_extra() {
+    let brewfile = parse(BREWFILE).unwrap();
+    let mut installed = Installed::from_brew_info(BREW_INFO).unwrap();
+    installed.taps = vec!["homebrew/cask-fonts".into(), "homebrew/services".into()];
+    installed.vscode = vec!["Rust-Lang.Rust-Analyzer".into()];
+
+    let check = Check::new(&brewfile, &installed);
+
+    let missing: Vec<_> = check.missing.iter().map(|i| i.to_string()).collect();
+    assert_eq!(
+        missing,
+        [
+            "tap acme/private",
+            "brew emacs-plus",
+            "brew mysql",
+            "cask font-fira-code",
+            "mas Xcode (497799835)",
+            "whalebrew whalebrew/wget",
+        ]
+    );
+    assert_eq!(check.outdated.len(), 1);
+    assert_eq!(
+        check.outdated[0].to_string(),
+        "brew postgresql@16 16.1 -> 16.2"
+    );
+    assert_eq!(check.outdated[0].line, Some(8));
+    let extra: Vec<_> = check
+        .extra
+        .iter()
+        .map(|i| (i.kind, i.name.as_str()))
+        .collect();
+    assert_eq!(
+        extra,
+        [(Kind::Tap, "homebrew/services"), (Kind::Brew, "jq")]
+    );
+    assert!(!check.is_satisfied());
+
+    let json = serde_json::to_value(&check).unwrap();
+    assert_eq!(json["missing"][4]["id"], 497799835);
+    assert_eq!(json["outdated"][0]["latest"], "16.2");
+}
+
+#[test]
+fn step_changes_only_what_is_missing_or_outdated() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("Brewfile");
+    fs::write(
+        &path,
+        "tap \"homebrew/cask-fonts\"\ntap \"homebrew/bundle\"\nbrew \"git\"\n\
+         brew \"postgresql@16\", restart_service: :changed\nbrew \"jq\"\ncask \"firefox\"\n",
+    )
+    .unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew");
+    fake.on(
+        ["brew", "info", "--json=v2", "--installed"],
+        Reply::stdout(BREW_INFO),
+    )
+    .on(["brew", "tap"], Reply::stdout("homebrew/cask-fonts\n"))
+    .on(
+        ["brew", "upgrade", "--formula", "postgresql@16"],
+        Reply::ok(),
+    )
+    .on(
+        ["brew", "services", "restart", "postgresql@16"],
+        Reply::ok(),
+    );
+    let cx = common::mac(home.path(), fake.clone());
+    let step = Bundle::new(&path);
+    // firefox is current, so nothing runs as root, and homebrew/bundle is
+    // retired, so it is never tapped.
+    assert!(!step.sudo(&cx));
+
+    let plan: Vec<_> = step
+        .plan(&cx)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.subject, c.detail))
+        .collect();
+    assert_eq!(
+        plan,
+        [(
+            "brew postgresql@16".to_owned(),
+            Some("16.1 -> 16.2".to_owned())
+        )]
+    );
+
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    let changes: Vec<_> = fake
+        .commands()
+        .into_iter()
+        .filter(|c| !c.starts_with("brew info") && c != "brew tap")
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            "brew upgrade --formula postgresql@16",
+            "brew services restart postgresql@16",
+        ]
+    );
+
+    fs::write(&path, "brew \"git\"\ncask \"firefox\"\n").unwrap();
+    assert!(step.plan(&cx).unwrap().is_empty());
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Ok);
+}
+
+#[test]
+fn casks_need_sudo_only_when_something_would_change() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("Brewfile");
+    fs::write(&path, "cask \"firefox\"\ncask \"iterm2\"\n").unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew")
+        .on(
+            ["brew", "info", "--json=v2", "--installed"],
+            Reply::stdout(BREW_INFO),
+        )
+        .on(["brew", "tap"], Reply::ok())
+        .on(["brew", "list", "--cask", "firefox"], Reply::ok())
+        .on(["brew", "list", "..."], Reply::exit(1));
+    let mut cx = common::mac(home.path(), fake.clone());
+
+    assert!(Bundle::new(&path).sudo(&cx));
+    assert!(!Package::new(PackageKind::Cask, "firefox").sudo(&cx));
+    assert!(Package::new(PackageKind::Cask, "iterm2").sudo(&cx));
+    assert!(!Package::new(PackageKind::Formula, "wget").sudo(&cx));
+
+    // Without sudo the Brewfile step leaves the casks itself.
+    cx.no_sudo = true;
+    assert!(!Bundle::new(&path).sudo(&cx));
+}
+
+#[test]
+fn latest_packages_fail_on_output_brew_outdated_did_not_mean() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew")
+        .on(["brew", "list", "--formula", "jq"], Reply::ok())
+        .on(
+            ["brew", "outdated", "--formula", "jq", "--json=v2"],
+            Reply::stdout("Warning: jq 1.7.1 is already installed\n"),
+        );
+    let cx = common::mac(home.path(), fake.clone());
+
+    let err = Package::new(PackageKind::Formula, "jq")
+        .latest(true)
+        .run(&cx)
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("unexpected output from `brew outdated --formula jq --json=v2`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn capture_adds_unlisted_entries_below_a_marker() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("Brewfile");
+    let original = "# CLI\nbrew \"git\" # always\n\n# Apps\ncask \"iterm2\"\n";
+    fs::write(&path, original).unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew");
+    fake.program("mas");
+    fake.on(
+        ["brew", "info", "--json=v2", "--installed"],
+        Reply::stdout(BREW_INFO),
+    )
+    .on(
+        ["brew", "tap"],
+        Reply::stdout("homebrew/services\nacme/tools\n"),
+    )
+    .on(
+        ["brew", "leaves"],
+        Reply::stdout("git\njq\npostgresql@16\n"),
+    )
+    .on(["mas", "list"], Reply::stdout("497799835  Xcode  (15.2)\n"));
+    let cx = common::mac(home.path(), fake.clone());
+
+    // homebrew/services is retired; the tap audit reports it instead.
+    let added = capture::run(&cx, &path, true).unwrap();
+    assert_eq!(added.len(), 5);
+    assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+    capture::run(&cx, &path, false).unwrap();
+    let captured = fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        captured,
+        format!(
+            "{original}\n{}\ntap \"acme/tools\"\nbrew \"postgresql@16\"\nbrew \"jq\"\n\
+             cask \"firefox\"\nmas \"Xcode\", id: 497799835\n",
+            capture::SECTION
+        )
+    );
+    assert!(capture::run(&cx, &path, false).unwrap().is_empty());
+
+    // Later captures add to the section, even when it is not at the end.
+    let entries = parse("cask \"zoom\"\n").unwrap().entries;
+    let merged = capture::merge(&format!("{captured}\n# Work\ncask \"slack\"\n"), &entries);
+    assert!(
+        merged.contains("mas \"Xcode\", id: 497799835\ncask \"zoom\"\n\n# Work\n"),
+        "{merged}"
+    );
+}
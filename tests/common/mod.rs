@@ -0,0 +1,39 @@
+//! The machine the integration tests run against.
+
+// Each test file uses what it needs of this.
+#![allow(dead_code)]
+
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+
+use trk::exec::CommandRunner;
+use trk::facts::{homebrew_prefix, Arch, Facts, Os};
+use trk::Context;
+
+/// A Mac with the Command Line Tools and Homebrew's default prefix for
+/// `arch`.
+pub fn facts(arch: Arch) -> Facts {
+    Facts {
+        os: Os::Macos,
+        distro: None,
+        os_version: Some("14.4".into()),
+        homebrew_prefix: homebrew_prefix(&Os::Macos, &arch),
+        arch,
+        rosetta: false,
+        xcode_clt: true,
+    }
+}
+
+/// A context for `home` on an Apple Silicon Mac that runs every command
+/// through `runner`. The test's own `$PATH` comes after Homebrew's, for
+/// tests that run real commands.
+pub fn mac(home: &Path, runner: Arc<dyn CommandRunner>) -> Context {
+    mac_on(Arch::Arm64, home, runner)
+}
+
+/// [`mac`] on a Mac with `arch`.
+pub fn mac_on(arch: Arch, home: &Path, runner: Arc<dyn CommandRunner>) -> Context {
+    let path = env::var_os("PATH").unwrap_or_default();
+    Context::new(home.to_owned(), path, runner, facts(arch)).unwrap()
+}
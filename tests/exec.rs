@@ -0,0 +1,138 @@
+use std::ffi::OsStr;
+use std::sync::Arc;
+
+use trk::exec::{Cmd, CommandRunner, FakeRunner, Recorder, Reply, SystemRunner};
+
+#[test]
+fn fake_matches_wildcards_and_rest() {
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(["brew", "list", "--formula", "*"], Reply::exit(1))
+        .on(["git", "clone", "..."], Reply::stdout("cloned"));
+
+    let list = Cmd::new("brew")
+        .args(["list", "--formula", "git"])
+        .runner(fake.clone());
+    assert!(!list.succeeds().unwrap());
+
+    let clone = Cmd::new("git")
+        .args([
+            "clone",
+            "https://example.com/repo",
+            "/tmp/repo",
+            "--branch",
+            "main",
+        ])
+        .runner(fake.clone());
+    assert_eq!(clone.output().unwrap().stdout, "cloned");
+
+    assert_eq!(
+        fake.commands(),
+        [
+            "brew list --formula git",
+            "git clone https://example.com/repo /tmp/repo --branch main",
+        ]
+    );
+}
+
+#[test]
+fn fake_once_rules_answer_in_order() {
+    let fake = Arc::new(FakeRunner::new());
+    fake.once(["git", "rev-parse", "HEAD"], Reply::stdout("aaa"))
+        .once(["git", "rev-parse", "HEAD"], Reply::stdout("bbb"));
+    let head = Cmd::new("git")
+        .args(["rev-parse", "HEAD"])
+        .runner(fake.clone());
+
+    assert_eq!(head.output().unwrap().stdout, "aaa");
+    assert_eq!(head.output().unwrap().stdout, "bbb");
+    assert!(head.output().is_err());
+}
+
+#[test]
+fn fake_rejects_unexpected_commands() {
+    let fake = Arc::new(FakeRunner::new());
+    let err = Cmd::new("brew")
+        .arg("update")
+        .runner(fake)
+        .status()
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("no fake reply for `brew update`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn fake_status_fails_on_non_zero_exit() {
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(["brew", "update"], Reply::exit(1).stderr("offline"));
+    let err = Cmd::new("brew")
+        .arg("update")
+        .runner(fake)
+        .status()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "`brew update` exited with status 1");
+}
+
+#[test]
+fn provides_makes_program_visible() {
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(["/bin/bash", "-c", "*"], Reply::ok().provides("brew"));
+    assert_eq!(fake.which("brew", OsStr::new("")), None);
+
+    Cmd::new("/bin/bash")
+        .args(["-c", "install brew"])
+        .runner(fake.clone())
+        .status()
+        .unwrap();
+    assert!(fake.which("brew", OsStr::new("")).is_some());
+}
+
+#[test]
+fn recorded_session_replays() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = dir.path().join("session.json");
+    let recorder = Arc::new(Recorder::new(Arc::new(SystemRunner), &fixture));
+
+    let script = Cmd::new("sh")
+        .args(["-c", "echo out; echo err >&2; exit 3"])
+        .runner(recorder.clone());
+    let recorded = script.output().unwrap();
+    assert_eq!(recorded.status, 3);
+    assert_eq!(recorded.stdout, "out\n");
+    recorder.which("sh", OsStr::new("/bin:/usr/bin"));
+
+    let replay = Arc::new(FakeRunner::replay(&fixture).unwrap());
+    let replayed = Cmd::new("sh")
+        .args(["-c", "echo out; echo err >&2; exit 3"])
+        .runner(replay.clone())
+        .output()
+        .unwrap();
+    assert_eq!(replayed, recorded);
+    assert!(replay.which("sh", OsStr::new("")).is_some());
+}
+
+#[test]
+fn replay_keeps_programs_missing_until_installed() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = dir.path().join("session.json");
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(["brew", "install", "gh"], Reply::ok().provides("gh"));
+    let recorder = Arc::new(Recorder::new(fake, &fixture));
+    assert_eq!(recorder.which("gh", OsStr::new("")), None);
+    Cmd::new("brew")
+        .args(["install", "gh"])
+        .runner(recorder.clone())
+        .status()
+        .unwrap();
+    assert!(recorder.which("gh", OsStr::new("")).is_some());
+
+    let replay = Arc::new(FakeRunner::replay(&fixture).unwrap());
+    assert_eq!(replay.which("gh", OsStr::new("")), None);
+    Cmd::new("brew")
+        .args(["install", "gh"])
+        .runner(replay.clone())
+        .status()
+        .unwrap();
+    assert!(replay.which("gh", OsStr::new("")).is_some());
+}
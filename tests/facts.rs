@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use trk::exec::{CommandRunner, FakeRunner, Reply};
+use trk::facts::{self, Arch, Facts, Os};
+
+fn mac(translated: bool) -> Arc<dyn CommandRunner> {
+    let fake = Arc::new(FakeRunner::new());
+    let machine = if translated { "x86_64\n" } else { "arm64\n" };
+    fake.on(["/usr/bin/uname", "-s"], Reply::stdout("Darwin\n"))
+        .on(["/usr/bin/uname", "-m"], Reply::stdout(machine))
+        .on(
+            ["/usr/sbin/sysctl", "-n", "sysctl.proc_translated"],
+            Reply::stdout(if translated { "1\n" } else { "0\n" }),
+        )
+        .on(
+            ["/usr/bin/sw_vers", "-productVersion"],
+            Reply::stdout("14.2\n"),
+        )
+        .on(["/usr/bin/xcode-select", "-p"], Reply::exit(2));
+    fake
+}
+
+#[test]
+fn rosetta_shell_is_still_apple_silicon() {
+    let facts = Facts::detect(&mac(true));
+
+    assert_eq!(facts.os, Os::Macos);
+    assert_eq!(facts.arch, Arch::Arm64);
+    assert!(facts.rosetta);
+    assert_eq!(facts.homebrew_prefix.to_str(), Some("/opt/homebrew"));
+    assert!(!facts.xcode_clt);
+    assert_eq!(
+        facts.to_string(),
+        "macos 14.2 arm64 (Rosetta), Homebrew in /opt/homebrew, \
+         no Xcode Command Line Tools"
+    );
+    assert_eq!(Facts::detect(&mac(false)).arch, Arch::Arm64);
+}
+
+#[test]
+fn missing_sysctl_key_means_native() {
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(["/usr/bin/uname", "-s"], Reply::stdout("Darwin\n"))
+        .on(["/usr/bin/uname", "-m"], Reply::stdout("x86_64\n"))
+        .on(["/usr/sbin/sysctl", "..."], Reply::exit(1))
+        .on(["/usr/bin/sw_vers", "..."], Reply::stdout("12.6\n"))
+        .on(
+            ["/usr/bin/xcode-select", "-p"],
+            Reply::stdout("/Library/Developer/CommandLineTools\n"),
+        );
+    let facts = Facts::detect(&(fake as Arc<dyn CommandRunner>));
+
+    assert_eq!(facts.arch, Arch::X86_64);
+    assert!(!facts.rosetta);
+    assert!(facts.xcode_clt);
+    assert_eq!(facts.homebrew_prefix.to_str(), Some("/usr/local"));
+}
+
+#[test]
+fn linux_uses_linuxbrew() {
+    assert_eq!(
+        facts::homebrew_prefix(&Os::Linux, &Arch::Arm64).to_str(),
+        Some("/home/linuxbrew/.linuxbrew")
+    );
+    let release = "NAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\nID=ubuntu\nID_LIKE=debian\n";
+    assert_eq!(
+        facts::parse_os_release(release),
+        (Some("ubuntu".into()), Some("22.04".into()))
+    );
+}
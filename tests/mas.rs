@@ -0,0 +1,245 @@
+//! The Mac App Store preflight before the Brewfile install.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use trk::exec::{FakeRunner, Reply};
+use trk::journal::{Journal, Recorded};
+use trk::report::MasDecision;
+use trk::steps::{self, Account, Bundle, MasChoice, MasPreflight, Outcome, Selection, Step};
+use trk::Context;
+
+mod common;
+
+const BREWFILE: &str = "brew \"jq\"\n\
+                        mas \"Keynote\", id: 409183694\n\
+                        mas \"Xcode\", id: 497799835\n";
+
+/// A Mac with Keynote installed from the App Store; each test says what
+/// `mas account` answers.
+fn mac_with_keynote(home: &Path) -> Arc<FakeRunner> {
+    fs::write(home.join("Brewfile"), BREWFILE).unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew")
+        .program("mas")
+        .on(
+            ["brew", "info", "--json=v2", "--installed"],
+            Reply::stdout(r#"{"formulae": [], "casks": []}"#),
+        )
+        .on(["brew", "tap"], Reply::ok())
+        .on(["brew", "install", "..."], Reply::ok())
+        .on(
+            ["mas", "list"],
+            Reply::stdout("409183694  Keynote  (14.0)\n"),
+        )
+        .on(["mas", "install", "*"], Reply::ok());
+    fake
+}
+
+fn not_signed_in() -> Reply {
+    Reply::exit(1).stderr("Not signed in")
+}
+
+fn run(cx: &Context, path: &Path, preflight: MasPreflight) -> (trk::Result<()>, Journal) {
+    let steps: Vec<Box<dyn Step>> = vec![Box::new(preflight), Box::new(Bundle::new(path))];
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap();
+    let result = steps::run_all(cx, &steps, &Selection::All, &mut journal);
+    (
+        result,
+        Journal::load(Journal::path(&cx.trk_dir, "bootstrap")).unwrap(),
+    )
+}
+
+#[test]
+fn skipping_records_the_apps_and_the_brewfile_leaves_them() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = mac_with_keynote(home.path());
+    fake.on(["mas", "account"], not_signed_in());
+    let mut cx = common::mac(home.path(), fake.clone());
+    cx.mas = Some(MasChoice::Skip);
+    let path = home.path().join("Brewfile");
+
+    let plan = MasPreflight::new(&path).plan(&cx).unwrap();
+    assert_eq!(plan[0].subject, "App Store sign-in");
+    assert_eq!(plan[0].detail.as_deref(), Some("needed for Xcode"));
+
+    let (result, journal) = run(&cx, &path, MasPreflight::new(&path));
+    result.unwrap();
+
+    assert_eq!(
+        journal.steps["mas"].result,
+        Recorded::Outcome(Outcome::Skipped(
+            "App Store account signed out; left for later: Xcode".into()
+        ))
+    );
+    assert_eq!(
+        journal.report.mas,
+        Some(MasDecision {
+            account: "signed out".into(),
+            choice: MasChoice::Skip,
+            skipped: vec!["Xcode".into()],
+        })
+    );
+    assert_eq!(
+        journal.steps["brewfile"].result,
+        Recorded::Outcome(Outcome::Skipped("left for later: mas Xcode".into()))
+    );
+    assert!(fake
+        .commands()
+        .contains(&"brew install --formula jq".into()));
+    assert!(!fake.commands().iter().any(|c| c.starts_with("mas install")));
+}
+
+#[test]
+fn waiting_checks_again_until_signed_in() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = mac_with_keynote(home.path());
+    fake.once(["mas", "account"], not_signed_in())
+        .on(["mas", "account"], Reply::stdout("ada@example.com\n"));
+    let mut cx = common::mac(home.path(), fake.clone());
+    cx.mas = Some(MasChoice::Wait);
+    let path = home.path().join("Brewfile");
+
+    let preflight = MasPreflight::new(&path).recheck(Duration::from_millis(1));
+    let (result, journal) = run(&cx, &path, preflight);
+    result.unwrap();
+
+    assert_eq!(
+        fake.commands()
+            .iter()
+            .filter(|c| *c == "mas account")
+            .count(),
+        2
+    );
+    assert_eq!(journal.steps["mas"].result, Recorded::Outcome(Outcome::Ok));
+    assert_eq!(journal.report.mas.unwrap().choice, MasChoice::Wait);
+    assert!(fake.commands().contains(&"mas install 497799835".into()));
+    assert_eq!(
+        Account::check(&cx).unwrap(),
+        Account::SignedIn("ada@example.com".into())
+    );
+}
+
+#[test]
+fn waiting_gives_up_and_leaves_the_apps_for_later() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = mac_with_keynote(home.path());
+    fake.on(["mas", "account"], not_signed_in());
+    let mut cx = common::mac(home.path(), fake.clone());
+    cx.mas = Some(MasChoice::Wait);
+    let path = home.path().join("Brewfile");
+
+    let preflight = MasPreflight::new(&path)
+        .recheck(Duration::from_millis(1))
+        .max_rechecks(3);
+    let (result, journal) = run(&cx, &path, preflight);
+    result.unwrap();
+
+    assert_eq!(
+        fake.commands()
+            .iter()
+            .filter(|c| *c == "mas account")
+            .count(),
+        4
+    );
+    assert_eq!(journal.report.mas.unwrap().skipped, ["Xcode"]);
+    assert_eq!(
+        journal.steps["brewfile"].result,
+        Recorded::Outcome(Outcome::Skipped("left for later: mas Xcode".into()))
+    );
+    assert!(!fake.commands().iter().any(|c| c.starts_with("mas install")));
+}
+
+#[test]
+fn aborting_stops_before_the_brewfile() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = mac_with_keynote(home.path());
+    fake.on(["mas", "account"], not_signed_in());
+    let mut cx = common::mac(home.path(), fake.clone());
+    cx.mas = Some(MasChoice::Abort);
+    let path = home.path().join("Brewfile");
+
+    let (result, journal) = run(&cx, &path, MasPreflight::new(&path));
+
+    let err = result.unwrap_err().to_string();
+    assert!(
+        err.starts_with("step `mas` failed: App Store: ")
+            && err.ends_with(
+                "the App Store account is signed out and Xcode need it; \
+             sign in to the App Store app and run again with --resume"
+            ),
+        "{err}"
+    );
+    assert_eq!(journal.report.mas.unwrap().choice, MasChoice::Abort);
+    assert!(!journal.steps.contains_key("brewfile"));
+    assert!(!fake
+        .commands()
+        .iter()
+        .any(|c| c.starts_with("brew install")));
+}
+
+#[test]
+fn an_account_mas_cannot_read_is_waited_for_once() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = mac_with_keynote(home.path());
+    fake.on(
+        ["mas", "account"],
+        Reply::exit(1).stderr("This command is not supported on this macOS version."),
+    );
+    let mut cx = common::mac(home.path(), fake.clone());
+    cx.mas = Some(MasChoice::Wait);
+    let path = home.path().join("Brewfile");
+
+    let preflight = MasPreflight::new(&path).recheck(Duration::from_millis(1));
+    assert_eq!(preflight.run(&cx).unwrap(), Outcome::Ok);
+
+    assert_eq!(
+        fake.commands()
+            .iter()
+            .filter(|c| *c == "mas account")
+            .count(),
+        2
+    );
+    assert_eq!(
+        cx.report().mas.unwrap().account,
+        "unknown (This command is not supported on this macOS version.)"
+    );
+
+    // Everything installed: nothing to ask about.
+    fs::write(&path, "mas \"Keynote\", id: 409183694\n").unwrap();
+    cx.mas = None;
+    assert_eq!(preflight.run(&cx).unwrap(), Outcome::Ok);
+}
+
+#[test]
+fn update_checks_the_sign_in_before_the_brewfile() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = mac_with_keynote(home.path());
+    fake.on(["mas", "account"], not_signed_in());
+    let mut cx = common::mac(home.path(), fake.clone());
+    cx.mas = Some(MasChoice::Skip);
+    let osx = cx.dotfiles_dir.join("osx");
+    fs::create_dir_all(&osx).unwrap();
+    fs::rename(home.path().join("Brewfile"), osx.join("Brewfile")).unwrap();
+
+    let steps = steps::update(&cx);
+    let names: Vec<String> = steps.iter().map(|step| step.name()).collect();
+    let mas = names.iter().position(|name| name == "mas").unwrap();
+    assert!(mas < names.iter().position(|name| name == "brewfile").unwrap());
+
+    let only = Selection::Only(vec!["mas".into(), "brewfile".into()]);
+    let mut journal = Journal::load(Journal::path(&cx.trk_dir, "update")).unwrap();
+    steps::run_all(&cx, &steps, &only, &mut journal).unwrap();
+
+    assert_eq!(
+        journal.report.mas.unwrap().skipped,
+        vec!["Xcode".to_owned()]
+    );
+    assert_eq!(
+        journal.steps["brewfile"].result,
+        Recorded::Outcome(Outcome::Skipped("left for later: mas Xcode".into()))
+    );
+    assert!(!fake.commands().iter().any(|c| c.starts_with("mas install")));
+}
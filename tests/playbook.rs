@@ -0,0 +1,582 @@
+//! trk's own runner for the Ansible task subset dotfiles playbooks use.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::json;
+use trk::exec::{FakeRunner, Reply};
+use trk::manifest::PlaybookRunner;
+use trk::playbook::report::Status;
+use trk::playbook::{self, Module, Runner};
+use trk::steps::{Outcome, Playbook, Step};
+use trk::Manifest;
+
+mod common;
+
+const MAC_YML: &str = include_str!("../ansible/mac.yml");
+
+fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn runs_the_dotfiles_playbook_through_mac_yml() {
+    let home = tempfile::tempdir().unwrap();
+    let mac = home.path().join(".trk/ansible/mac.yml");
+    write(&mac, MAC_YML);
+    let hook = home.path().join("dotfiles/osx/playbook.yml");
+    write(
+        &hook,
+        r#"
+- name: Install formulae
+  homebrew:
+    name: "{{ item }}"
+    state: latest
+  with_items:
+    - jq
+    - ripgrep
+
+- name: Check for a shell
+  shell: grep -q fish /etc/shells
+  register: fish
+  changed_when: false
+
+- name: Add fish
+  become: yes
+  shell: echo /opt/homebrew/bin/fish >> /etc/shells
+  when: fish.rc != 0 and trk_arch == "arm64"
+
+- name: Build helpers
+  command: make "all the things"
+  args:
+    chdir: ~/dotfiles
+  when:
+    - "'fish' in fish.cmd"
+    - not trk_rosetta
+
+- include_tasks: extra.yml
+"#,
+    );
+    write(
+        &home.path().join("dotfiles/osx/extra.yml"),
+        "- ansible.builtin.stat: path=~/.config\n  register: config\n",
+    );
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew")
+        .on(["brew", "list", "--formula", "jq"], Reply::ok())
+        .on(
+            ["brew", "outdated", "..."],
+            Reply::stdout(r#"{"formulae": []}"#),
+        )
+        .on(["brew", "list", "..."], Reply::exit(1))
+        .on(["brew", "install", "..."], Reply::ok())
+        .on(["/bin/sh", "-c", "..."], Reply::ok())
+        .on(["sudo", "..."], Reply::ok())
+        .on(
+            ["make", "..."],
+            Reply::ok().effect(|cmd| fs::write(cmd.get_cwd().unwrap().join("built"), "").unwrap()),
+        );
+    let cx = common::mac(home.path(), fake.clone());
+
+    let step = Playbook::new(&mac)
+        .var("dotfiles_playbook", hook.to_string_lossy())
+        .var("trk_arch", "arm64")
+        .var("trk_rosetta", "false");
+    // `Add fish` becomes root, through the include in mac.yml.
+    assert!(step.sudo(&cx));
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+
+    assert_eq!(
+        fake.commands(),
+        [
+            "brew list --formula jq",
+            "brew outdated --formula jq --json=v2",
+            "brew list --formula ripgrep",
+            "brew install --formula ripgrep",
+            "/bin/sh -c grep -q fish /etc/shells",
+            "make all the things",
+        ]
+    );
+    assert_eq!(fake.calls()[5], ["make", "all the things"]);
+    assert!(home.path().join("dotfiles/built").is_file());
+}
+
+/// The tasks `ansible/mac.yml` ran before trk took over Homebrew and asdf,
+/// as older dotfiles playbooks still spell them.
+const LEGACY_MAC_YML: &str = r#"
+- hosts: 127.0.0.1
+  connection: local
+  tasks:
+    - homebrew: update_homebrew=yes
+
+    - name: Tap Caskroom
+      homebrew_tap: tap=homebrew/cask
+
+    - name: Tap bundle
+      homebrew_tap: tap=homebrew/bundle
+
+    - name: Update ansible
+      homebrew:
+        name: ansible
+        state: latest
+
+    - name: "Install asdf plugins"
+      shell: |
+        source ~/.asdf/asdf.sh
+        asdf plugin-add {{ item }} || exit 0
+      with_items:
+        - nodejs
+
+    - name: Install or Update mas
+      homebrew:
+        pkg: mas
+        state: latest
+
+    - name: Check dotfiles playbook
+      stat:
+        path: ~/dotfiles/osx/playbook.yml
+      register: dotfiles_playbook
+
+    - name: Install dotfiles
+      include_tasks: ~/dotfiles/osx/playbook.yml
+      when: dotfiles_playbook.stat.exists
+
+    - name: Check brew bundle dump
+      stat:
+        path: ~/dotfiles/osx/Brewfile
+      register: dotfiles_Brewfile
+
+    - name: Install OSX Apps from Brewfile
+      command: brew bundle --file Brewfile
+      args:
+        chdir: ~/dotfiles/osx/
+      when: dotfiles_Brewfile.stat.exists
+      register: result
+      changed_when:
+        - '"Installing" in result.stdout'
+"#;
+
+#[test]
+fn runs_the_legacy_mac_yml_tasks() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("mac.yml");
+    write(&path, LEGACY_MAC_YML);
+    write(&home.path().join("dotfiles/osx/Brewfile"), "brew \"jq\"\n");
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew")
+        .on(["brew", "update"], Reply::ok())
+        .on(["brew", "tap"], Reply::stdout("homebrew/core\n"))
+        .on(["brew", "list", "..."], Reply::ok())
+        .on(
+            ["brew", "outdated", "..."],
+            Reply::stdout(r#"{"formulae": []}"#),
+        )
+        .on(["brew", "bundle", "..."], Reply::stdout("Using jq\n"))
+        .on(["/bin/sh", "-c", "..."], Reply::ok());
+    let cx = common::mac(home.path(), fake.clone());
+
+    let mut runner = Runner::new(&cx);
+    // Only `brew update` changes anything: the retired taps are skipped.
+    assert!(runner.run(&path).unwrap());
+
+    assert_eq!(
+        fake.commands(),
+        [
+            "brew update",
+            "brew tap",
+            "brew tap",
+            "brew list --formula ansible",
+            "brew outdated --formula ansible --json=v2",
+            "/bin/sh -c source ~/.asdf/asdf.sh\nasdf plugin-add nodejs || exit 0\n",
+            "brew list --formula mas",
+            "brew outdated --formula mas --json=v2",
+            "brew bundle --file Brewfile",
+        ]
+    );
+    assert_eq!(runner.vars()["result"]["changed"], false);
+
+    write(
+        &path,
+        "- tasks:\n    - homebrew_tap: tap=user/one name=user/two\n",
+    );
+    let err = Runner::new(&cx).run(&path).unwrap_err().to_string();
+    assert!(
+        err.contains("`tap` and `name` are the same argument for `homebrew_tap`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn registers_results_and_skips_on_conditions() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("play.yml");
+    write(
+        &path,
+        r#"
+- hosts: 127.0.0.1
+  connection: local
+  vars:
+    greeting: "hello {{ name }}"
+    name: world
+  tasks:
+    - shell: "echo {{ greeting }}"
+      register: hello
+    - name: Only on mismatch
+      shell: echo never
+      when: hello.stdout != "hello world"
+      register: never
+    - command: "true"
+      with_items: [a, b]
+      register: loop
+      changed_when: item == "b"
+"#,
+    );
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(
+        ["/bin/sh", "-c", "echo hello world"],
+        Reply::stdout("hello world\n"),
+    )
+    .on(["true"], Reply::ok());
+    let cx = common::mac(home.path(), fake.clone());
+
+    let mut runner = Runner::new(&cx);
+    assert!(runner.run(&path).unwrap());
+
+    let vars = runner.vars();
+    assert_eq!(vars["hello"]["stdout"], "hello world");
+    assert_eq!(vars["hello"]["stdout_lines"], json!(["hello world"]));
+    assert_eq!(vars["hello"]["rc"], 0);
+    assert_eq!(vars["never"]["skipped"], true);
+    assert_eq!(vars["loop"]["changed"], true);
+    assert_eq!(vars["loop"]["results"][0]["changed"], false);
+    assert_eq!(vars["loop"]["results"][1]["item"], "b");
+    assert!(!fake.commands().iter().any(|c| c.contains("never")));
+}
+
+#[test]
+fn each_play_starts_with_its_own_vars() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("play.yml");
+    write(
+        &path,
+        r#"
+- vars:
+    first: one
+  tasks:
+    - shell: "echo {{ first }}"
+      register: hello
+- tasks:
+    - name: Sees the first play
+      shell: echo leaked
+      when: first is defined or hello is defined
+    - shell: "echo {{ who }}"
+"#,
+    );
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(["/bin/sh", "..."], Reply::ok());
+    let cx = common::mac(home.path(), fake.clone());
+
+    let mut runner = Runner::new(&cx).var("who", "extra");
+    runner.run(&path).unwrap();
+
+    let vars = runner.vars();
+    assert!(!vars.contains_key("first"));
+    assert!(!vars.contains_key("hello"));
+    assert_eq!(vars["who"], "extra");
+    let commands = fake.commands();
+    assert!(
+        !commands.iter().any(|c| c.contains("leaked")),
+        "{commands:?}"
+    );
+    assert!(
+        commands.iter().any(|c| c.contains("echo extra")),
+        "{commands:?}"
+    );
+}
+
+#[test]
+fn unsupported_modules_fail_before_anything_runs() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("tasks.yml");
+    write(
+        &path,
+        "- shell: echo first\n- name: Link dotfiles\n  file:\n    src: a\n    dest: b\n",
+    );
+    let fake = Arc::new(FakeRunner::new());
+    let cx = common::mac(home.path(), fake.clone());
+    let play = home.path().join("play.yml");
+    write(&play, "- tasks:\n    - include_tasks: tasks.yml\n");
+
+    let err = Runner::new(&cx).run(&play).unwrap_err().to_string();
+
+    assert_eq!(
+        err,
+        format!(
+            "{}: task `Link dotfiles`: unsupported module `file`; trk runs homebrew, \
+             homebrew_tap, shell, command, stat, template and include_tasks",
+            path.display()
+        )
+    );
+    assert!(fake.commands().is_empty());
+
+    write(&path, "- shell: echo\n  notify: restart\n");
+    let err = playbook::load_tasks(&path).unwrap_err().to_string();
+    assert!(err.contains("task 1: unsupported module `notify`"), "{err}");
+
+    write(&path, "- stat:\n    path: ~/x\n    get_checksum: no\n");
+    let err = playbook::load_tasks(&path).unwrap_err().to_string();
+    assert!(
+        err.contains("unsupported argument `get_checksum` for `stat`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn task_files_load_every_spelling_of_the_modules() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("tasks.yml");
+    write(
+        &path,
+        r#"
+- community.general.homebrew: name=git state=present
+- homebrew_tap:
+    name: [user/one, user/two]
+- command:
+    argv: [ls, -l]
+- import_tasks: more.yml
+"#,
+    );
+
+    let tasks = playbook::load_tasks(&path).unwrap();
+
+    let modules: Vec<_> = tasks.iter().map(|task| task.module).collect();
+    assert_eq!(
+        modules,
+        [
+            Module::Homebrew,
+            Module::HomebrewTap,
+            Module::Command,
+            Module::IncludeTasks
+        ]
+    );
+    assert_eq!(tasks[0].name, "homebrew");
+    assert_eq!(tasks[0].args["state"], "present");
+    assert_eq!(tasks[3].args["file"], "more.yml");
+}
+
+#[test]
+fn failed_commands_name_the_task_and_file() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("play.yml");
+    write(
+        &path,
+        "- tasks:\n    - name: Break\n      shell: exit 3\n    - shell: echo after\n",
+    );
+    let fake = Arc::new(FakeRunner::new());
+    fake.on(["/bin/sh", "..."], Reply::exit(3).stderr("nope"));
+    let cx = common::mac(home.path(), fake.clone());
+
+    let err = Runner::new(&cx).run(&path).unwrap_err().to_string();
+
+    assert_eq!(
+        err,
+        format!(
+            "{}: task `Break`: `/bin/sh -c 'exit 3'` exited with status 3: nope",
+            path.display()
+        )
+    );
+    assert_eq!(fake.commands().len(), 1);
+}
+
+#[test]
+fn homebrew_states_and_taps() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("play.yml");
+    write(
+        &path,
+        r#"
+- tasks:
+    - homebrew:
+        name: wget, curl
+        state: absent
+    - homebrew_tap:
+        name: user/tools
+    - homebrew:
+        name: git
+        state: linked
+"#,
+    );
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew")
+        .on(["brew", "list", "--formula", "wget"], Reply::ok())
+        .on(["brew", "list", "..."], Reply::exit(1))
+        .on(["brew", "uninstall", "..."], Reply::ok())
+        .on(["brew", "tap"], Reply::stdout("homebrew/core\n"))
+        .on(["brew", "tap", "*"], Reply::ok());
+    let cx = common::mac(home.path(), fake.clone());
+
+    let err = Runner::new(&cx).run(&path).unwrap_err().to_string();
+
+    assert!(
+        err.ends_with("task `homebrew`: unsupported state `linked` for `homebrew`; use present, latest or absent"),
+        "{err}"
+    );
+    assert_eq!(
+        fake.commands(),
+        [
+            "brew list --formula wget",
+            "brew uninstall --formula wget",
+            "brew list --formula curl",
+            "brew tap",
+            "brew tap user/tools",
+        ]
+    );
+}
+
+#[test]
+fn the_ansible_runner_needs_ansible_installed() {
+    let home = tempfile::tempdir().unwrap();
+    let manifest = Manifest::parse(
+        "[dotfiles]\nplaybook_runner = \"ansible\"\n",
+        Path::new("trk.toml"),
+    )
+    .unwrap();
+    let runner = manifest.dotfiles.playbook_runner.unwrap();
+    assert_eq!(runner, PlaybookRunner::Ansible);
+    let fake = Arc::new(FakeRunner::new());
+    let cx = common::mac(home.path(), fake.clone());
+    let step = Playbook::new(home.path().join("mac.yml")).runner(runner);
+
+    let err = step.run(&cx).unwrap_err().to_string();
+    assert!(err.contains("ansible-playbook is not installed"), "{err}");
+
+    fake.program("ansible-playbook")
+        .on(["ansible-playbook", "..."], Reply::ok());
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    assert!(fake.commands()[0].starts_with("ansible-playbook"));
+}
+
+#[test]
+fn templates_render_files_with_the_task_vars() {
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("dotfiles/osx/playbook.yml");
+    write(
+        &path,
+        r#"
+- vars:
+    user: { name: Ada, email: ada@example.com }
+    signing: false
+  tasks:
+    - template:
+        src: gitconfig.j2
+        dest: ~/.gitconfig
+        mode: "0600"
+      register: gitconfig
+"#,
+    );
+    write(
+        &home.path().join("dotfiles/osx/templates/gitconfig.j2"),
+        "[user]\n\
+         \tname = {{ user.name }}\n\
+         \temail = {{ user.email | lower }}\n\
+         {% if signing %}\n\
+         [commit]\n\
+         \tgpgsign = true\n\
+         {% endif %}\n\
+         {# editors, one per line #}\n\
+         {% for editor in ['vim', 'code'] -%}\n\
+         # {{ loop.index }}: {{ editor }}\n\
+         {% endfor %}",
+    );
+    let fake = Arc::new(FakeRunner::new());
+    let cx = common::mac(home.path(), fake.clone());
+
+    let mut runner = Runner::new(&cx);
+    assert!(runner.run(&path).unwrap());
+    let gitconfig = home.path().join(".gitconfig");
+    assert_eq!(
+        fs::read_to_string(&gitconfig).unwrap(),
+        "[user]\n\tname = Ada\n\temail = ada@example.com\n\n# 1: vim\n# 2: code\n"
+    );
+    assert_eq!(runner.vars()["gitconfig"]["changed"], true);
+
+    // Rendering the same content again changes nothing.
+    let mut runner = Runner::new(&cx);
+    assert!(!runner.run(&path).unwrap());
+}
+
+const EVENTS: &str = r#"{"task": "Install formulae", "path": "/d/osx/playbook.yml:2", "status": "changed", "duration": 3.25, "message": ""}
+{"task": "Check for a shell", "path": "/d/osx/playbook.yml:9", "status": "ok", "duration": 0.5, "message": ""}
+{"task": "Add fish", "path": "/d/osx/playbook.yml:14", "status": "skipped", "duration": 0.0, "message": "Conditional result was False"}
+{"task": "Build helpers", "path": "/d/osx/extra.yml:3", "status": "failed", "duration": 1.25, "message": "non-zero return code: make: *** No rule to make target"}
+"#;
+
+#[test]
+fn ansible_events_parse_into_a_report() {
+    let report = playbook::Report::parse(EVENTS).unwrap();
+
+    assert_eq!(report.tasks.len(), 4);
+    let failed = report.failures().collect::<Vec<_>>();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].name, "Build helpers");
+    assert_eq!(failed[0].location(), "/d/osx/extra.yml:3");
+    assert_eq!(report.count(Status::Skipped), 1);
+    assert!(report.changed());
+    assert_eq!(
+        report.to_string(),
+        "    status         time  task\n\
+         \x20   changed        3.2s  Install formulae\n\
+         \x20   ok             0.5s  Check for a shell\n\
+         \x20   skipped        0.0s  Add fish\n\
+         \x20   failed         1.2s  Build helpers\n\
+         \x20   4 tasks: 1 ok, 1 changed, 1 failed, 1 skipped in 5.0s\n\
+         \n\
+         !!! failed `Build helpers` at /d/osx/extra.yml:3\n\
+         \x20   non-zero return code: make: *** No rule to make target\n"
+    );
+
+    let err = playbook::Report::parse("{\"task\": \"x\", \"status\": \"rescued\"}").unwrap_err();
+    assert!(
+        err.starts_with("line 1: unknown variant `rescued`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn ansible_failures_name_the_task_and_its_file() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    let events = |reply: Reply, lines: usize| {
+        reply.effect(move |cmd| {
+            let path = cmd.get_env("TRK_ANSIBLE_EVENTS").unwrap();
+            let mut text = fs::read_to_string(path).unwrap_or_default();
+            for line in EVENTS.lines().take(lines) {
+                text += line;
+                text += "\n";
+            }
+            fs::write(path, text).unwrap();
+        })
+    };
+    fake.program("ansible-playbook")
+        .on(["ansible-playbook", "..."], events(Reply::ok(), 2))
+        .once(["ansible-playbook", "..."], events(Reply::exit(2), 4));
+    let cx = common::mac(home.path(), fake.clone());
+    let step = Playbook::new(home.path().join("mac.yml")).runner(PlaybookRunner::Ansible);
+
+    let err = step.run(&cx).unwrap_err().to_string();
+
+    assert_eq!(
+        err,
+        "/d/osx/extra.yml:3: task `Build helpers`: non-zero return code: \
+         make: *** No rule to make target"
+    );
+    let call = &fake.calls()[0];
+    assert!(!call.contains(&"--verbose".to_owned()), "{call:?}");
+
+    // The next run starts a fresh events file.
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    let events = fs::read_to_string(home.path().join(".trk/state/ansible-events.jsonl")).unwrap();
+    assert_eq!(events.lines().count(), 2);
+}
@@ -0,0 +1,278 @@
+//! Release binaries downloaded from a local stand-in for GitHub.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use trk::exec::SystemRunner;
+use trk::facts::Arch;
+use trk::journal::file_digest;
+use trk::steps::{Outcome, ReleaseBinary, Step};
+use trk::{Error, Manifest};
+
+mod common;
+
+type Files = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+/// Answers `GET <path>` with the matching body, or 404.
+fn serve(files: Vec<(String, Vec<u8>)>) -> String {
+    serve_shared(Arc::new(Mutex::new(files)))
+}
+
+/// A body that makes [`serve`] redirect to `to`, the way GitHub answers
+/// `releases/latest`.
+fn redirect(to: &str) -> Vec<u8> {
+    format!("Location: {to}").into_bytes()
+}
+
+/// [`serve`], with files the test can change while it runs; the first
+/// match wins.
+fn serve_shared(files: Files) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let files = files.lock().unwrap();
+            match files.iter().find(|(p, _)| p == path) {
+                Some((_, body)) if body.starts_with(b"Location: ") => {
+                    stream.write_all(b"HTTP/1.1 302 Found\r\n").unwrap();
+                    stream.write_all(body).unwrap();
+                    stream
+                        .write_all(b"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .unwrap();
+                }
+                Some((_, body)) => {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    )
+                    .unwrap();
+                    stream.write_all(body).unwrap();
+                }
+                None => stream
+                    .write_all(
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )
+                    .unwrap(),
+            }
+        }
+    });
+    url
+}
+
+/// `releases/latest` of acme/tool, pointing at `tag`.
+fn latest(tag: &str) -> (String, Vec<u8>) {
+    (
+        "/acme/tool/releases/latest".into(),
+        redirect(&format!("/acme/tool/releases/tag/{tag}")),
+    )
+}
+
+/// The page of release `tag`, which the redirect ends at.
+fn tag(tag: &str) -> (String, Vec<u8>) {
+    (format!("/acme/tool/releases/tag/{tag}"), Vec::new())
+}
+
+fn release(toml: &str) -> ReleaseBinary {
+    let manifest = Manifest::parse(&format!("[releases.tool]\n{toml}"), Path::new("t")).unwrap();
+    ReleaseBinary::new("tool", manifest.releases["tool"].clone())
+}
+
+/// Packs `tool_1.2.0/tool` with `packer` (`tar` or `zip`) and returns the
+/// archive's bytes.
+fn archive(dir: &Path, name: &str, packer: &[&str]) -> Vec<u8> {
+    let root = dir.join("tool_1.2.0");
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("tool"), "#!/bin/sh\necho tool\n").unwrap();
+    let status = Command::new(packer[0])
+        .args(&packer[1..])
+        .arg(name)
+        .arg("tool_1.2.0")
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    fs::read(dir.join(name)).unwrap()
+}
+
+#[test]
+fn installs_asset_for_this_arch_checked_against_published_sums() {
+    let home = tempfile::tempdir().unwrap();
+    let binary = b"#!/bin/sh\necho arm\n".to_vec();
+    fs::write(home.path().join("expected"), &binary).unwrap();
+    let sums = format!(
+        "{}  tool-darwin-arm64\n0000  tool-darwin-amd64\n",
+        file_digest(&home.path().join("expected"))
+    );
+    let files: Files = Arc::new(Mutex::new(vec![
+        latest("v1.2.0"),
+        tag("v1.2.0"),
+        (
+            "/acme/tool/releases/download/v1.2.0/tool-darwin-arm64".into(),
+            binary.clone(),
+        ),
+        (
+            "/acme/tool/releases/download/v1.2.0/checksums.txt".into(),
+            sums.clone().into_bytes(),
+        ),
+    ]));
+    let url = serve_shared(files.clone());
+    let mut cx = common::mac_on(Arch::Arm64, home.path(), Arc::new(SystemRunner));
+    cx.github_url = url;
+    let step = release(
+        r#"repo = "acme/tool"
+asset = "tool-{os}-{arch}"
+checksums = "checksums.txt""#,
+    );
+
+    assert_eq!(step.plan(&cx).unwrap().len(), 1);
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    let installed = cx.bin_dir.join("tool");
+    assert_eq!(fs::read(&installed).unwrap(), binary);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = installed.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    assert!(step.plan(&cx).unwrap().is_empty());
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Ok);
+
+    // A new release replaces it.
+    let newer = b"#!/bin/sh\necho newer\n".to_vec();
+    fs::write(home.path().join("expected"), &newer).unwrap();
+    let sums = format!(
+        "{}  tool-darwin-arm64\n",
+        file_digest(&home.path().join("expected"))
+    );
+    files.lock().unwrap().splice(
+        0..0,
+        [
+            latest("v1.3.0"),
+            tag("v1.3.0"),
+            (
+                "/acme/tool/releases/download/v1.3.0/tool-darwin-arm64".into(),
+                newer.clone(),
+            ),
+            (
+                "/acme/tool/releases/download/v1.3.0/checksums.txt".into(),
+                sums.into_bytes(),
+            ),
+        ],
+    );
+    let plan = step.plan(&cx).unwrap();
+    assert!(
+        plan[0]
+            .detail
+            .as_ref()
+            .unwrap()
+            .ends_with("/download/v1.3.0/tool-darwin-arm64"),
+        "{plan:?}"
+    );
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    assert_eq!(fs::read(&installed).unwrap(), newer);
+}
+
+#[test]
+fn unpacks_tarball_with_pinned_hash() {
+    let home = tempfile::tempdir().unwrap();
+    let work = tempfile::tempdir().unwrap();
+    let name = "tool_1.2.0_darwin_x86_64.tar.gz";
+    let tarball = archive(work.path(), name, &["tar", "-czf"]);
+    let url = serve(vec![(
+        format!("/acme/tool/releases/download/v1.2.0/{name}"),
+        tarball,
+    )]);
+    let mut cx = common::mac_on(Arch::X86_64, home.path(), Arc::new(SystemRunner));
+    cx.github_url = url;
+    let step = release(&format!(
+        r#"repo = "acme/tool"
+version = "v1.2.0"
+asset = "{{name}}_{{version}}_{{os}}_{{arch}}.tar.gz"
+arch = {{ x86_64 = "x86_64" }}
+sha256 = {{ "{name}" = "{}" }}"#,
+        file_digest(&work.path().join(name))
+    ));
+
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    assert_eq!(
+        fs::read_to_string(cx.bin_dir.join("tool")).unwrap(),
+        "#!/bin/sh\necho tool\n"
+    );
+    assert!(!cx.trk_dir.join("state/downloads/tool").exists());
+}
+
+#[test]
+fn refuses_asset_that_does_not_match_its_hash() {
+    let home = tempfile::tempdir().unwrap();
+    let work = tempfile::tempdir().unwrap();
+    let zip = archive(work.path(), "tool.zip", &["zip", "-qr"]);
+    let url = serve(vec![
+        latest("v1.2.0"),
+        tag("v1.2.0"),
+        ("/acme/tool/releases/download/v1.2.0/tool.zip".into(), zip),
+    ]);
+    let mut cx = common::mac_on(Arch::Arm64, home.path(), Arc::new(SystemRunner));
+    cx.github_url = url;
+    let step = release(
+        r#"repo = "acme/tool"
+asset = "tool.zip"
+sha256 = { "tool.zip" = "deadbeef" }"#,
+    );
+
+    let err = step.run(&cx).unwrap_err();
+    assert!(
+        matches!(&err, Error::Checksum { asset, expected, .. }
+            if asset == "tool.zip" && expected == "deadbeef"),
+        "{err}"
+    );
+    assert!(!cx.bin_dir.join("tool").exists());
+}
+
+#[test]
+fn placeholders_take_the_tag_latest_points_at() {
+    let home = tempfile::tempdir().unwrap();
+    let url = serve(vec![latest("v1.2.0"), tag("v1.2.0")]);
+    let mut cx = common::mac_on(Arch::Arm64, home.path(), Arc::new(SystemRunner));
+    cx.github_url = url.clone();
+    let step = release(
+        r#"repo = "acme/tool"
+asset = "tool_{version}.tar.gz"
+checksums = "checksums.txt""#,
+    );
+    assert_eq!(
+        step.plan(&cx).unwrap()[0].detail.as_deref(),
+        Some(format!("{url}/acme/tool/releases/download/v1.2.0/tool_1.2.0.tar.gz").as_str())
+    );
+
+    // Without a release there is nothing to install.
+    let mut cx = common::mac_on(Arch::Arm64, home.path(), Arc::new(SystemRunner));
+    cx.github_url = serve(Vec::new());
+    let err = step.plan(&cx).unwrap_err().to_string();
+    assert!(err.contains("/acme/tool/releases/latest"), "{err}");
+
+    let err = Manifest::parse(
+        "[releases.tool]\nrepo = \"a/b\"\nasset = \"tool-{platform}\"\n",
+        Path::new("trk.toml"),
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("unknown placeholder `{platform}`"),
+        "{err}"
+    );
+}
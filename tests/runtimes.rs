@@ -0,0 +1,422 @@
+//! `[runtimes]`, `.tool-versions`, the steps that install them and the
+//! asdf and mise backends.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use trk::exec::{FakeRunner, Reply};
+use trk::manifest::{self, Manager};
+use trk::runtime::{self, Alias, Asdf, Flavor, Lock, Mise, Update, VersionManager};
+use trk::steps::{Outcome, Runtime, Step, ToolVersions};
+use trk::Manifest;
+
+mod common;
+
+const NODE_INDEX: &str = include_str!("fixtures/node-index.json");
+
+/// A checkout of asdf that already has the nodejs plugin, with 20.11.1
+/// installed and set as the global default.
+fn asdf(home: &Path) -> Arc<FakeRunner> {
+    let dir = home.join(".asdf");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("asdf.sh"), "").unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    let asdf = |args: &[&str]| {
+        let mut pattern = vec!["bash", "-c", "*", "asdf"];
+        pattern.extend(args);
+        pattern.into_iter().map(str::to_owned).collect::<Vec<_>>()
+    };
+    fake.on(asdf(&["plugin-list"]), Reply::stdout("nodejs\n"))
+        .on(
+            asdf(&["list", "nodejs"]),
+            Reply::stdout("  18.19.0\n *20.11.1\n"),
+        )
+        .on(
+            asdf(&["current", "nodejs"]),
+            Reply::stdout("nodejs          20.11.1         /home/.tool-versions\n"),
+        )
+        .on(asdf(&["list", "..."]), Reply::exit(1))
+        .on(asdf(&["current", "..."]), Reply::exit(1))
+        .on(asdf(&["install", "..."]), Reply::ok())
+        .on(asdf(&["global", "..."]), Reply::ok());
+    fake
+}
+
+fn runtime(versions: &[&str]) -> manifest::Runtime {
+    manifest::Runtime {
+        versions: versions.iter().map(|v| v.to_string()).collect(),
+        plugin: None,
+    }
+}
+
+fn changes(fake: &FakeRunner) -> Vec<String> {
+    fake.commands()
+        .into_iter()
+        .filter_map(|c| {
+            c.split_once("\"$@\" asdf ")
+                .map(|(_, rest)| rest.to_owned())
+        })
+        .filter(|c| {
+            !["plugin-list", "list ", "current "]
+                .iter()
+                .any(|q| c.starts_with(q))
+        })
+        .collect()
+}
+
+#[test]
+fn runtimes_are_a_version_a_list_or_a_table() {
+    let manifest = Manifest::parse(
+        "[runtimes]\n\
+         nodejs = \"20.11.1\"\n\
+         python = [\"3.12.1\", \"3.11.7\"]\n\
+         zig = { versions = \"0.11.0\", plugin = \"https://github.com/acme/asdf-zig\" }\n",
+        Path::new("t"),
+    )
+    .unwrap();
+    assert_eq!(manifest.runtimes["nodejs"], runtime(&["20.11.1"]));
+    assert_eq!(manifest.runtimes["python"], runtime(&["3.12.1", "3.11.7"]));
+    assert_eq!(
+        manifest.runtimes["zig"].plugin.as_deref(),
+        Some("https://github.com/acme/asdf-zig")
+    );
+
+    let err = Manifest::parse("[runtimes]\nnodejs = []\n", Path::new("t")).unwrap_err();
+    assert!(
+        err.to_string().contains("list at least one version"),
+        "{err}"
+    );
+}
+
+#[test]
+fn tool_versions_lines_name_a_plugin_and_its_versions() {
+    let runtimes = manifest::parse_tool_versions(
+        "# pinned for CI\nnodejs 20.11.1 18.19.0\n\npython 3.12.1 # latest\n",
+    )
+    .unwrap();
+    assert_eq!(runtimes["nodejs"], runtime(&["20.11.1", "18.19.0"]));
+    assert_eq!(runtimes["python"], runtime(&["3.12.1"]));
+
+    let err = manifest::parse_tool_versions("nodejs 20.11.1\nruby\n").unwrap_err();
+    assert_eq!(err, (2, "`ruby` has no version".to_owned()));
+}
+
+#[test]
+fn installs_only_missing_versions_and_moves_the_global() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = asdf(home.path());
+    let cx = common::mac(home.path(), fake.clone());
+
+    let current = Runtime::new(Manager::Asdf, "nodejs", runtime(&["20.11.1", "18.19.0"]));
+    assert!(current.plan(&cx).unwrap().is_empty());
+    assert_eq!(current.run(&cx).unwrap(), Outcome::Ok);
+    assert!(changes(&fake).is_empty());
+
+    let newer = Runtime::new(Manager::Asdf, "nodejs", runtime(&["24.11.0", "20.11.1"]));
+    let plan: Vec<_> = newer
+        .plan(&cx)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.subject, c.detail))
+        .collect();
+    assert_eq!(
+        plan,
+        [
+            ("nodejs 24.11.0".to_owned(), None),
+            (
+                "global nodejs".to_owned(),
+                Some("20.11.1 -> 24.11.0".to_owned())
+            ),
+        ]
+    );
+    assert_eq!(newer.run(&cx).unwrap(), Outcome::Changed);
+    assert_eq!(
+        changes(&fake),
+        ["install nodejs 24.11.0", "global nodejs 24.11.0"]
+    );
+}
+
+#[test]
+fn plugin_add_failures_are_reported() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = asdf(home.path());
+    fake.on(
+        ["bash", "-c", "*", "asdf", "plugin-add", "..."],
+        Reply::exit(1).stderr("plugin zig not found in repository"),
+    );
+    let cx = common::mac(home.path(), fake.clone());
+
+    let zig = Runtime::new(Manager::Asdf, "zig", runtime(&["0.11.0"]));
+    assert_eq!(zig.plan(&cx).unwrap()[0].subject, "plugin zig");
+    let err = zig.run(&cx).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("plugin zig not found in repository"),
+        "{err}"
+    );
+    assert_eq!(changes(&fake), ["plugin-add zig"]);
+}
+
+#[test]
+fn tool_versions_leaves_declared_runtimes_to_the_manifest() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = asdf(home.path());
+    fake.on(
+        ["bash", "-c", "*", "asdf", "plugin-add", "..."],
+        Reply::ok(),
+    );
+    let cx = common::mac(home.path(), fake.clone());
+    let path = home.path().join(".tool-versions");
+
+    let step = ToolVersions::new(Manager::Asdf, &path).declared(["python"]);
+    assert_eq!(
+        step.run(&cx).unwrap(),
+        Outcome::Skipped(format!("no runtimes in {}", path.display()))
+    );
+
+    fs::write(&path, "nodejs 20.11.1\npython 3.12.1\nruby 3.3.0\n").unwrap();
+    assert_eq!(step.plan(&cx).unwrap().len(), 3);
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    assert_eq!(
+        changes(&fake),
+        ["plugin-add ruby", "install ruby 3.3.0", "global ruby 3.3.0"]
+    );
+}
+
+#[test]
+fn asdf_from_0_16_is_the_go_command_set() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("asdf");
+    fake.on(
+        ["asdf", "--version"],
+        Reply::stdout("asdf version 0.16.2\n"),
+    )
+    .on(["asdf", "plugin", "list"], Reply::stdout("nodejs\n"))
+    .on(["asdf", "list", "nodejs"], Reply::stdout("  20.11.1\n"))
+    .on(
+        ["asdf", "current", "nodejs"],
+        Reply::stdout(
+            "Name            Version         Source                 Installed\n\
+                 nodejs          20.11.1         /home/.tool-versions   true\n",
+        ),
+    )
+    .on(["asdf", "install", "..."], Reply::ok())
+    .on(["asdf", "set", "..."], Reply::ok())
+    .on(
+        ["asdf", "latest", "nodejs", "22"],
+        Reply::stdout("22.1.0\n"),
+    );
+    let cx = common::mac(home.path(), fake.clone());
+
+    let asdf = Asdf::detect(&cx, home.path().join(".asdf")).unwrap();
+    assert_eq!(asdf.flavor(), &Flavor::Go);
+    assert_eq!(
+        asdf.global(&cx, "nodejs").unwrap().as_deref(),
+        Some("20.11.1")
+    );
+    assert_eq!(asdf.latest(&cx, "nodejs", Some("22")).unwrap(), "22.1.0");
+
+    let step = Runtime::new(Manager::Asdf, "nodejs", runtime(&["22.1.0"]));
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    let commands = fake.commands();
+    assert!(commands.contains(&"asdf install nodejs 22.1.0".to_owned()));
+    assert!(commands.contains(&"asdf set --home nodejs 22.1.0".to_owned()));
+
+    // A Homebrew asdf from before the rewrite keeps the old commands.
+    fake.once(["asdf", "--version"], Reply::stdout("v0.15.0-31e8c93\n"));
+    let asdf = Asdf::detect(&cx, home.path().join(".asdf")).unwrap();
+    assert_eq!(asdf.flavor(), &Flavor::Shell);
+}
+
+#[test]
+fn mise_installs_registry_tools_without_plugins() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("mise");
+    fake.on(["mise", "plugins", "ls"], Reply::stdout(""))
+        .on(["mise", "registry", "node"], Reply::stdout("core:node\n"))
+        .on(["mise", "registry", "..."], Reply::exit(1))
+        .on(
+            ["mise", "ls", "--installed", "--json", "node"],
+            Reply::stdout(r#"[{"version": "20.11.1", "installed": true, "active": true}]"#),
+        )
+        .on(["mise", "current", "node"], Reply::stdout("20.11.1\n"))
+        .on(["mise", "install", "*"], Reply::ok())
+        .on(["mise", "use", "--global", "*"], Reply::ok())
+        .on(["mise", "latest", "node@22"], Reply::stdout("22.1.0\n"));
+    let mut cx = common::mac(home.path(), fake.clone());
+    cx.manifest.version_manager = Some(Manager::Mise);
+
+    assert!(Mise.has_plugin(&cx, "node").unwrap());
+    assert!(!Mise.has_plugin(&cx, "zig").unwrap());
+    assert_eq!(Mise.installed(&cx, "node").unwrap(), ["20.11.1"]);
+    assert_eq!(Mise.latest(&cx, "node", Some("22")).unwrap(), "22.1.0");
+
+    let step = Runtime::new(Manager::Mise, "node", runtime(&["22.1.0", "20.11.1"]));
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    let changes: Vec<_> = fake
+        .commands()
+        .into_iter()
+        .filter(|c| c.starts_with("mise install") || c.starts_with("mise use"))
+        .collect();
+    assert_eq!(
+        changes,
+        ["mise install node@22.1.0", "mise use --global node@22.1.0"]
+    );
+
+    let mut manifest = Manifest::parse("version_manager = \"mise\"\n", Path::new("t")).unwrap();
+    assert_eq!(manifest.version_manager(), Manager::Mise);
+
+    // A dotfiles manifest can choose asdf again, and one that says
+    // nothing keeps the choice.
+    manifest.merge(Manifest::default());
+    assert_eq!(manifest.version_manager(), Manager::Mise);
+    let overlay = Manifest::parse("version_manager = \"asdf\"\n", Path::new("t")).unwrap();
+    manifest.merge(overlay);
+    assert_eq!(manifest.version_manager(), Manager::Asdf);
+}
+
+#[test]
+fn aliases_pick_the_newest_matching_node_release() {
+    let releases = runtime::parse_node_index(NODE_INDEX).unwrap();
+    let pick = |alias: &str| Alias::parse(alias).unwrap().unwrap().pick(&releases);
+    assert_eq!(pick("latest").as_deref(), Some("25.1.0"));
+    assert_eq!(pick("lts").as_deref(), Some("24.11.0"));
+    assert_eq!(pick("lts/iron").as_deref(), Some("20.19.5"));
+    assert_eq!(pick("20").as_deref(), Some("20.19.5"));
+    assert_eq!(pick("20.11").as_deref(), Some("20.11.1"));
+    assert_eq!(pick("~20.11").as_deref(), Some("20.11.1"));
+    assert_eq!(pick("^22").as_deref(), Some("22.21.1"));
+    assert_eq!(pick(">=18, <20").as_deref(), Some("18.20.8"));
+    assert_eq!(pick("19"), None);
+    assert_eq!(Alias::parse("20.11.1"), Ok(None));
+    assert_eq!(Alias::parse("system"), Ok(None));
+
+    let err = Manifest::parse("[runtimes]\nnodejs = \"~twenty\"\n", Path::new("t")).unwrap_err();
+    assert!(err.to_string().contains("version range `~twenty`"), "{err}");
+}
+
+#[test]
+fn aliases_stay_locked_until_runtimes_update() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = asdf(home.path());
+    fake.on(["curl", "..."], Reply::stdout(NODE_INDEX));
+    let cx = common::mac(home.path(), fake.clone());
+    let lock = cx.runtimes_lock();
+    let curls = || {
+        fake.commands()
+            .iter()
+            .filter(|c| c.starts_with("curl"))
+            .count()
+    };
+
+    let step = Runtime::new(Manager::Asdf, "nodejs", runtime(&["lts", "20"])).lock(&lock);
+    let plan = |step: &Runtime| -> Vec<_> {
+        step.plan(&cx)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.subject, c.detail.unwrap_or_default()))
+            .collect()
+    };
+    let unlocked = "not locked yet; resolved when installed".to_owned();
+    assert_eq!(
+        plan(&step),
+        [
+            ("nodejs lts".to_owned(), unlocked.clone()),
+            ("nodejs 20".to_owned(), unlocked),
+            ("global nodejs".to_owned(), "20.11.1 -> lts".to_owned()),
+        ]
+    );
+    assert_eq!(curls(), 0, "planning must not go online");
+    assert!(!lock.exists());
+
+    assert_eq!(step.run(&cx).unwrap(), Outcome::Changed);
+    assert_eq!(
+        changes(&fake),
+        [
+            "install nodejs 24.11.0",
+            "install nodejs 20.19.5",
+            "global nodejs 24.11.0"
+        ]
+    );
+    let locked = Lock::load(&lock).unwrap();
+    assert_eq!(locked.get("nodejs", "lts"), Some("24.11.0"));
+    assert_eq!(locked.get("nodejs", "20"), Some("20.19.5"));
+
+    // Once locked, the plan names the releases, and a newer LTS upstream
+    // changes nothing until someone updates the lock.
+    let seen = curls();
+    assert_eq!(
+        plan(&step),
+        [
+            ("nodejs 24.11.0".to_owned(), "lts".to_owned()),
+            ("nodejs 20.19.5".to_owned(), "20".to_owned()),
+            ("global nodejs".to_owned(), "20.11.1 -> 24.11.0".to_owned()),
+        ]
+    );
+    step.run(&cx).unwrap();
+    assert_eq!(curls(), seen);
+
+    let newer = NODE_INDEX.replacen("[", "[\n{\"version\":\"v24.12.0\",\"lts\":\"Krypton\"},", 1);
+    fake.once(["curl", "..."], Reply::stdout(newer.clone()))
+        .once(["curl", "..."], Reply::stdout(newer));
+    let runtimes = BTreeMap::from([
+        ("nodejs".to_owned(), runtime(&["lts", "20"])),
+        ("python".to_owned(), runtime(&["3.12.1"])),
+    ]);
+    let updates = runtime::update(&cx, &runtimes, &[]).unwrap();
+    assert_eq!(
+        updates,
+        [
+            Update {
+                plugin: "nodejs".into(),
+                alias: "lts".into(),
+                before: Some("24.11.0".into()),
+                version: "24.12.0".into(),
+            },
+            Update {
+                plugin: "nodejs".into(),
+                alias: "20".into(),
+                before: Some("20.19.5".into()),
+                version: "20.19.5".into(),
+            },
+        ]
+    );
+    assert_eq!(
+        Lock::load(&lock).unwrap().get("nodejs", "lts"),
+        Some("24.12.0")
+    );
+    assert!(fs::read_to_string(&lock)
+        .unwrap()
+        .contains("trk runtimes update"));
+}
+
+#[test]
+fn other_runtimes_resolve_aliases_through_the_manager() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = asdf(home.path());
+    fake.on(
+        ["bash", "-c", "*", "asdf", "latest", "python"],
+        Reply::stdout("3.13.0\n"),
+    )
+    .on(
+        ["bash", "-c", "*", "asdf", "latest", "python", "3.12"],
+        Reply::stdout("3.12.7\n"),
+    );
+    let cx = common::mac(home.path(), fake.clone());
+    let asdf = Asdf::detect(&cx, home.path().join(".asdf")).unwrap();
+    let resolve = |alias: &str| {
+        Alias::parse(alias)
+            .unwrap()
+            .unwrap()
+            .resolve(&cx, &asdf, "python")
+    };
+
+    assert_eq!(resolve("latest").unwrap(), "3.13.0");
+    assert_eq!(resolve("3.12").unwrap(), "3.12.7");
+    let err = resolve("lts").unwrap_err();
+    assert!(err.to_string().contains("only nodejs has"), "{err}");
+}
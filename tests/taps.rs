@@ -0,0 +1,145 @@
+//! Declared taps, retired official taps and taps nobody declared.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use trk::exec::{FakeRunner, Reply};
+use trk::manifest::{DeprecatedTaps, State};
+use trk::plan::Action;
+use trk::steps::{Outcome, Step, Tap, TapAudit};
+use trk::Manifest;
+
+mod common;
+
+fn tapped(taps: &str) -> Arc<FakeRunner> {
+    let fake = Arc::new(FakeRunner::new());
+    fake.program("brew");
+    fake.on(["brew", "tap"], Reply::stdout(taps))
+        .on(["brew", "tap", "*"], Reply::ok())
+        .on(["brew", "untap", "*"], Reply::ok());
+    fake
+}
+
+fn changes(fake: &FakeRunner) -> Vec<String> {
+    fake.commands()
+        .into_iter()
+        .filter(|c| c != "brew tap")
+        .collect()
+}
+
+#[test]
+fn taps_are_names_or_tables_with_a_state() {
+    let manifest = Manifest::parse(
+        "[homebrew]\n\
+         taps = [\"acme/tools\", { name = \"homebrew/cask\", state = \"absent\" }]\n\
+         deprecated_taps = \"untap\"\n",
+        Path::new("t"),
+    )
+    .unwrap();
+    let taps: Vec<_> = manifest
+        .homebrew
+        .taps
+        .iter()
+        .map(|t| (t.as_str(), t.state))
+        .collect();
+    assert_eq!(
+        taps,
+        [
+            ("acme/tools", State::Present),
+            ("homebrew/cask", State::Absent)
+        ]
+    );
+    assert_eq!(manifest.homebrew.deprecated_taps(), DeprecatedTaps::Untap);
+
+    // A dotfiles manifest can go back to warnings.
+    let mut merged = manifest.clone();
+    merged.merge(
+        Manifest::parse("[homebrew]\ndeprecated_taps = \"warn\"\n", Path::new("t")).unwrap(),
+    );
+    assert_eq!(merged.homebrew.deprecated_taps(), DeprecatedTaps::Warn);
+    merged.merge(Manifest::default());
+    assert_eq!(merged.homebrew.deprecated_taps(), DeprecatedTaps::Warn);
+
+    let err = Manifest::parse(
+        "[homebrew]\ntaps = [{ name = \"acme/tools\", state = \"gone\" }]\n",
+        Path::new("t"),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("unknown variant `gone`"), "{err}");
+}
+
+#[test]
+fn absent_taps_are_untapped_and_retired_taps_never_tapped() {
+    let home = tempfile::tempdir().unwrap();
+    let fake = tapped("homebrew/cask\nacme/tools\n");
+    let cx = common::mac(home.path(), fake.clone());
+
+    let absent = Tap::new("homebrew/cask").absent();
+    assert_eq!(absent.plan(&cx).unwrap().len(), 1);
+    assert_eq!(absent.run(&cx).unwrap(), Outcome::Changed);
+    assert_eq!(Tap::new("acme/tools").run(&cx).unwrap(), Outcome::Ok);
+    assert_eq!(
+        Tap::new("homebrew/bundle").run(&cx).unwrap(),
+        Outcome::Skipped("deprecated: `brew bundle` is part of Homebrew".into())
+    );
+    assert!(Tap::new("homebrew/bundle").plan(&cx).unwrap().is_empty());
+    assert_eq!(Tap::new("acme/more").run(&cx).unwrap(), Outcome::Changed);
+
+    assert_eq!(
+        changes(&fake),
+        ["brew untap homebrew/cask", "brew tap acme/more"]
+    );
+}
+
+#[test]
+fn audit_warns_about_or_untaps_what_nobody_declared() {
+    let home = tempfile::tempdir().unwrap();
+    let brewfile = home.path().join("Brewfile");
+    fs::write(&brewfile, "tap \"acme/fonts\"\n").unwrap();
+    let manifest = Manifest::parse(
+        "[homebrew]\ntaps = [\"acme/tools\", \"homebrew/core\"]\n",
+        Path::new("t"),
+    )
+    .unwrap();
+    let installed = "acme/fonts\nacme/tools\nhomebrew/core\nhomebrew/services\nstray/tap\n";
+
+    let fake = tapped(installed);
+    let cx = common::mac(home.path(), fake.clone());
+    let warn = TapAudit::new(&manifest.homebrew.taps, DeprecatedTaps::Warn).brewfile(&brewfile);
+    let plan: Vec<_> = warn
+        .plan(&cx)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.action, c.subject))
+        .collect();
+    assert_eq!(
+        plan,
+        [
+            (Action::Warn, "homebrew/services".to_owned()),
+            (Action::Warn, "stray/tap".to_owned())
+        ]
+    );
+    assert_eq!(warn.run(&cx).unwrap(), Outcome::Ok);
+    assert!(changes(&fake).is_empty());
+
+    let fake = tapped(installed);
+    let cx = common::mac(home.path(), fake.clone());
+    let untap = TapAudit::new(&manifest.homebrew.taps, DeprecatedTaps::Untap).brewfile(&brewfile);
+    let plan: Vec<_> = untap
+        .plan(&cx)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.action, c.subject))
+        .collect();
+    assert_eq!(
+        plan,
+        [
+            (Action::Remove, "homebrew/services".to_owned()),
+            (Action::Warn, "stray/tap".to_owned())
+        ]
+    );
+    assert_eq!(untap.run(&cx).unwrap(), Outcome::Changed);
+    // homebrew/core is declared present, and stray/tap is only reported.
+    assert_eq!(changes(&fake), ["brew untap homebrew/services"]);
+}
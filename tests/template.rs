@@ -0,0 +1,135 @@
+//! The Jinja subset shared by conditions, loops and `template` files.
+
+use serde_json::{json, Value};
+use trk::template::{self, Vars};
+
+fn vars(value: Value) -> Vars {
+    match value {
+        Value::Object(vars) => vars,
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn expressions() {
+    let vars = vars(json!({
+        "result": { "rc": 0, "stdout": "a\nb", "stdout_lines": ["a", "b"] },
+        "enabled": "yes",
+        "disabled": "no",
+        "items": [1, 2, 3],
+    }));
+    let eval = |expr| template::eval(expr, &vars).unwrap();
+
+    assert_eq!(eval("result.rc == 0"), true);
+    assert_eq!(eval("result['stdout_lines'][1]"), "b");
+    assert_eq!(eval("result.stdout_lines.0"), "a");
+    assert_eq!(eval("'b' in result.stdout"), true);
+    assert_eq!(eval("4 not in items"), true);
+    assert_eq!(eval("not (result.rc != 0 or items[-1] < 3)"), true);
+    assert_eq!(eval("enabled and not disabled"), Value::Bool(true));
+    assert!(template::condition(&json!("{{ enabled }}"), &vars).unwrap());
+    assert!(!template::condition(&json!("disabled"), &vars).unwrap());
+    assert!(!template::condition(&json!(false), &vars).unwrap());
+
+    assert_eq!(
+        template::render("rc={{ result.rc }} {{ items }}", &vars).unwrap(),
+        "rc=0 [1,2,3]"
+    );
+    assert_eq!(
+        template::render("{{ items }}", &vars).unwrap(),
+        json!([1, 2, 3])
+    );
+    assert_eq!(
+        template::eval("missing.attr", &vars).unwrap_err(),
+        "`missing` is undefined"
+    );
+    assert_eq!(
+        template::eval("result.nope", &vars).unwrap_err(),
+        "`result` has no attribute `nope`"
+    );
+}
+
+#[test]
+fn filters_tests_and_methods() {
+    let vars = vars(json!({
+        "result": { "changed": true, "stdout": "==> Installing jq\nDone" },
+        "skipped": { "changed": false, "skipped": true },
+        "names": ["b", "A", "b"],
+        "path": "/usr/local/bin/brew",
+        "empty": "",
+    }));
+    let eval = |expr| template::eval(expr, &vars).unwrap();
+
+    assert_eq!(
+        eval("'\"Installing\" in result.stdout'"),
+        "\"Installing\" in result.stdout"
+    );
+    assert_eq!(eval("\"Installing\" in result.stdout"), true);
+    assert_eq!(eval("missing | default('fallback')"), "fallback");
+    assert_eq!(eval("result.nope.deeper | d(1)"), 1);
+    assert_eq!(eval("empty | default('x')"), "");
+    assert_eq!(eval("empty | default('x', true)"), "x");
+    assert_eq!(eval("names | unique | join(', ') | lower"), "b, a");
+    assert_eq!(eval("names | length + 1"), 4);
+    assert_eq!(eval("names | first ~ '-' ~ names | last"), "b-b");
+    assert_eq!(eval("path | basename"), "brew");
+    assert_eq!(eval("path | dirname"), "/usr/local/bin");
+    assert_eq!(eval("result.stdout.split('\\n') | last"), "Done");
+    assert_eq!(eval("'10' | int * 2"), 20);
+    assert_eq!(eval("'yes' | bool"), true);
+    assert_eq!(eval("result.stdout.startswith('==>')"), true);
+    assert_eq!(eval("result is changed and skipped is skipped"), true);
+    assert_eq!(eval("result is not failed"), true);
+    assert_eq!(eval("missing is defined or missing.x is undefined"), true);
+    assert_eq!(eval("'on' if result.changed else 'off'"), "on");
+    assert_eq!(eval("{'a': 1}.get('b', 2)"), 2);
+    assert_eq!(eval("[1, 2] + [3]"), json!([1, 2, 3]));
+    assert_eq!(eval("7 // 2"), 3);
+
+    assert_eq!(
+        template::eval("names | shuffle", &vars).unwrap_err(),
+        "unsupported filter `shuffle`; trk has default, lower, upper, capitalize, trim, join, \
+         split, replace, length, first, last, unique, sort, reverse, int, float, bool, string, \
+         list, basename and dirname"
+    );
+    assert_eq!(
+        template::eval("missing | lower", &vars).unwrap_err(),
+        "`missing` is undefined"
+    );
+}
+
+#[test]
+fn blocks_and_whitespace_control() {
+    let vars = vars(json!({ "hosts": ["a", "b"], "os": "macos" }));
+    let render = |source| template::render_string(source, &vars).unwrap();
+
+    assert_eq!(
+        render("{% if os == 'linux' %}L{% elif os == 'macos' %}M{% else %}?{% endif %}"),
+        "M"
+    );
+    assert_eq!(
+        render("{% for h in hosts %}{{ h }}{% if not loop.last %},{% endif %}{% endfor %}"),
+        "a,b"
+    );
+    assert_eq!(render("a\n{% if true %}\nb\n{% endif %}\nc"), "a\nb\nc");
+    assert_eq!(render("x   {{- ' y ' -}}   z"), "x y z");
+    assert_eq!(render("{# gone #}kept"), "kept");
+    assert_eq!(
+        render("{% for k, v in [['a', 1], ['b', 2]] %}{{ k }}={{ v }} {% endfor %}"),
+        "a=1 b=2 "
+    );
+
+    assert_eq!(
+        template::render_string("{% if os %}open", &vars).unwrap_err(),
+        "`{% if %}` without `{% endif %}`"
+    );
+    assert_eq!(
+        template::render_string("{% set x = 1 %}", &vars).unwrap_err(),
+        "unsupported tag `{% set %}`; trk has if, elif, else and for"
+    );
+    // A single expression keeps its type.
+    assert_eq!(
+        template::render(" {{ hosts }} ", &vars).unwrap(),
+        json!(["a", "b"])
+    );
+}